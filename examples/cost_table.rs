@@ -0,0 +1,125 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Measures the cost of map operations and prints it as a CSV table
+//!
+//! For each key distribution and map size, the average number of nodes
+//! visited and the average wall time of every operation are measured, to
+//! derive gas schedules from. Run with
+//! `cargo run --release --example cost_table`.
+
+use std::time::Instant;
+
+use dusk_hamt::{CountingStore, Hamt, Lookup};
+use microkelvin::{HostStore, OffsetLen, StoreRef};
+use rkyv::rend::LittleEndian;
+
+type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+const SIZES: [u64; 3] = [1 << 10, 1 << 14, 1 << 17];
+const SAMPLES: u64 = 1 << 10;
+
+/// Keys following different distributions
+fn key(distribution: &str, i: u64) -> u64 {
+    match distribution {
+        "sequential" => i,
+        // xorshift over the index, spreading keys over the whole space
+        "uniform" => {
+            let mut x = i.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^ (x << 17)
+        }
+        // keys in a few dense clusters far apart
+        "clustered" => ((i % 8) << 56) | (i / 8),
+        _ => unreachable!("unknown distribution"),
+    }
+}
+
+#[derive(Default)]
+struct Cost {
+    visits: u64,
+    nanos: u128,
+}
+
+impl Cost {
+    fn print(&self, distribution: &str, size: u64, operation: &str) {
+        println!(
+            "{},{},{},{:.2},{:.1}",
+            distribution,
+            size,
+            operation,
+            self.visits as f64 / SAMPLES as f64,
+            self.nanos as f64 / SAMPLES as f64,
+        );
+    }
+}
+
+fn main() {
+    println!("distribution,size,operation,node_visits,nanos");
+
+    for distribution in ["sequential", "uniform", "clustered"].iter() {
+        for size in SIZES.iter().copied() {
+            let mut map = Map::new();
+            for i in 0..size {
+                map.insert(key(distribution, i).into(), i);
+            }
+
+            // the sampled keys are spread evenly over the inserted ones
+            let sample = |s: u64| key(distribution, s * (size / SAMPLES));
+            let fresh = |s: u64| key(distribution, size + s);
+
+            let mut get = Cost::default();
+            for s in 0..SAMPLES {
+                let k = sample(s).into();
+                let start = Instant::now();
+                let value = map.get(&k).expect("key inserted above");
+                get.nanos += start.elapsed().as_nanos();
+                get.visits += value.depth() as u64 + 1;
+            }
+            get.print(distribution, size, "get");
+
+            let mut insert = Cost::default();
+            for s in 0..SAMPLES {
+                let k = fresh(s).into();
+                let start = Instant::now();
+                map.insert(k, s);
+                insert.nanos += start.elapsed().as_nanos();
+                let value = map.get(&k).expect("key inserted above");
+                insert.visits += value.depth() as u64 + 1;
+            }
+            insert.print(distribution, size, "insert");
+
+            let mut remove = Cost::default();
+            for s in 0..SAMPLES {
+                let k = fresh(s).into();
+                let value = map.get(&k).expect("key inserted above");
+                remove.visits += value.depth() as u64 + 1;
+                let start = Instant::now();
+                map.remove(&k);
+                remove.nanos += start.elapsed().as_nanos();
+            }
+            remove.print(distribution, size, "remove");
+
+            let store = CountingStore::new(HostStore::new());
+            let telemetry = store.telemetry();
+            let store = StoreRef::new(store);
+            let stored = store.store(&map);
+
+            telemetry.reset();
+            let start = Instant::now();
+            for s in 0..SAMPLES {
+                let k = sample(s).into();
+                assert!(stored.get(&k).is_some());
+            }
+            Cost {
+                visits: telemetry.stats().reads,
+                nanos: start.elapsed().as_nanos(),
+            }
+            .print(distribution, size, "get_stored");
+        }
+    }
+}
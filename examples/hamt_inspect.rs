@@ -0,0 +1,175 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Inspects a map persisted in a store directory
+//!
+//! Run with `cargo run --example hamt_inspect -- <command> <dir> [args]`,
+//! where the command is one of:
+//!
+//! - `demo <dir>` writes a sample map to the store in `<dir>` and prints
+//!   the offset and length of its root
+//! - `keys <dir> <offset> <len>` dumps the entries of the map
+//! - `stats <dir> <offset> <len>` prints the occupancy of every level
+//! - `verify <dir> <offset> <len>` checks the annotations against the
+//!   entries
+//! - `dot <dir> <offset> <len>` renders the nodes in DOT format
+//!
+//! The map type is fixed at compile time, change [`Map`] to inspect maps
+//! of other types.
+
+use std::env;
+use std::process;
+
+use dusk_hamt::{Hamt, Limits, Lookup};
+use microkelvin::{
+    Cardinality, HostStore, Ident, MaybeArchived, OffsetLen, StoreRef, Stored,
+    UnwrapInfallible,
+};
+use rkyv::rend::LittleEndian;
+use rkyv::Deserialize;
+
+type Map = Hamt<LittleEndian<u64>, u64, Cardinality, OffsetLen>;
+
+fn usage() -> ! {
+    eprintln!("usage: hamt_inspect demo <dir>");
+    eprintln!("       hamt_inspect keys|stats|verify|dot <dir> <offset> <len>");
+    process::exit(2)
+}
+
+fn open(dir: &str) -> StoreRef<OffsetLen> {
+    match HostStore::with_file(dir) {
+        Ok(store) => StoreRef::new(store),
+        Err(e) => {
+            eprintln!("cannot open {}: {}", dir, e);
+            process::exit(1)
+        }
+    }
+}
+
+fn demo(store: StoreRef<OffsetLen>) {
+    let mut map = Map::new();
+    for i in 0..1024u64 {
+        map.insert(i.into(), i * i);
+    }
+    let stored = store.store(&map);
+    store.persist().expect("persisting the store");
+
+    let root = stored.ident().erase();
+    println!("{} {}", root.offset(), root.len());
+}
+
+fn keys(root: &Map) {
+    for (key, val) in root.iter_by_digest() {
+        let val = match val {
+            MaybeArchived::Memory(val) | MaybeArchived::Archived(val) => *val,
+        };
+        println!("{} {}", key, val);
+    }
+}
+
+fn stats(root: &Map) {
+    let profile = root.profile();
+    println!("depth,nodes,empty,leaves,links");
+    for (depth, level) in profile.levels().iter().enumerate() {
+        println!(
+            "{},{},{},{},{}",
+            depth, level.nodes, level.empty, level.leaves, level.links
+        );
+    }
+    let bytes: usize = root.nodes().map(|node| node.size).sum();
+    println!("# {} entries, {} bytes of nodes", profile.len(), bytes);
+}
+
+fn verify(stored: &Stored<Map, OffsetLen>, root: &Map) -> bool {
+    // deeper than any path of a 64 bit digest, without bounding the size
+    let limits = Limits {
+        max_depth: 64,
+        max_nodes: usize::MAX,
+    };
+    if let Err(e) = stored.check_limits(&limits) {
+        println!("limits exceeded: {:?}", e);
+        return false;
+    }
+
+    let entries = root.iter_by_digest().count() as u64;
+    let counted = u64::from(root.recompute());
+    if counted != entries {
+        println!("{} entries but a cardinality of {}", entries, counted);
+        return false;
+    }
+
+    // walking by index relies on the cardinality of every subtree
+    for n in 0..entries {
+        if root.nth(n).is_none() {
+            println!("entry {} cannot be reached by index", n);
+            return false;
+        }
+    }
+
+    println!("ok, {} entries", entries);
+    true
+}
+
+fn dot(root: &Map) {
+    let name = |path: &[usize]| {
+        let slots: Vec<_> = path.iter().map(ToString::to_string).collect();
+        format!("n{}", slots.concat())
+    };
+
+    println!("digraph hamt {{");
+    for node in root.nodes() {
+        let location = match node.ident {
+            Some(id) => format!("{}+{}", id.offset(), id.len()),
+            None => String::from("memory"),
+        };
+        println!(
+            "  {} [label=\"{}\\n{} leaves\"];",
+            name(&node.path),
+            location,
+            node.leaves
+        );
+        if let Some((_, parent)) = node.path.split_last() {
+            println!("  {} -> {};", name(parent), name(&node.path));
+        }
+    }
+    println!("}}");
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.as_slice() {
+        [command, dir] if command == "demo" => demo(open(dir)),
+        [command, dir, offset, len] => {
+            let (offset, len): (u64, u16) = match (offset.parse(), len.parse())
+            {
+                (Ok(offset), Ok(len)) => (offset, len),
+                _ => usage(),
+            };
+
+            let store = open(dir);
+            let ident = Ident::new(OffsetLen::new(offset, len));
+            let stored: Stored<Map, _> = Stored::new(store.clone(), ident);
+            let root: Map = stored
+                .inner()
+                .deserialize(&mut store.clone())
+                .unwrap_infallible();
+
+            match command.as_str() {
+                "keys" => keys(&root),
+                "stats" => stats(&root),
+                "verify" => {
+                    if !verify(&stored, &root) {
+                        process::exit(1)
+                    }
+                }
+                "dot" => dot(&root),
+                _ => usage(),
+            }
+        }
+        _ => usage(),
+    }
+}
@@ -0,0 +1,134 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map keeping the values of its most recently read keys at hand
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, MaybeArchived, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Infallible};
+
+use crate::{Hamt, KeyHasher, KvPair};
+
+/// A value read from the map, with the digest of its key
+type Hot<K, V, H> = (<H as KeyHasher>::Digest, K, V);
+
+/// A map keeping copies of the values of the last `capacity` keys read,
+/// so reading them again skips descending the map
+///
+/// This pays off for maps kept in a store, where descending reads and
+/// validates a node per level, when a handful of keys is read over and
+/// over. A mutation only drops the copy of the key it changes, the other
+/// copies staying valid.
+pub struct CachedHamt<K, V, A, I, H>
+where
+    H: KeyHasher,
+{
+    map: Hamt<K, V, A, I, H>,
+    hot: Vec<Hot<K, V, H>>,
+    capacity: usize,
+}
+
+impl<K, V, A, I, H> CachedHamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived:
+        Deserialize<V, Infallible> + for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I, H>: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+        + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Caches the values of up to `capacity` keys of `map`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(map: Hamt<K, V, A, I, H>, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        CachedHamt {
+            map,
+            hot: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns a copy of the value stored under `key`
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let digest = H::digest(key);
+        if let Some(pos) = self.position(key, digest) {
+            self.hot[..=pos].rotate_right(1);
+            return Some(self.hot[0].2.clone());
+        }
+
+        let val = match self.map.find(key)? {
+            MaybeArchived::Memory(val) => val.clone(),
+            MaybeArchived::Archived(val) => val
+                .deserialize(&mut Infallible)
+                .expect("deserializing is infallible"),
+        };
+        if self.hot.len() == self.capacity {
+            self.hot.pop();
+        }
+        self.hot.insert(0, (digest, key.clone(), val.clone()));
+        Some(val)
+    }
+
+    /// Inserts a key-value pair, returning the previous value under `key`
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        self.invalidate(&key);
+        self.map.insert(key, val)
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.invalidate(key);
+        self.map.remove(key)
+    }
+
+    /// Returns true if a copy of the value under `key` is at hand
+    pub fn is_cached(&self, key: &K) -> bool {
+        self.position(key, H::digest(key)).is_some()
+    }
+
+    /// Drops the cached copies
+    pub fn clear_cache(&mut self) {
+        self.hot.clear();
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &Hamt<K, V, A, I, H> {
+        &self.map
+    }
+
+    /// Consumes the cache, returning the underlying map
+    pub fn into_map(self) -> Hamt<K, V, A, I, H> {
+        self.map
+    }
+
+    fn position(&self, key: &K, digest: H::Digest) -> Option<usize> {
+        self.hot
+            .iter()
+            .position(|(d, k, _)| *d == digest && k == key)
+    }
+
+    fn invalidate(&mut self, key: &K) {
+        if let Some(pos) = self.position(key, H::digest(key)) {
+            self.hot.remove(pos);
+        }
+    }
+}
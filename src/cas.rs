@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Batches of updates applied only to the expected version of a map
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    Annotation, ArchivedCompound, StoreRef, StoreSerializer, Stored,
+};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{Commitment, Hamt, KeyHasher, KvPair, RootRegistry, Update};
+
+type StoredMap<K, V, A, I, H> = Stored<Hamt<K, V, A, I, H>, I>;
+
+/// The root commitment of a map differs from the one a batch of updates
+/// was prepared against, see [`RootRegistry::compare_and_apply`]
+pub struct Conflict<C> {
+    /// The commitment the batch expected
+    pub expected: Commitment<C>,
+    /// The commitment of the map
+    pub found: Commitment<C>,
+}
+
+impl<C> Clone for Conflict<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Conflict<C> {}
+
+impl<C> PartialEq for Conflict<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.expected == other.expected && self.found == other.found
+    }
+}
+
+impl<C> Eq for Conflict<C> {}
+
+impl<C> fmt::Debug for Conflict<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conflict")
+            .field("expected", &self.expected)
+            .field("found", &self.found)
+            .finish()
+    }
+}
+
+impl<I> RootRegistry<I>
+where
+    I: Archive<Archived = I>
+        + Clone
+        + Serialize<StoreSerializer<I>>
+        + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Applies `updates` in order to the map saved under `name`, only if
+    /// its root commitment is `expected`, saving and returning the new root
+    ///
+    /// Writers sharing a map through handles to the same registry read its
+    /// commitment, prepare a batch of updates from what they read and apply
+    /// it with the commitment they read. The map is reloaded from the root
+    /// currently saved under `name`, so if another writer got there first,
+    /// or saves a root under `name` while the batch is being applied, the
+    /// registry is left untouched and the batch should be prepared again
+    /// from the current root. A name with no root stands for an empty map.
+    pub fn compare_and_apply<K, V, A, H, C>(
+        &self,
+        name: &str,
+        expected: &Commitment<C>,
+        updates: &[Update<'_, K, V>],
+    ) -> Result<StoredMap<K, V, A, I, H>, Conflict<C>>
+    where
+        K: Archive<Archived = K>
+            + Clone
+            + Eq
+            + Hash
+            + for<'a> CheckBytes<DefaultValidator<'a>>,
+        V: Archive + Clone,
+        V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+        A: Annotation<KvPair<K, V>> + Borrow<Commitment<C>>,
+        Hamt<K, V, A, I, H>: Archive + Serialize<StoreSerializer<I>>,
+        <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+            + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+            + for<'a> CheckBytes<DefaultValidator<'a>>,
+        H: KeyHasher,
+    {
+        let (mut map, revision) = self.current(name);
+        let found = map.commitment();
+        if found != *expected {
+            return Err(Conflict {
+                expected: *expected,
+                found,
+            });
+        }
+        for update in updates {
+            match *update {
+                Update::Insert(key, val) => {
+                    map.insert(key.clone(), val.clone());
+                }
+                Update::Remove(key) => {
+                    map.remove(key);
+                }
+            }
+        }
+        let root = self.store().store(&map);
+        if self.replace_root(name, revision, &root) {
+            Ok(root)
+        } else {
+            let (map, _) = self.current::<K, V, A, H>(name);
+            Err(Conflict {
+                expected: *expected,
+                found: map.commitment(),
+            })
+        }
+    }
+
+    /// Loads the map saved under `name`, along with the revision of its
+    /// root, or an empty map if there is none
+    fn current<K, V, A, H>(
+        &self,
+        name: &str,
+    ) -> (Hamt<K, V, A, I, H>, Option<u64>)
+    where
+        K: Archive<Archived = K>
+            + Clone
+            + Eq
+            + Hash
+            + for<'a> CheckBytes<DefaultValidator<'a>>,
+        V: Archive + Clone,
+        V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+        A: Annotation<KvPair<K, V>>,
+        Hamt<K, V, A, I, H>: Archive,
+        <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+            + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+            + for<'a> CheckBytes<DefaultValidator<'a>>,
+        H: KeyHasher,
+    {
+        match self.revision_of(name) {
+            Some((root, revision)) => (Hamt::load_root(&root), Some(revision)),
+            None => (Hamt::new(), None),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Annotations aggregating with checked arithmetic
+
+use core::borrow::Borrow;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    Annotation, Combine, Compound, Discriminant, Primitive, Step, Walkable,
+    Walker,
+};
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{KvPair, Projection};
+
+/// Error returned when an aggregate overflowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+/// The number of elements of a collection, poisoned instead of wrapping on
+/// overflow
+#[derive(
+    PartialEq,
+    Eq,
+    Debug,
+    Clone,
+    Copy,
+    Archive,
+    Serialize,
+    Deserialize,
+    CheckBytes,
+)]
+#[repr(u8)]
+#[archive(as = "Self")]
+pub enum CheckedCardinality {
+    /// The number of elements
+    Count(u64),
+    /// The number of elements overflowed
+    Overflow,
+}
+
+impl CheckedCardinality {
+    /// Returns the number of elements, or an error if it overflowed
+    pub fn get(&self) -> Result<u64, Overflow> {
+        match self {
+            CheckedCardinality::Count(count) => Ok(*count),
+            CheckedCardinality::Overflow => Err(Overflow),
+        }
+    }
+}
+
+impl Default for CheckedCardinality {
+    fn default() -> Self {
+        CheckedCardinality::Count(0)
+    }
+}
+
+impl<L> Annotation<L> for CheckedCardinality {
+    fn from_leaf(_: &L) -> Self {
+        CheckedCardinality::Count(1)
+    }
+}
+
+impl<A> Combine<A> for CheckedCardinality
+where
+    A: Borrow<Self>,
+{
+    fn combine(&mut self, other: &A) {
+        *self = match (*self, other.borrow()) {
+            (CheckedCardinality::Count(a), CheckedCardinality::Count(b)) => {
+                match a.checked_add(*b) {
+                    Some(count) => CheckedCardinality::Count(count),
+                    None => CheckedCardinality::Overflow,
+                }
+            }
+            _ => CheckedCardinality::Overflow,
+        }
+    }
+}
+
+/// Walker to find the nth element of a collection annotated with
+/// [`CheckedCardinality`]
+///
+/// Aborts on subtrees whose count overflowed, which can only happen if the
+/// count of the root overflowed as well.
+#[derive(Debug)]
+pub struct CheckedNth(pub u64);
+
+impl<C, A, S> Walker<C, A, S> for CheckedNth
+where
+    C: Compound<A, S>,
+    A: Borrow<CheckedCardinality>,
+{
+    fn walk(&mut self, walk: impl Walkable<C, A, S>) -> Step {
+        for i in 0.. {
+            match walk.probe(i) {
+                Discriminant::Leaf(_) => {
+                    if self.0 == 0 {
+                        return Step::Found(i);
+                    } else {
+                        self.0 -= 1;
+                    }
+                }
+                Discriminant::Annotation(a) => {
+                    let card: &CheckedCardinality = (*a).borrow();
+                    match card.get() {
+                        Ok(count) if count <= self.0 => self.0 -= count,
+                        Ok(_) => return Step::Found(i),
+                        Err(Overflow) => return Step::Abort,
+                    }
+                }
+                Discriminant::Empty => (),
+                Discriminant::End => return Step::Abort,
+            };
+        }
+        unreachable!()
+    }
+}
+
+/// Addition that fails instead of wrapping on overflow
+pub trait CheckedAdd: Sized {
+    /// Adds `other` to `self`, returning `None` on overflow
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! checked_add {
+    ($($t:ty),*) => {
+        $(
+            impl CheckedAdd for $t {
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_add(*self, *other)
+                }
+            }
+        )*
+    };
+}
+
+checked_add!(u8, u16, u32, u64, u128);
+
+/// Subtraction that fails instead of wrapping on underflow
+pub trait CheckedSub: Sized {
+    /// Subtracts `other` from `self`, returning `None` on underflow
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! checked_sub {
+    ($($t:ty),*) => {
+        $(
+            impl CheckedSub for $t {
+                fn checked_sub(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_sub(*self, *other)
+                }
+            }
+        )*
+    };
+}
+
+checked_sub!(u8, u16, u32, u64, u128);
+
+/// The sum of the amounts `T` projected from the leaves of a subtree,
+/// poisoned instead of wrapping on overflow
+#[derive(
+    PartialEq, Eq, Clone, Debug, Archive, Serialize, Deserialize, CheckBytes,
+)]
+#[repr(u8)]
+#[archive(as = "Self")]
+#[archive(bound(archive = "
+  T: Primitive"))]
+pub enum CheckedSum<T> {
+    /// The sum of the amounts
+    Sum(T),
+    /// The sum overflowed
+    Overflow,
+}
+
+impl<T> CheckedSum<T> {
+    /// Returns the sum, or an error if it overflowed
+    pub fn get(&self) -> Result<&T, Overflow> {
+        match self {
+            CheckedSum::Sum(sum) => Ok(sum),
+            CheckedSum::Overflow => Err(Overflow),
+        }
+    }
+}
+
+impl<T> Default for CheckedSum<T>
+where
+    T: Default,
+{
+    fn default() -> Self {
+        CheckedSum::Sum(T::default())
+    }
+}
+
+impl<K, V, T> Annotation<KvPair<K, V>> for CheckedSum<T>
+where
+    V: Archive,
+    T: Projection<K, V> + CheckedAdd + Default + Primitive + Clone,
+{
+    fn from_leaf(leaf: &KvPair<K, V>) -> Self {
+        CheckedSum::Sum(T::project(&leaf.key, &leaf.val))
+    }
+}
+
+impl<T, A> Combine<A> for CheckedSum<T>
+where
+    T: CheckedAdd,
+    A: Borrow<Self>,
+{
+    fn combine(&mut self, other: &A) {
+        let sum = match (&*self, other.borrow()) {
+            (CheckedSum::Sum(a), CheckedSum::Sum(b)) => a.checked_add(b),
+            _ => None,
+        };
+        *self = match sum {
+            Some(sum) => CheckedSum::Sum(sum),
+            None => CheckedSum::Overflow,
+        }
+    }
+}
@@ -0,0 +1,478 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Merkle commitments over the contents of a map
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::marker::PhantomData;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, Child, Combine, Compound};
+use rkyv::rend::{BigEndian, LittleEndian};
+use rkyv::{Archive, Deserialize, Fallible, Serialize};
+
+use crate::KvPair;
+
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+const COMBINE: u8 = 2;
+
+const SLOT_EMPTY: u8 = 0;
+const SLOT_LEAF: u8 = 1;
+const SLOT_NODE: u8 = 2;
+
+/// A hash function producing 32 byte digests, used to build commitments
+///
+/// The crate does not mandate a hash function, implement this trait for the
+/// one required by your protocol.
+pub trait CommitmentHasher: Default {
+    /// Feeds bytes into the hasher
+    fn update(&mut self, bytes: &[u8]);
+
+    /// Consumes the hasher, returning the digest
+    fn finalize(self) -> [u8; 32];
+}
+
+/// Canonical encoding of the keys and values committed to by leaves
+///
+/// Unlike `Hash`, whose output depends on the endianness and pointer width
+/// of the platform, the encoding is the same everywhere, so commitments
+/// computed on different machines agree. Integers are written in fixed
+/// width little endian, `usize` and `isize` as 64 bits. The contents of
+/// slices, strings and vectors are prefixed by their length as a 32 bit
+/// little endian integer, those of arrays are not, their length being part
+/// of their type.
+pub trait CommitmentEncode {
+    /// Feeds the encoding of `self` into `hasher`
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher;
+}
+
+/// Collects the encoding of a value, see [`encoding`]
+#[cfg(feature = "seahash")]
+#[derive(Default)]
+struct Encoding(Vec<u8>);
+
+#[cfg(feature = "seahash")]
+impl CommitmentHasher for Encoding {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes)
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        unreachable!("Only used to collect the encoding")
+    }
+}
+
+/// Returns the canonical encoding of `t`
+#[cfg(feature = "seahash")]
+pub(crate) fn encoding<T>(t: &T) -> Vec<u8>
+where
+    T: CommitmentEncode + ?Sized,
+{
+    let mut encoding = Encoding::default();
+    t.encode(&mut encoding);
+    encoding.0
+}
+
+macro_rules! encode_int {
+    ($($int:ty),*) => {
+        $(
+            impl CommitmentEncode for $int {
+                fn encode<H>(&self, hasher: &mut H)
+                where
+                    H: CommitmentHasher,
+                {
+                    hasher.update(&self.to_le_bytes())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! encode_endian {
+    ($($int:ty),*) => {
+        $(
+            impl CommitmentEncode for LittleEndian<$int> {
+                fn encode<H>(&self, hasher: &mut H)
+                where
+                    H: CommitmentHasher,
+                {
+                    self.value().encode(hasher)
+                }
+            }
+
+            impl CommitmentEncode for BigEndian<$int> {
+                fn encode<H>(&self, hasher: &mut H)
+                where
+                    H: CommitmentHasher,
+                {
+                    self.value().encode(hasher)
+                }
+            }
+        )*
+    };
+}
+
+encode_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+encode_endian!(u16, u32, u64, u128, i16, i32, i64, i128);
+
+impl CommitmentEncode for usize {
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        (*self as u64).encode(hasher)
+    }
+}
+
+impl CommitmentEncode for isize {
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        (*self as i64).encode(hasher)
+    }
+}
+
+impl CommitmentEncode for bool {
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        (*self as u8).encode(hasher)
+    }
+}
+
+impl CommitmentEncode for char {
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        (*self as u32).encode(hasher)
+    }
+}
+
+impl CommitmentEncode for () {
+    fn encode<H>(&self, _: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+    }
+}
+
+/// Writes the length prefixing the contents of slices
+///
+/// # Panics
+///
+/// Panics if `len` does not fit in 32 bits.
+fn encode_len<H>(len: usize, hasher: &mut H)
+where
+    H: CommitmentHasher,
+{
+    u32::try_from(len)
+        .expect("Committed lengths fit in 32 bits")
+        .encode(hasher)
+}
+
+impl<T, const N: usize> CommitmentEncode for [T; N]
+where
+    T: CommitmentEncode,
+{
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        for item in self {
+            item.encode(hasher);
+        }
+    }
+}
+
+impl<T> CommitmentEncode for [T]
+where
+    T: CommitmentEncode,
+{
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        encode_len(self.len(), hasher);
+        for item in self {
+            item.encode(hasher);
+        }
+    }
+}
+
+impl<T> CommitmentEncode for Vec<T>
+where
+    T: CommitmentEncode,
+{
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        self.as_slice().encode(hasher)
+    }
+}
+
+impl CommitmentEncode for str {
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        encode_len(self.len(), hasher);
+        hasher.update(self.as_bytes());
+    }
+}
+
+impl CommitmentEncode for String {
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        self.as_str().encode(hasher)
+    }
+}
+
+impl<T> CommitmentEncode for Option<T>
+where
+    T: CommitmentEncode,
+{
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        match self {
+            None => 0u8.encode(hasher),
+            Some(val) => {
+                1u8.encode(hasher);
+                val.encode(hasher);
+            }
+        }
+    }
+}
+
+impl<T> CommitmentEncode for &T
+where
+    T: CommitmentEncode + ?Sized,
+{
+    fn encode<H>(&self, hasher: &mut H)
+    where
+        H: CommitmentHasher,
+    {
+        (**self).encode(hasher)
+    }
+}
+
+macro_rules! encode_tuple {
+    ($($name:ident)+) => {
+        impl<$($name),+> CommitmentEncode for ($($name,)+)
+        where
+            $($name: CommitmentEncode,)+
+        {
+            #[allow(non_snake_case)]
+            fn encode<H>(&self, hasher: &mut H)
+            where
+                H: CommitmentHasher,
+            {
+                let ($($name,)+) = self;
+                $($name.encode(hasher);)+
+            }
+        }
+    };
+}
+
+encode_tuple!(A);
+encode_tuple!(A B);
+encode_tuple!(A B C);
+encode_tuple!(A B C D);
+encode_tuple!(A B C D E);
+encode_tuple!(A B C D E F);
+
+/// Merkle commitment to a subtree, using `H` as hash function
+///
+/// Leaves commit to the [`CommitmentEncode`] encoding of their key and
+/// value, nodes commit to the kind and
+/// commitment of each of their slots, so two maps with equal root
+/// commitments hold the same entries.
+///
+/// Like every annotation, commitments are computed lazily: mutations only
+/// drop the ones cached along the paths they touch, and nothing is hashed
+/// until a commitment is read, so a batch of mutations costs no hashing
+/// until [`Hamt::commitment`](crate::Hamt::commitment) is called.
+#[derive(CheckBytes)]
+pub struct Commitment<H>([u8; 32], PhantomData<H>);
+
+impl<H> Archive for Commitment<H> {
+    type Archived = Self;
+    type Resolver = ();
+
+    unsafe fn resolve(&self, _: usize, _: (), out: *mut Self) {
+        out.write(*self)
+    }
+}
+
+impl<H, S> Serialize<S> for Commitment<H>
+where
+    S: Fallible + ?Sized,
+{
+    fn serialize(&self, _: &mut S) -> Result<(), S::Error> {
+        Ok(())
+    }
+}
+
+impl<H, D> Deserialize<Commitment<H>, D> for Commitment<H>
+where
+    D: Fallible + ?Sized,
+{
+    fn deserialize(&self, _: &mut D) -> Result<Commitment<H>, D::Error> {
+        Ok(*self)
+    }
+}
+
+impl<H> Commitment<H> {
+    /// Creates a commitment from its digest
+    pub fn new(digest: [u8; 32]) -> Self {
+        Commitment(digest, PhantomData)
+    }
+
+    /// Returns the digest of the commitment
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl<H> Clone for Commitment<H> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<H> Copy for Commitment<H> {}
+
+impl<H> Default for Commitment<H> {
+    fn default() -> Self {
+        Commitment::new([0; 32])
+    }
+}
+
+impl<H> PartialEq for Commitment<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<H> Eq for Commitment<H> {}
+
+impl<H> fmt::Debug for Commitment<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Commitment(")?;
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<H> Commitment<H>
+where
+    H: CommitmentHasher,
+{
+    /// Returns the commitment of a leaf holding `key` and `val`
+    pub(crate) fn from_entry<K, V>(key: &K, val: &V) -> Self
+    where
+        K: CommitmentEncode + ?Sized,
+        V: CommitmentEncode + ?Sized,
+    {
+        let mut hasher = H::default();
+        hasher.update(&[LEAF]);
+        key.encode(&mut hasher);
+        val.encode(&mut hasher);
+        Commitment::new(hasher.finalize())
+    }
+}
+
+/// Computes the commitment of a node from its slots, in order
+pub(crate) struct NodeHasher<H>(H);
+
+impl<H> NodeHasher<H>
+where
+    H: CommitmentHasher,
+{
+    pub fn new() -> Self {
+        let mut hasher = H::default();
+        hasher.update(&[NODE]);
+        NodeHasher(hasher)
+    }
+
+    pub fn empty(&mut self) {
+        self.0.update(&[SLOT_EMPTY]);
+    }
+
+    pub fn leaf(&mut self, leaf: &Commitment<H>) {
+        self.0.update(&[SLOT_LEAF]);
+        self.0.update(leaf.as_bytes());
+    }
+
+    pub fn node(&mut self, node: &Commitment<H>) {
+        self.0.update(&[SLOT_NODE]);
+        self.0.update(node.as_bytes());
+    }
+
+    pub fn finish(self) -> Commitment<H> {
+        Commitment::new(self.0.finalize())
+    }
+}
+
+impl<H, K, V> Annotation<KvPair<K, V>> for Commitment<H>
+where
+    H: CommitmentHasher,
+    K: CommitmentEncode,
+    V: CommitmentEncode,
+{
+    fn from_leaf(leaf: &KvPair<K, V>) -> Self {
+        Self::from_entry(&leaf.key, &leaf.val)
+    }
+
+    fn from_node<C, S>(node: &C) -> Self
+    where
+        C: Compound<Self, S, Leaf = KvPair<K, V>>,
+        C::Leaf: Archive,
+    {
+        let mut hasher = NodeHasher::new();
+        for i in 0.. {
+            match node.child(i) {
+                Child::Leaf(leaf) => hasher.leaf(&Self::from_leaf(leaf)),
+                Child::Link(link) => hasher.node(&link.annotation()),
+                Child::Empty => hasher.empty(),
+                Child::End => break,
+            }
+        }
+        hasher.finish()
+    }
+}
+
+impl<H> Combine<Self> for Commitment<H>
+where
+    H: CommitmentHasher,
+{
+    /// Order dependent combination of two commitments
+    ///
+    /// Node commitments are computed from all slots at once in
+    /// `Annotation::from_node`, this is only provided for completeness.
+    fn combine(&mut self, other: &Self) {
+        let mut hasher = H::default();
+        hasher.update(&[COMBINE]);
+        hasher.update(&self.0);
+        hasher.update(&other.0);
+        self.0 = hasher.finalize();
+    }
+}
@@ -0,0 +1,233 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Annotations updated in place by the mutations of a map
+
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    Annotation, ArchivedCompound, Cardinality, Combine, Link, Primitive,
+    StoreRef,
+};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::{
+    slot, Bucket, CheckedAdd, CheckedCardinality, CheckedSub, CheckedSum, Hamt,
+    KeyHasher, KvPair, Projection,
+};
+
+/// An annotation that can be updated from the leaves inserted in and
+/// removed from a subtree, rather than recomputed from its children
+///
+/// Each method returns false if the change cannot be applied, in which case
+/// the annotation is recomputed from the children on the next read, as it
+/// is for annotations updated by [`Hamt::insert`] and [`Hamt::remove`].
+pub trait DeltaAnnotation<L>: Annotation<L> {
+    /// Accounts for `leaf` being inserted in the subtree
+    fn on_insert(&mut self, leaf: &L) -> bool;
+
+    /// Accounts for `leaf` being removed from the subtree
+    fn on_remove(&mut self, leaf: &L) -> bool;
+
+    /// Accounts for `old` being replaced by `new` in the subtree
+    fn on_replace(&mut self, old: &L, new: &L) -> bool {
+        self.on_remove(old) && self.on_insert(new)
+    }
+}
+
+impl<L> DeltaAnnotation<L> for Cardinality {
+    fn on_insert(&mut self, leaf: &L) -> bool {
+        self.combine(&<Self as Annotation<L>>::from_leaf(leaf));
+        true
+    }
+
+    /// Cardinalities cannot be decremented, so removals always fall back
+    /// to recomputing them
+    fn on_remove(&mut self, _: &L) -> bool {
+        false
+    }
+
+    fn on_replace(&mut self, _: &L, _: &L) -> bool {
+        true
+    }
+}
+
+impl<L> DeltaAnnotation<L> for CheckedCardinality {
+    fn on_insert(&mut self, leaf: &L) -> bool {
+        self.combine(&<Self as Annotation<L>>::from_leaf(leaf));
+        true
+    }
+
+    fn on_remove(&mut self, _: &L) -> bool {
+        match self {
+            CheckedCardinality::Count(count) => match count.checked_sub(1) {
+                Some(decremented) => {
+                    *count = decremented;
+                    true
+                }
+                None => false,
+            },
+            // the count of the remaining leaves may fit again
+            CheckedCardinality::Overflow => false,
+        }
+    }
+
+    fn on_replace(&mut self, _: &L, _: &L) -> bool {
+        true
+    }
+}
+
+impl<K, V, T> DeltaAnnotation<KvPair<K, V>> for CheckedSum<T>
+where
+    V: Archive,
+    T: Projection<K, V> + CheckedAdd + CheckedSub + Default + Primitive + Clone,
+{
+    fn on_insert(&mut self, leaf: &KvPair<K, V>) -> bool {
+        self.combine(&Self::from_leaf(leaf));
+        true
+    }
+
+    fn on_remove(&mut self, leaf: &KvPair<K, V>) -> bool {
+        let amount = T::project(&leaf.key, &leaf.val);
+        match self {
+            CheckedSum::Sum(sum) => match sum.checked_sub(&amount) {
+                Some(difference) => {
+                    *sum = difference;
+                    true
+                }
+                None => false,
+            },
+            // the sum of the remaining leaves may fit again
+            CheckedSum::Overflow => false,
+        }
+    }
+}
+
+/// Returns the annotation cached in `link`, if any
+fn cached<C, A, I>(link: &Link<C, A, I>) -> Option<A>
+where
+    A: Clone,
+{
+    match link {
+        Link::Memory { annotation, .. } => annotation.borrow().clone(),
+        Link::Stored { a, .. } => Some(a.clone()),
+    }
+}
+
+/// Caches `a` as the annotation of `link`, after it was mutated in memory
+fn restore<C, A, I>(link: &Link<C, A, I>, a: A) {
+    if let Link::Memory { annotation, .. } = link {
+        *annotation.borrow_mut() = Some(a);
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: DeltaAnnotation<KvPair<K, V>> + Clone,
+    Self: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Self, A, I>
+        + Deserialize<Self, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Inserts a key-value pair like [`insert`](Self::insert), updating the
+    /// cached annotations along its path instead of invalidating them
+    pub fn insert_delta(&mut self, key: K, val: V) -> Option<V> {
+        let digest = H::digest(&key);
+        self._insert_delta(&KvPair { key, val }, digest, 0)
+    }
+
+    fn _insert_delta(
+        &mut self,
+        kv: &KvPair<K, V>,
+        digest: H::Digest,
+        depth: usize,
+    ) -> Option<V> {
+        let ofs = slot::<H>(digest, depth);
+        let link = match &mut self.0[ofs] {
+            Bucket::Node(link) => link,
+            _ => {
+                return self._insert(
+                    kv.key.clone(),
+                    kv.val.clone(),
+                    digest,
+                    depth,
+                )
+            }
+        };
+
+        let before = cached(link);
+        let old = link.inner_mut()._insert_delta(kv, digest, depth + 1);
+
+        if let Some(mut a) = before {
+            let applied = match &old {
+                Some(val) => {
+                    let replaced = KvPair::new(kv.key.clone(), val.clone());
+                    a.on_replace(&replaced, kv)
+                }
+                None => a.on_insert(kv),
+            };
+            if applied {
+                restore(link, a);
+            }
+        }
+        old
+    }
+
+    /// Removes the entry stored under `key` like [`remove`](Self::remove),
+    /// updating the cached annotations along its path instead of
+    /// invalidating them
+    pub fn remove_delta(&mut self, key: &K) -> Option<V> {
+        let digest = H::digest(key);
+        self._remove_delta(key, digest, 0)
+    }
+
+    fn _remove_delta(
+        &mut self,
+        key: &K,
+        digest: H::Digest,
+        depth: usize,
+    ) -> Option<V> {
+        let ofs = slot::<H>(digest, depth);
+        let link = match &mut self.0[ofs] {
+            Bucket::Node(link) => link,
+            _ => return self._remove(key, digest, depth),
+        };
+
+        let before = cached(link);
+        let node = link.inner_mut();
+        let old = node._remove_delta(key, digest, depth + 1);
+
+        if let Some((key, val)) = node.collapse() {
+            self.0[ofs] = Bucket::Leaf(KvPair { key, val });
+            return old;
+        }
+
+        if let Some(mut a) = before {
+            let applied = match &old {
+                Some(val) => {
+                    a.on_remove(&KvPair::new(key.clone(), val.clone()))
+                }
+                // nothing was removed, the annotation is unchanged
+                None => true,
+            };
+            if applied {
+                restore(link, a);
+            }
+        }
+        old
+    }
+}
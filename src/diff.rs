@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Changelists between two versions of a map
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::MaybeArchived;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Infallible, Serialize};
+
+use crate::join::Join;
+use crate::{Hamt, KeyHasher};
+
+/// A change to a single entry of a map
+#[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
+pub enum Change<K, V> {
+    /// The entry is only present in the newer map
+    Added {
+        /// The key of the entry
+        key: K,
+        /// The value in the newer map
+        val: V,
+    },
+    /// The entry is only present in the older map
+    Removed {
+        /// The key of the entry
+        key: K,
+        /// The value in the older map
+        val: V,
+    },
+    /// The entry is present in both maps with different values
+    Changed {
+        /// The key of the entry
+        key: K,
+        /// The value in the older map
+        old: V,
+        /// The value in the newer map
+        new: V,
+    },
+}
+
+impl<K, V> Change<K, V> {
+    /// Returns the key of the changed entry
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Added { key, .. }
+            | Change::Removed { key, .. }
+            | Change::Changed { key, .. } => key,
+        }
+    }
+}
+
+impl<K, V> fmt::Display for Change<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { key, val } => write!(f, "+ {:?}: {:?}", key, val),
+            Change::Removed { key, val } => {
+                write!(f, "- {:?}: {:?}", key, val)
+            }
+            Change::Changed { key, old, new } => {
+                write!(f, "~ {:?}: {:?} -> {:?}", key, old, new)
+            }
+        }
+    }
+}
+
+/// The changes turning one map into another, in digest path order, see
+/// [`Hamt::changes`]
+///
+/// Displays as one line per change, prefixed with `+`, `-` or `~` for
+/// added, removed and changed entries respectively.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, Archive, Serialize, Deserialize,
+)]
+pub struct Changelist<K, V> {
+    changes: Vec<Change<K, V>>,
+}
+
+impl<K, V> Changelist<K, V> {
+    pub(crate) fn new<A, I, H>(
+        old: &Hamt<K, V, A, I, H>,
+        new: &Hamt<K, V, A, I, H>,
+    ) -> Self
+    where
+        K: Archive<Archived = K> + Clone + Eq + Hash,
+        V: Archive + Clone + PartialEq,
+        V::Archived: Deserialize<V, Infallible>,
+        <Hamt<K, V, A, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+        H: KeyHasher,
+    {
+        let changes = Join::new(old, new, false, true)
+            .filter_map(|(key, old, new)| {
+                let key = key.clone();
+                match (old.map(owned), new.map(owned)) {
+                    (Some(old), Some(new)) if old == new => None,
+                    (Some(old), Some(new)) => {
+                        Some(Change::Changed { key, old, new })
+                    }
+                    (Some(val), None) => Some(Change::Removed { key, val }),
+                    (None, Some(val)) => Some(Change::Added { key, val }),
+                    (None, None) => None,
+                }
+            })
+            .collect();
+        Changelist { changes }
+    }
+
+    /// Returns the changes
+    pub fn changes(&self) -> &[Change<K, V>] {
+        &self.changes
+    }
+
+    /// Returns the number of changed entries
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns true if both maps hold the same entries
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Consumes the changelist, returning the changes
+    pub fn into_changes(self) -> Vec<Change<K, V>> {
+        self.changes
+    }
+}
+
+impl<K, V> fmt::Display for Changelist<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+fn owned<V>(val: MaybeArchived<'_, V>) -> V
+where
+    V: Archive + Clone,
+    V::Archived: Deserialize<V, Infallible>,
+{
+    match val {
+        MaybeArchived::Memory(val) => val.clone(),
+        MaybeArchived::Archived(val) => val
+            .deserialize(&mut Infallible)
+            .expect("deserializing is infallible"),
+    }
+}
@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Approximate count of distinct values
+
+use core::borrow::Borrow;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, Combine};
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{hash, KvPair};
+
+const INDEX_BITS: u32 = 6;
+const REGISTERS: usize = 1 << INDEX_BITS;
+
+/// HyperLogLog sketch estimating the number of distinct values in a subtree
+///
+/// Uses 64 registers, giving a standard error of about 13%. Equal values
+/// stored under different keys are counted once.
+#[derive(
+    PartialEq,
+    Eq,
+    Debug,
+    Clone,
+    Copy,
+    Archive,
+    Serialize,
+    Deserialize,
+    CheckBytes,
+)]
+#[archive(as = "Self")]
+pub struct DistinctValues([u8; REGISTERS]);
+
+impl Default for DistinctValues {
+    fn default() -> Self {
+        DistinctValues([0; REGISTERS])
+    }
+}
+
+impl DistinctValues {
+    /// Returns the estimated number of distinct values
+    pub fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+
+        let mut sum = 0.0;
+        let mut zeros = 0;
+        for register in self.0.iter() {
+            sum += 1.0 / (1u64 << register) as f64;
+            if *register == 0 {
+                zeros += 1;
+            }
+        }
+
+        let alpha = 0.709;
+        let raw = alpha * m * m / sum;
+
+        // small range correction, using linear counting
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * ln(m / zeros as f64)
+        } else {
+            raw
+        };
+
+        (estimate + 0.5) as u64
+    }
+}
+
+/// Natural logarithm for `x >= 1`, since `core` provides no float math
+fn ln(mut x: f64) -> f64 {
+    const LN_2: f64 = core::f64::consts::LN_2;
+
+    let mut result = 0.0;
+    while x >= 2.0 {
+        x /= 2.0;
+        result += LN_2;
+    }
+
+    // ln(x) = 2 * atanh((x - 1) / (x + 1)), converging fast for x in [1, 2)
+    let y = (x - 1.0) / (x + 1.0);
+    let y2 = y * y;
+    let mut term = y;
+    let mut series = 0.0;
+    for n in 0..16 {
+        series += term / (2 * n + 1) as f64;
+        term *= y2;
+    }
+
+    result + 2.0 * series
+}
+
+impl<K, V> Annotation<KvPair<K, V>> for DistinctValues
+where
+    V: Hash,
+{
+    fn from_leaf(leaf: &KvPair<K, V>) -> Self {
+        let digest = hash(&leaf.val);
+        let index = (digest >> (64 - INDEX_BITS)) as usize;
+        let rank =
+            (digest << INDEX_BITS).leading_zeros().min(64 - INDEX_BITS) + 1;
+
+        let mut sketch = Self::default();
+        sketch.0[index] = rank as u8;
+        sketch
+    }
+}
+
+impl<A> Combine<A> for DistinctValues
+where
+    A: Borrow<Self>,
+{
+    fn combine(&mut self, other: &A) {
+        for (register, other) in self.0.iter_mut().zip(other.borrow().0.iter())
+        {
+            *register = (*register).max(*other);
+        }
+    }
+}
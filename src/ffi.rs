@@ -0,0 +1,399 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! C interface to maps of 32 byte keys to byte string values
+//!
+//! Maps are handed out as opaque pointers and must be released with
+//! [`hamt_free`]. Keys are exactly 32 bytes long, the size of the hashes
+//! used as keys throughout the Dusk stack, and values are up to
+//! [`HAMT_MAX_VAL_LEN`] bytes long. The functions are laid out to be picked
+//! up by `cbindgen`.
+//!
+//! Maps are written to stores opened with [`hamt_store_open`], and opened
+//! again from the [`HamtRoot`] they were written under with [`hamt_open`],
+//! by any program sharing the store.
+//!
+//! Panics never unwind into the caller: they are caught at the boundary and
+//! reported as [`HAMT_PANIC`], or as a null handle. A map whose call
+//! panicked may be left half updated, and should be released.
+
+use alloc::boxed::Box;
+use core::ffi::c_void;
+use core::ptr;
+use core::slice;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    HostStore, Ident, MaybeArchived, OffsetLen, StoreRef, Stored,
+};
+use rkyv::rend::LittleEndian;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{Hamt, Lookup};
+
+/// The operation succeeded
+pub const HAMT_OK: i32 = 0;
+/// The key is not present in the map
+pub const HAMT_NOT_FOUND: i32 = 1;
+/// A pointer argument was null
+pub const HAMT_NULL: i32 = -1;
+/// The key is not 32 bytes long
+pub const HAMT_INVALID_KEY: i32 = -2;
+/// The call panicked, see the [module documentation](self)
+pub const HAMT_PANIC: i32 = -3;
+/// The value is longer than [`HAMT_MAX_VAL_LEN`]
+pub const HAMT_INVALID_VAL: i32 = -4;
+/// The store failed to persist its contents
+pub const HAMT_IO: i32 = -5;
+
+/// Maximum length of a value, in bytes
+pub const HAMT_MAX_VAL_LEN: usize = 256;
+
+/// A value of up to [`HAMT_MAX_VAL_LEN`] bytes
+///
+/// The bytes are stored inline rather than in a separate allocation, since
+/// stores keep every node and its leaves in a single contiguous region.
+#[derive(Clone, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+struct Bytes {
+    len: LittleEndian<u16>,
+    bytes: [u8; HAMT_MAX_VAL_LEN],
+}
+
+type Map = Hamt<[u8; 32], Bytes, (), OffsetLen>;
+
+/// Opaque handle to a map
+pub struct HamtHandle(Map);
+
+/// Opaque handle to a store
+pub struct HamtStore(StoreRef<OffsetLen>);
+
+/// Identifies a map written to a store, see [`hamt_store_map`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HamtRoot {
+    /// Offset of the root node in the store
+    pub offset: u64,
+    /// Length of the root node, in bytes
+    pub len: u16,
+}
+
+/// Callback receiving the entries of a map, see [`hamt_iterate`]
+pub type HamtVisitor = extern "C" fn(
+    ctx: *mut c_void,
+    key: *const u8,
+    key_len: usize,
+    val: *const u8,
+    val_len: usize,
+);
+
+unsafe fn key_from_raw(
+    key: *const u8,
+    key_len: usize,
+) -> Result<[u8; 32], i32> {
+    if key.is_null() {
+        return Err(HAMT_NULL);
+    }
+    if key_len != 32 {
+        return Err(HAMT_INVALID_KEY);
+    }
+    let mut buf = [0; 32];
+    buf.copy_from_slice(slice::from_raw_parts(key, key_len));
+    Ok(buf)
+}
+
+/// Runs `f`, reporting a panic as [`HAMT_PANIC`] instead of unwinding
+fn guard<F>(f: F) -> i32
+where
+    F: FnOnce() -> i32,
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(HAMT_PANIC)
+}
+
+fn val_as_slice(val: MaybeArchived<'_, Bytes>) -> &[u8] {
+    match val {
+        MaybeArchived::Memory(val) => &val.bytes[..val.len.value() as usize],
+        MaybeArchived::Archived(val) => &val.bytes[..val.len.value() as usize],
+    }
+}
+
+/// Creates a new empty map
+#[no_mangle]
+pub extern "C" fn hamt_new() -> *mut HamtHandle {
+    panic::catch_unwind(|| Box::into_raw(Box::new(HamtHandle(Map::new()))))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Releases a map created by [`hamt_new`]
+///
+/// # Safety
+///
+/// `map` must have been returned by [`hamt_new`] and not been released yet,
+/// or be null.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_free(map: *mut HamtHandle) {
+    if !map.is_null() {
+        let map = AssertUnwindSafe(Box::from_raw(map));
+        // hamt_free returns nothing to report a panic with
+        let _ = panic::catch_unwind(move || drop(map));
+    }
+}
+
+/// Inserts a copy of `val` under a copy of `key`, replacing any previous
+/// value
+///
+/// # Safety
+///
+/// `map` must be a live map, `key` and `val` must point to `key_len` and
+/// `val_len` readable bytes respectively. `val` may be null if `val_len` is
+/// zero.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_insert(
+    map: *mut HamtHandle,
+    key: *const u8,
+    key_len: usize,
+    val: *const u8,
+    val_len: usize,
+) -> i32 {
+    guard(|| {
+        if map.is_null() || (val.is_null() && val_len > 0) {
+            return HAMT_NULL;
+        }
+        let key = match key_from_raw(key, key_len) {
+            Ok(key) => key,
+            Err(code) => return code,
+        };
+        if val_len > HAMT_MAX_VAL_LEN {
+            return HAMT_INVALID_VAL;
+        }
+        let mut bytes = [0; HAMT_MAX_VAL_LEN];
+        if val_len > 0 {
+            bytes[..val_len]
+                .copy_from_slice(slice::from_raw_parts(val, val_len));
+        }
+        let val = Bytes {
+            len: (val_len as u16).into(),
+            bytes,
+        };
+        (*map).0.insert(key, val);
+        HAMT_OK
+    })
+}
+
+/// Looks up the value stored under `key`
+///
+/// On success the value is written to `val` and `val_len`. It is owned by
+/// the map and only valid until the map is next modified or released.
+///
+/// # Safety
+///
+/// `map` must be a live map, `key` must point to `key_len` readable bytes,
+/// `val` and `val_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_get(
+    map: *const HamtHandle,
+    key: *const u8,
+    key_len: usize,
+    val: *mut *const u8,
+    val_len: *mut usize,
+) -> i32 {
+    guard(|| {
+        if map.is_null() || val.is_null() || val_len.is_null() {
+            return HAMT_NULL;
+        }
+        let key = match key_from_raw(key, key_len) {
+            Ok(key) => key,
+            Err(code) => return code,
+        };
+        match (*map).0.get(&key) {
+            Some(value) => {
+                let bytes = val_as_slice(value.leaf());
+                *val = bytes.as_ptr();
+                *val_len = bytes.len();
+                HAMT_OK
+            }
+            None => HAMT_NOT_FOUND,
+        }
+    })
+}
+
+/// Removes the value stored under `key`
+///
+/// # Safety
+///
+/// `map` must be a live map and `key` must point to `key_len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_remove(
+    map: *mut HamtHandle,
+    key: *const u8,
+    key_len: usize,
+) -> i32 {
+    guard(|| {
+        if map.is_null() {
+            return HAMT_NULL;
+        }
+        let key = match key_from_raw(key, key_len) {
+            Ok(key) => key,
+            Err(code) => return code,
+        };
+        match (*map).0.remove(&key) {
+            Some(_) => HAMT_OK,
+            None => HAMT_NOT_FOUND,
+        }
+    })
+}
+
+/// Calls `visit` with `ctx` on every entry of the map, in digest path order
+///
+/// The keys and values passed to `visit` are only valid for the duration of
+/// the call.
+///
+/// # Safety
+///
+/// `map` must be a live map, and must not be modified by `visit`.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_iterate(
+    map: *const HamtHandle,
+    visit: HamtVisitor,
+    ctx: *mut c_void,
+) -> i32 {
+    guard(|| {
+        if map.is_null() {
+            return HAMT_NULL;
+        }
+        for (key, val) in (*map).0.iter_by_digest() {
+            let val = val_as_slice(val);
+            visit(ctx, key.as_ptr(), key.len(), val.as_ptr(), val.len());
+        }
+        HAMT_OK
+    })
+}
+
+/// Opens the store kept in the directory at `path`, creating it if missing
+///
+/// Returns null if `path` is not valid UTF-8 or the store cannot be
+/// opened. The store must be released with [`hamt_store_free`].
+///
+/// # Safety
+///
+/// `path` must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_store_open(
+    path: *const c_char,
+) -> *mut HamtStore {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    panic::catch_unwind(|| {
+        let path = match CStr::from_ptr(path).to_str() {
+            Ok(path) => path,
+            Err(_) => return ptr::null_mut(),
+        };
+        match HostStore::with_file(path) {
+            Ok(store) => {
+                Box::into_raw(Box::new(HamtStore(StoreRef::new(store))))
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Releases a store opened by [`hamt_store_open`]
+///
+/// Maps opened from the store stay valid, they keep it open until they are
+/// released.
+///
+/// # Safety
+///
+/// `store` must have been returned by [`hamt_store_open`] and not been
+/// released yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_store_free(store: *mut HamtStore) {
+    if !store.is_null() {
+        let store = AssertUnwindSafe(Box::from_raw(store));
+        // hamt_store_free returns nothing to report a panic with
+        let _ = panic::catch_unwind(move || drop(store));
+    }
+}
+
+/// Writes the contents of the store to its directory
+///
+/// # Safety
+///
+/// `store` must be a live store.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_store_persist(store: *const HamtStore) -> i32 {
+    guard(|| {
+        if store.is_null() {
+            return HAMT_NULL;
+        }
+        match (*store).0.persist() {
+            Ok(()) => HAMT_OK,
+            Err(()) => HAMT_IO,
+        }
+    })
+}
+
+/// Writes `map` to `store`, writing the root it can be opened from with
+/// [`hamt_open`] to `root`
+///
+/// The map is only durable once the store is persisted.
+///
+/// # Safety
+///
+/// `store` must be a live store, `map` a live map and `root` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_store_map(
+    store: *const HamtStore,
+    map: *const HamtHandle,
+    root: *mut HamtRoot,
+) -> i32 {
+    guard(|| {
+        if store.is_null() || map.is_null() || root.is_null() {
+            return HAMT_NULL;
+        }
+        let stored = (*store).0.store(&(*map).0);
+        let ident = stored.ident().erase();
+        *root = HamtRoot {
+            offset: ident.offset(),
+            len: ident.len(),
+        };
+        HAMT_OK
+    })
+}
+
+/// Opens the map written to `store` under `root`
+///
+/// Nodes are read from the store as they are reached, and changes to the
+/// map are kept in memory until it is written again. Returns null if
+/// `root` does not lead to a valid map. The map must be released with
+/// [`hamt_free`].
+///
+/// # Safety
+///
+/// `store` must be a live store.
+#[no_mangle]
+pub unsafe extern "C" fn hamt_open(
+    store: *const HamtStore,
+    root: HamtRoot,
+) -> *mut HamtHandle {
+    if store.is_null() {
+        return ptr::null_mut();
+    }
+    let store = AssertUnwindSafe((*store).0.clone());
+    panic::catch_unwind(move || {
+        let ident = Ident::new(OffsetLen::new(root.offset, root.len));
+        let stored = Stored::new(store.0, ident);
+        Box::into_raw(Box::new(HamtHandle(Map::load_root(&stored))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
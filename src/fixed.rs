@@ -0,0 +1,271 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! A map of fixed capacity, never allocating
+
+use core::hash::Hash;
+use core::mem;
+
+use crate::{slot, KeyHasher, SeaHash};
+
+enum Slot<K, V> {
+    Empty,
+    Leaf(K, V),
+    Node(usize),
+}
+
+type Node<K, V> = [Slot<K, V>; 4];
+
+/// A map storing its nodes in an inline array of `CAP` nodes
+///
+/// Nodes refer to each other by their index in the array and leaves are
+/// held in the slots of the nodes, so the map never allocates. Keys take the
+/// same paths as in a [`Hamt`](crate::Hamt) using the default key hasher.
+///
+/// Insertions needing more nodes than are left fail, handing the entry back.
+pub struct FixedHamt<K, V, const CAP: usize> {
+    nodes: [Node<K, V>; CAP],
+    /// Number of nodes in use, including the root
+    used: usize,
+    /// Number of nodes ever handed out, released nodes are reused first
+    high: usize,
+    /// Head of the list of released nodes, linked through their first slot
+    free: Option<usize>,
+    len: usize,
+}
+
+impl<K, V, const CAP: usize> FixedHamt<K, V, CAP> {
+    const EMPTY: Node<K, V> =
+        [Slot::Empty, Slot::Empty, Slot::Empty, Slot::Empty];
+
+    /// Creates a new empty map
+    ///
+    /// # Panics
+    ///
+    /// Panics if `CAP` is zero, since the root takes one node.
+    pub fn new() -> Self {
+        assert!(CAP > 0, "capacity must be non-zero");
+        FixedHamt {
+            nodes: [Self::EMPTY; CAP],
+            used: 1,
+            high: 1,
+            free: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the map holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of nodes the map can hold, including the root
+    pub fn capacity(&self) -> usize {
+        CAP
+    }
+
+    /// Returns the number of nodes in use, including the root
+    pub fn nodes(&self) -> usize {
+        self.used
+    }
+
+    fn alloc(&mut self) -> usize {
+        self.used += 1;
+        match self.free {
+            Some(node) => {
+                self.free =
+                    match mem::replace(&mut self.nodes[node][0], Slot::Empty) {
+                        Slot::Node(next) => Some(next),
+                        _ => None,
+                    };
+                node
+            }
+            None => {
+                self.high += 1;
+                self.high - 1
+            }
+        }
+    }
+
+    fn release(&mut self, node: usize) {
+        self.used -= 1;
+        self.nodes[node][0] = match self.free {
+            Some(next) => Slot::Node(next),
+            None => Slot::Empty,
+        };
+        self.free = Some(node);
+    }
+
+    /// Takes the only leaf out of a node holding nothing else
+    fn collapse(&mut self, node: usize) -> Option<Slot<K, V>> {
+        let mut leaf = None;
+        for (i, slot) in self.nodes[node].iter().enumerate() {
+            match slot {
+                Slot::Empty => (),
+                Slot::Leaf(..) if leaf.is_none() => leaf = Some(i),
+                _ => return None,
+            }
+        }
+        leaf.map(|i| mem::replace(&mut self.nodes[node][i], Slot::Empty))
+    }
+}
+
+impl<K, V, const CAP: usize> FixedHamt<K, V, CAP>
+where
+    K: Eq + Hash,
+{
+    /// Inserts a key-value pair, returning the previous value under `key`
+    ///
+    /// Fails, handing the pair back, if storing it takes more nodes than
+    /// are left.
+    pub fn insert(&mut self, key: K, val: V) -> Result<Option<V>, (K, V)> {
+        let digest = SeaHash::digest(&key);
+        let mut node = 0;
+        let mut depth = 0;
+
+        loop {
+            let ofs = slot::<SeaHash>(digest, depth);
+            let old_digest = match &mut self.nodes[node][ofs] {
+                Slot::Empty => {
+                    self.nodes[node][ofs] = Slot::Leaf(key, val);
+                    self.len += 1;
+                    return Ok(None);
+                }
+                Slot::Leaf(old_key, old_val) if *old_key == key => {
+                    return Ok(Some(mem::replace(old_val, val)));
+                }
+                Slot::Leaf(old_key, _) => SeaHash::digest(old_key),
+                Slot::Node(child) => {
+                    node = *child;
+                    depth += 1;
+                    continue;
+                }
+            };
+
+            // one node per level until the paths of both keys diverge
+            let available = CAP - self.used;
+            let mut needed = 1;
+            while slot::<SeaHash>(digest, depth + needed)
+                == slot::<SeaHash>(old_digest, depth + needed)
+            {
+                if needed > available {
+                    break;
+                }
+                needed += 1;
+            }
+            if needed > available {
+                return Err((key, val));
+            }
+
+            let old = mem::replace(&mut self.nodes[node][ofs], Slot::Empty);
+            let (mut parent, mut parent_ofs) = (node, ofs);
+            for d in depth + 1..=depth + needed {
+                let child = self.alloc();
+                self.nodes[parent][parent_ofs] = Slot::Node(child);
+                parent = child;
+                parent_ofs = slot::<SeaHash>(digest, d);
+            }
+
+            let d = depth + needed;
+            self.nodes[parent][slot::<SeaHash>(old_digest, d)] = old;
+            self.nodes[parent][parent_ofs] = Slot::Leaf(key, val);
+            self.len += 1;
+            return Ok(None);
+        }
+    }
+
+    /// Returns a reference to the value stored under `key`
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let digest = SeaHash::digest(key);
+        let mut node = 0;
+        let mut depth = 0;
+
+        loop {
+            match &self.nodes[node][slot::<SeaHash>(digest, depth)] {
+                Slot::Leaf(k, v) if k == key => return Some(v),
+                Slot::Node(child) => {
+                    node = *child;
+                    depth += 1;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let digest = SeaHash::digest(key);
+        let mut node = 0;
+        let mut depth = 0;
+
+        loop {
+            let ofs = slot::<SeaHash>(digest, depth);
+            match &self.nodes[node][ofs] {
+                Slot::Leaf(k, _) if k == key => break,
+                Slot::Node(child) => {
+                    node = *child;
+                    depth += 1;
+                }
+                _ => return None,
+            }
+        }
+
+        match &mut self.nodes[node][slot::<SeaHash>(digest, depth)] {
+            Slot::Leaf(_, v) => Some(v),
+            _ => unreachable!("Leaf found by the walk above"),
+        }
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    ///
+    /// Nodes left with a single leaf are collapsed and released for reuse.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let digest = SeaHash::digest(key);
+        self._remove(0, key, digest, 0)
+    }
+
+    fn _remove(
+        &mut self,
+        node: usize,
+        key: &K,
+        digest: u64,
+        depth: usize,
+    ) -> Option<V> {
+        let ofs = slot::<SeaHash>(digest, depth);
+        match &self.nodes[node][ofs] {
+            Slot::Leaf(k, _) if k == key => {
+                match mem::replace(&mut self.nodes[node][ofs], Slot::Empty) {
+                    Slot::Leaf(_, val) => {
+                        self.len -= 1;
+                        Some(val)
+                    }
+                    _ => unreachable!("Match above guarantees a `Slot::Leaf`"),
+                }
+            }
+            Slot::Node(child) => {
+                let child = *child;
+                let result = self._remove(child, key, digest, depth + 1);
+                if let Some(leaf) = self.collapse(child) {
+                    self.nodes[node][ofs] = leaf;
+                    self.release(child);
+                }
+                result
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<K, V, const CAP: usize> Default for FixedHamt<K, V, CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
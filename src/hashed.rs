@@ -0,0 +1,170 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Keys carrying their digest, to hash them once for many operations
+
+use core::fmt;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    Annotation, ArchivedCompound, Compound, MaybeArchived, StoreRef,
+};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::{Hamt, KeyHasher, KvPair, PathWalker, Value};
+
+/// A key along with its digest, see [`Hamt::hash_key`]
+///
+/// Operations taking a hashed key skip hashing it, which pays off when the
+/// same key is looked up, then changed, as in validate-then-apply flows.
+pub struct HashedKey<K, H>
+where
+    H: KeyHasher,
+{
+    key: K,
+    digest: H::Digest,
+}
+
+impl<K, H> HashedKey<K, H>
+where
+    H: KeyHasher,
+{
+    /// Returns the key
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the digest of the key
+    pub fn digest(&self) -> H::Digest {
+        self.digest
+    }
+
+    /// Consumes the hashed key, returning the key
+    pub fn into_key(self) -> K {
+        self.key
+    }
+}
+
+// Implemented by hand, since the key hasher is not required to be `Clone`
+impl<K, H> Clone for HashedKey<K, H>
+where
+    K: Clone,
+    H: KeyHasher,
+{
+    fn clone(&self) -> Self {
+        HashedKey {
+            key: self.key.clone(),
+            digest: self.digest,
+        }
+    }
+}
+
+impl<K, H> fmt::Debug for HashedKey<K, H>
+where
+    K: fmt::Debug,
+    H: KeyHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashedKey")
+            .field("key", &self.key)
+            .field("digest", &self.digest)
+            .finish()
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Self: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Self, A, I>
+        + Deserialize<Self, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Hashes `key` with the key hasher of the map, for use with the
+    /// `_hashed` operations
+    pub fn hash_key(&self, key: &K) -> HashedKey<K, H> {
+        HashedKey {
+            key: key.clone(),
+            digest: H::digest(key),
+        }
+    }
+
+    /// Returns the value stored under a hashed key, like
+    /// [`Lookup::get`](crate::Lookup::get)
+    pub fn get_hashed(
+        &self,
+        key: &HashedKey<K, H>,
+    ) -> Option<Value<'_, K, V, A, I, H>> {
+        self.walk(PathWalker::<H>::from_digest(key.digest))
+            .filter(|b| match b.leaf() {
+                MaybeArchived::Memory(kv) => kv.key == key.key,
+                MaybeArchived::Archived(kv) => kv.key == key.key,
+            })
+            .map(Value::new)
+    }
+
+    /// Inserts a value under a hashed key, like [`insert`](Self::insert)
+    pub fn insert_hashed(&mut self, key: HashedKey<K, H>, val: V) -> Option<V> {
+        self._insert(key.key, val, key.digest, 0)
+    }
+
+    /// Removes a hashed key, like [`remove`](Self::remove)
+    pub fn remove_hashed(&mut self, key: &HashedKey<K, H>) -> Option<V> {
+        self._remove(&key.key, key.digest, 0)
+    }
+
+    /// Returns the value stored at `digest` whose key satisfies `eq`
+    ///
+    /// Meant for callers keeping digests of their own, `eq` lets them match
+    /// keys without constructing one.
+    pub fn raw_get<F>(
+        &self,
+        digest: H::Digest,
+        eq: F,
+    ) -> Option<Value<'_, K, V, A, I, H>>
+    where
+        F: Fn(&K) -> bool,
+    {
+        self.walk(PathWalker::<H>::from_digest(digest))
+            .filter(|b| match b.leaf() {
+                MaybeArchived::Memory(kv) => eq(&kv.key),
+                MaybeArchived::Archived(kv) => eq(&kv.key),
+            })
+            .map(Value::new)
+    }
+
+    /// Inserts `pair` at `digest`, returning the value it replaces
+    ///
+    /// This only saves hashing the key of `pair`, it does not lift the
+    /// `Hash` bound on keys: the keys of existing entries are hashed again
+    /// when their paths split. The digest must be the one the key hasher of
+    /// the map yields for the key, any other leaves the entry out of reach
+    /// of [`get`](crate::Lookup::get) and [`remove`](Self::remove).
+    ///
+    /// # Panics
+    ///
+    /// With debug assertions, panics if `digest` is not the digest of the
+    /// key.
+    pub fn raw_insert(
+        &mut self,
+        digest: H::Digest,
+        pair: KvPair<K, V>,
+    ) -> Option<V> {
+        debug_assert_eq!(digest, H::digest(&pair.key));
+        self._insert(pair.key, pair.val, digest, 0)
+    }
+}
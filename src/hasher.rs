@@ -0,0 +1,139 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Hash functions deriving the paths of keys
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::hash::Hash;
+#[cfg(feature = "seahash")]
+use core::hash::Hasher;
+use core::marker::PhantomData;
+
+#[cfg(feature = "seahash")]
+use seahash::SeaHasher;
+
+/// A digest from which the path of a key is derived
+///
+/// The slot at each depth is taken from the hash of the digest offset by
+/// the depth, so wider digests lower the odds of two keys sharing a path.
+pub trait Digest: Copy + Eq + Hash + Debug {
+    /// Returns the digest offset by `depth`, hashed to select the slot at
+    /// that depth
+    fn at_depth(self, depth: usize) -> Self;
+
+    /// Returns the slot, out of four, selected by the digest
+    fn slot(self) -> usize;
+}
+
+impl Digest for u64 {
+    fn at_depth(self, depth: usize) -> Self {
+        self.wrapping_add(depth as u64)
+    }
+
+    fn slot(self) -> usize {
+        (self % 4) as usize
+    }
+}
+
+impl Digest for u128 {
+    fn at_depth(self, depth: usize) -> Self {
+        self.wrapping_add(depth as u128)
+    }
+
+    fn slot(self) -> usize {
+        (self % 4) as usize
+    }
+}
+
+impl Digest for [u8; 32] {
+    /// Offsets the first eight bytes, read as a little endian integer
+    fn at_depth(mut self, depth: usize) -> Self {
+        let mut low = [0; 8];
+        low.copy_from_slice(&self[..8]);
+        let low = u64::from_le_bytes(low).wrapping_add(depth as u64);
+        self[..8].copy_from_slice(&low.to_le_bytes());
+        self
+    }
+
+    fn slot(self) -> usize {
+        (self[0] % 4) as usize
+    }
+}
+
+/// The hash function from which the path of each key in a map is derived
+///
+/// Maps using different key hashers lay out the same entries differently,
+/// use [`Hamt::migrate_hasher`](crate::Hamt::migrate_hasher) to move the
+/// contents of a map from one to another.
+pub trait KeyHasher {
+    /// The type of the digests
+    type Digest: Digest;
+
+    /// Returns the digest of `t`
+    fn digest<T>(t: &T) -> Self::Digest
+    where
+        T: Hash + ?Sized;
+
+    /// Returns the digests of a batch of keys, in order
+    ///
+    /// Hashers able to hash several keys at once override this, it is used
+    /// by the batch operations of maps.
+    fn digest_many<T>(ts: &[&T]) -> Vec<Self::Digest>
+    where
+        T: Hash + ?Sized,
+    {
+        ts.iter().map(|t| Self::digest(*t)).collect()
+    }
+}
+
+/// The default key hasher, using SeaHash
+///
+/// Without the `seahash` feature this is only a placeholder for the default
+/// hasher parameter, and maps must name a key hasher of their own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeaHash;
+
+#[cfg(feature = "seahash")]
+impl KeyHasher for SeaHash {
+    type Digest = u64;
+
+    fn digest<T>(t: &T) -> u64
+    where
+        T: Hash + ?Sized,
+    {
+        let mut hasher = SeaHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A tag separating the paths of keys in maps of different domains
+pub trait DomainTag {
+    /// The tag, mixed into the digest of every key
+    const TAG: &'static [u8];
+}
+
+/// A key hasher mixing the tag of the domain `D` into every digest of `H`
+///
+/// Identical keys in maps of different domains take unrelated paths, so the
+/// commitments of one map cannot be mistaken for those of another.
+pub struct Tagged<D, H = SeaHash>(PhantomData<(D, H)>);
+
+impl<D, H> KeyHasher for Tagged<D, H>
+where
+    D: DomainTag,
+    H: KeyHasher,
+{
+    type Digest = H::Digest;
+
+    fn digest<T>(t: &T) -> H::Digest
+    where
+        T: Hash + ?Sized,
+    {
+        H::digest(&(D::TAG, t))
+    }
+}
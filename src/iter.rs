@@ -0,0 +1,159 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Iteration in an order defined by the digests of the keys
+
+use alloc::vec::Vec;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, MaybeArchived, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::node::{EntryRef, NodeRef};
+use crate::{Bucket, Hamt, KvPair};
+
+/// A node being iterated over, with the offset of its next slot and the
+/// offset to stop at
+type Frame<'a, K, V, A, I, H> = (NodeRef<'a, K, V, A, I, H>, usize, usize);
+
+/// Iterator over the entries of a map in digest path order, see
+/// [`Hamt::iter_by_digest`]
+pub struct DigestOrder<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    stack: Vec<Frame<'a, K, V, A, I, H>>,
+}
+
+impl<'a, K, V, A, I, H> DigestOrder<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    pub(crate) fn new(root: &'a Hamt<K, V, A, I, H>) -> Self {
+        DigestOrder {
+            stack: vec![(NodeRef::Memory(root), 0, 4)],
+        }
+    }
+
+    /// Iterates over the entries under the slot `ofs` of the root only
+    fn bucket(root: &'a Hamt<K, V, A, I, H>, ofs: usize) -> Self {
+        DigestOrder {
+            stack: vec![(NodeRef::Memory(root), ofs, ofs + 1)],
+        }
+    }
+}
+
+impl<'a, K, V, A, I, H> Iterator for DigestOrder<'a, K, V, A, I, H>
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    type Item = (&'a K, MaybeArchived<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, ofs, end) = self.stack.last_mut()?;
+            if *ofs == *end {
+                self.stack.pop();
+                continue;
+            }
+
+            let entry = node.entry(*ofs);
+            *ofs += 1;
+
+            match entry {
+                EntryRef::Empty => (),
+                EntryRef::Leaf(key, val) => return Some((key, val)),
+                EntryRef::Node(node) => self.stack.push((node, 0, 4)),
+            }
+        }
+    }
+}
+
+/// Iterator over the entries of a map grouped by the slot of the root they
+/// lie under, see [`Hamt::iter_buckets`]
+pub struct Buckets<'a, K, V, A, I, H> {
+    root: &'a Hamt<K, V, A, I, H>,
+    ofs: usize,
+}
+
+impl<'a, K, V, A, I, H> Buckets<'a, K, V, A, I, H> {
+    pub(crate) fn new(root: &'a Hamt<K, V, A, I, H>) -> Self {
+        Buckets { root, ofs: 0 }
+    }
+}
+
+impl<'a, K, V, A, I, H> Iterator for Buckets<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    type Item = DigestOrder<'a, K, V, A, I, H>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ofs == 4 {
+            return None;
+        }
+        let bucket = DigestOrder::bucket(self.root, self.ofs);
+        self.ofs += 1;
+        Some(bucket)
+    }
+}
+
+/// An owned node of a snapshot, with the offset of its next slot
+type OwnedFrame<K, V, A, I, H> = (Hamt<K, V, A, I, H>, usize);
+
+/// Iterator over the entries of a snapshot of a map, in digest path order,
+/// see [`Hamt::iter_snapshot`]
+pub struct Snapshot<K, V, A, I, H> {
+    stack: Vec<OwnedFrame<K, V, A, I, H>>,
+}
+
+impl<K, V, A, I, H> Snapshot<K, V, A, I, H> {
+    pub(crate) fn new(root: Hamt<K, V, A, I, H>) -> Self {
+        Snapshot {
+            stack: vec![(root, 0)],
+        }
+    }
+}
+
+impl<K, V, A, I, H> Iterator for Snapshot<K, V, A, I, H>
+where
+    K: Archive + Clone,
+    V: Archive + Clone,
+    A: Annotation<KvPair<K, V>>,
+    I: Clone,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, ofs) = self.stack.last_mut()?;
+            if *ofs == 4 {
+                self.stack.pop();
+                continue;
+            }
+
+            // the nodes of the snapshot are owned, so buckets are moved out
+            // rather than cloned, shared nodes being copied on unlinking
+            let bucket = node.0[*ofs].take();
+            *ofs += 1;
+
+            match bucket {
+                Bucket::Empty => (),
+                Bucket::Leaf(leaf) => return Some(leaf.into_parts()),
+                Bucket::Node(link) => self.stack.push((link.unlink(), 0)),
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::MaybeArchived;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::Archive;
+
+use crate::node::{EntryRef, NodeRef};
+use crate::{slot, Hamt, KeyHasher};
+
+/// One side of a level being joined
+enum Side<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    Absent,
+    Node(NodeRef<'a, K, V, A, I, H>),
+    /// A leaf facing a node on the other side, only present in `slot`
+    Leaf {
+        slot: usize,
+        leaf: Option<(&'a K, MaybeArchived<'a, V>)>,
+    },
+}
+
+impl<'a, K, V, A, I, H> Side<'a, K, V, A, I, H>
+where
+    K: Archive<Archived = K> + Hash,
+    V: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    fn new(entry: EntryRef<'a, K, V, A, I, H>, depth: usize) -> Self {
+        match entry {
+            EntryRef::Empty => Side::Absent,
+            EntryRef::Node(node) => Side::Node(node),
+            EntryRef::Leaf(key, val) => Side::Leaf {
+                slot: slot::<H>(H::digest(key), depth),
+                leaf: Some((key, val)),
+            },
+        }
+    }
+
+    fn entry(&mut self, ofs: usize) -> EntryRef<'a, K, V, A, I, H> {
+        match self {
+            Side::Absent => EntryRef::Empty,
+            Side::Node(node) => node.entry(ofs),
+            Side::Leaf { slot, leaf } if *slot == ofs => match leaf.take() {
+                Some((key, val)) => EntryRef::Leaf(key, val),
+                None => EntryRef::Empty,
+            },
+            Side::Leaf { .. } => EntryRef::Empty,
+        }
+    }
+}
+
+struct Level<'a, K, V, A, V2, A2, I, H>
+where
+    K: Archive,
+    V: Archive,
+    V2: Archive,
+{
+    depth: usize,
+    ofs: usize,
+    left: Side<'a, K, V, A, I, H>,
+    right: Side<'a, K, V2, A2, I, H>,
+}
+
+/// An item yielded by [`Join`]: a key together with its value in each map
+pub type JoinItem<'a, K, V, V2> = (
+    &'a K,
+    Option<MaybeArchived<'a, V>>,
+    Option<MaybeArchived<'a, V2>>,
+);
+
+/// Iterator over the union of the keys of two maps, see [`Hamt::join`]
+pub struct Join<'a, K, V, A, V2, A2, I, H>
+where
+    K: Archive,
+    V: Archive,
+    V2: Archive,
+{
+    levels: Vec<Level<'a, K, V, A, V2, A2, I, H>>,
+    pending: Option<JoinItem<'a, K, V, V2>>,
+    /// Skip keys only present in the right map
+    left_only: bool,
+    /// Skip subtrees shared by both maps
+    skip_shared: bool,
+}
+
+impl<'a, K, V, A, V2, A2, I, H> Join<'a, K, V, A, V2, A2, I, H>
+where
+    K: Archive,
+    V: Archive,
+    V2: Archive,
+{
+    pub(crate) fn new(
+        left: &'a Hamt<K, V, A, I, H>,
+        right: &'a Hamt<K, V2, A2, I, H>,
+        left_only: bool,
+        skip_shared: bool,
+    ) -> Self {
+        Join {
+            levels: vec![Level {
+                depth: 0,
+                ofs: 0,
+                left: Side::Node(NodeRef::Memory(left)),
+                right: Side::Node(NodeRef::Memory(right)),
+            }],
+            pending: None,
+            left_only,
+            skip_shared,
+        }
+    }
+}
+
+impl<'a, K, V, A, V2, A2, I, H> Iterator for Join<'a, K, V, A, V2, A2, I, H>
+where
+    K: Archive<Archived = K> + Eq + Hash,
+    V: Archive,
+    V2: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+    <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    type Item = JoinItem<'a, K, V, V2>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.take() {
+                return Some(item);
+            }
+
+            let level = self.levels.last_mut()?;
+            if level.ofs == 4 {
+                self.levels.pop();
+                continue;
+            }
+
+            let ofs = level.ofs;
+            let depth = level.depth + 1;
+            level.ofs += 1;
+
+            match (level.left.entry(ofs), level.right.entry(ofs)) {
+                (EntryRef::Empty, EntryRef::Empty) => (),
+                (EntryRef::Leaf(k, v), EntryRef::Empty) => {
+                    return Some((k, Some(v), None))
+                }
+                (EntryRef::Empty, EntryRef::Leaf(k, v)) => {
+                    if !self.left_only {
+                        return Some((k, None, Some(v)));
+                    }
+                }
+                (EntryRef::Leaf(k, v), EntryRef::Leaf(k2, v2)) => {
+                    if k == k2 {
+                        return Some((k, Some(v), Some(v2)));
+                    }
+                    if !self.left_only {
+                        self.pending = Some((k2, None, Some(v2)));
+                    }
+                    return Some((k, Some(v), None));
+                }
+                (EntryRef::Empty, _) if self.left_only => (),
+                (EntryRef::Node(l), EntryRef::Node(r))
+                    if self.skip_shared && l.addr() == r.addr() => {}
+                (left, right) => self.levels.push(Level {
+                    depth,
+                    ofs: 0,
+                    left: Side::new(left, depth),
+                    right: Side::new(right, depth),
+                }),
+            }
+        }
+    }
+}
+
+/// An item yielded by [`LeftJoin`]: a key and value of the left map together
+/// with the value of the right map, if present
+pub type LeftJoinItem<'a, K, V, V2> =
+    (&'a K, MaybeArchived<'a, V>, Option<MaybeArchived<'a, V2>>);
+
+/// Iterator over the entries of a map enriched with the matching values of
+/// another, see [`Hamt::left_join`]
+pub struct LeftJoin<'a, K, V, A, V2, A2, I, H>(Join<'a, K, V, A, V2, A2, I, H>)
+where
+    K: Archive,
+    V: Archive,
+    V2: Archive;
+
+impl<'a, K, V, A, V2, A2, I, H> LeftJoin<'a, K, V, A, V2, A2, I, H>
+where
+    K: Archive,
+    V: Archive,
+    V2: Archive,
+{
+    pub(crate) fn new(
+        left: &'a Hamt<K, V, A, I, H>,
+        right: &'a Hamt<K, V2, A2, I, H>,
+    ) -> Self {
+        LeftJoin(Join::new(left, right, true, false))
+    }
+}
+
+impl<'a, K, V, A, V2, A2, I, H> Iterator for LeftJoin<'a, K, V, A, V2, A2, I, H>
+where
+    K: Archive<Archived = K> + Eq + Hash,
+    V: Archive,
+    V2: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+    <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    type Item = LeftJoinItem<'a, K, V, V2>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let (key, Some(val), right) = self.0.next()? {
+                return Some((key, val, right));
+            }
+        }
+    }
+}
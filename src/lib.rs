@@ -7,20 +7,141 @@
 #![no_std]
 
 //! Hamt
-use core::borrow::BorrowMut;
-use core::hash::{Hash, Hasher};
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod cached;
+mod cas;
+mod checked;
+mod commitment;
+mod delta;
+mod diff;
+#[cfg(feature = "seahash")]
+mod distinct;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "seahash")]
+mod fixed;
+mod hashed;
+mod hasher;
+mod iter;
+mod join;
+mod limits;
+#[cfg(feature = "seahash")]
+mod lru;
+mod node;
+mod overlay;
+mod profile;
+mod projection;
+mod proof;
+#[cfg(feature = "seahash")]
+mod queue;
+mod raw;
+mod registry;
+mod schema;
+mod scratch;
+#[cfg(feature = "seahash")]
+mod sequence;
+#[cfg(feature = "seahash")]
+mod small;
+#[cfg(feature = "seahash")]
+mod stamp;
+#[cfg(feature = "seahash")]
+mod static_map;
+mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "seahash")]
+mod tombstone;
+#[cfg(feature = "seahash")]
+mod ttl;
+mod value;
+#[cfg(feature = "seahash")]
+mod versioned;
+#[cfg(feature = "audit")]
+mod wal;
+mod walkers;
+mod watch;
+
+pub use cached::CachedHamt;
+pub use cas::Conflict;
+pub use checked::{
+    CheckedAdd, CheckedCardinality, CheckedNth, CheckedSub, CheckedSum,
+    Overflow,
+};
+pub use commitment::{Commitment, CommitmentEncode, CommitmentHasher};
+pub use delta::DeltaAnnotation;
+pub use diff::{Change, Changelist};
+#[cfg(feature = "seahash")]
+pub use distinct::DistinctValues;
+#[cfg(feature = "seahash")]
+pub use fixed::FixedHamt;
+pub use hashed::HashedKey;
+pub use hasher::{Digest, DomainTag, KeyHasher, SeaHash, Tagged};
+pub use iter::{Buckets, DigestOrder, Snapshot};
+pub use join::{Join, JoinItem, LeftJoin, LeftJoinItem};
+pub use limits::{LimitError, Limits};
+#[cfg(feature = "seahash")]
+pub use lru::LruHamt;
+pub use overlay::OverlayHamt;
+pub use profile::{LevelProfile, NodeInfo, Nodes, Profile};
+pub use projection::{FindMaxOf, FindMinOf, MaxOf, MinOf, Projection};
+pub use proof::{
+    verify_many, verify_update, BatchProof, DecodeError, ProofSlot, Update,
+    UpdateProof, VerifyError, MAX_PROOF_DEPTH, PROOF_FORMAT_VERSION,
+};
+#[cfg(feature = "seahash")]
+pub use queue::HamtPriorityQueue;
+pub use registry::{PersistError, RootRegistry};
+pub use schema::{ArchivedUpgradable, Migrate, Upgradable};
+pub use scratch::LookupScratch;
+#[cfg(feature = "seahash")]
+pub use sequence::{InsertionOrder, SequencedHamt};
+#[cfg(feature = "seahash")]
+pub use small::SmallHamt;
+#[cfg(feature = "seahash")]
+pub use stamp::{ArchivedStamped, Stamp, Stamped};
+#[cfg(feature = "seahash")]
+pub use static_map::{StaticHamt, StaticHamtBuilder, StaticNode, StaticSlot};
+pub use telemetry::{CountingStore, StoreStats, Telemetry};
+#[cfg(feature = "seahash")]
+pub use tombstone::TombstoneHamt;
+#[cfg(feature = "seahash")]
+pub use ttl::TtlHamt;
+pub use value::{Value, ValueGuard, ValueMut};
+#[cfg(feature = "seahash")]
+pub use versioned::{Version, VersionedHamt, Versions};
+#[cfg(feature = "audit")]
+pub use wal::{LoggedHamt, Mutation, WalSink};
+pub use walkers::{First, Last};
+pub use watch::{Subscriber, SubscriptionId, Watch, WatchedHamt};
+
+use core::borrow::{Borrow, BorrowMut};
+use core::hash::Hash;
+#[cfg(feature = "seahash")]
+use core::hash::Hasher;
+use core::marker::PhantomData;
 use core::mem;
+use core::ops::RangeBounds;
+
+use alloc::vec::{self, Vec};
 
 use bytecheck::CheckBytes;
 use microkelvin::{
-    Annotation, ArchivedChild, ArchivedCompound, Child, ChildMut, Compound,
-    Discriminant, Keyed, Link, MappedBranch, MappedBranchMut, MaybeArchived,
-    Step, StoreProvider, StoreRef, StoreSerializer, Stored, Walkable, Walker,
+    Annotation, ArchivedChild, ArchivedCompound, Branch, Cardinality, Child,
+    ChildMut, Compound, Discriminant, FindMaxKey, Keyed, Link, MaxKey,
+    MaybeArchived, Nth, Step, StoreProvider, StoreRef, StoreSerializer, Stored,
+    Walkable, Walker,
 };
 use rkyv::validation::validators::DefaultValidator;
-use rkyv::{Archive, Deserialize, Serialize};
+use rkyv::{Archive, Deserialize, Infallible, Serialize};
+#[cfg(feature = "seahash")]
 use seahash::SeaHasher;
 
+use node::{EntryRef, NodeRef};
+
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
 #[archive_attr(derive(CheckBytes))]
 pub struct KvPair<K, V> {
@@ -29,6 +150,16 @@ pub struct KvPair<K, V> {
 }
 
 impl<K, V> KvPair<K, V> {
+    /// Creates a new key-value pair
+    pub fn new(key: K, val: V) -> Self {
+        KvPair { key, val }
+    }
+
+    /// Returns a reference to the key
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
     pub fn value(&self) -> &V {
         &self.val
     }
@@ -36,6 +167,22 @@ impl<K, V> KvPair<K, V> {
     pub fn value_mut(&mut self) -> &mut V {
         &mut self.val
     }
+
+    /// Consumes the pair, returning the key and the value
+    pub fn into_parts(self) -> (K, V) {
+        (self.key, self.val)
+    }
+
+    /// Transforms the value of the pair, keeping its key
+    pub fn map_val<W, F>(self, f: F) -> KvPair<K, W>
+    where
+        F: FnOnce(V) -> W,
+    {
+        KvPair {
+            key: self.key,
+            val: f(self.val),
+        }
+    }
 }
 
 impl<K, V> ArchivedKvPair<K, V>
@@ -64,7 +211,7 @@ where
     }
 }
 
-#[derive(Clone, Serialize, Archive, Deserialize)]
+#[derive(Serialize, Archive, Deserialize)]
 #[archive_attr(derive(CheckBytes))]
 #[archive(bound(serialize = "
   K: Archive + Serialize<StoreSerializer<I>>,
@@ -78,17 +225,49 @@ where
   A: Clone + Annotation<KvPair<K, V>>,
   I: Clone,
   __D: StoreProvider<I>,"))]
-pub enum Bucket<K, V, A, I> {
+pub enum Bucket<K, V, A, I, H = SeaHash> {
     Empty,
     Leaf(KvPair<K, V>),
-    Node(#[omit_bounds] Link<Hamt<K, V, A, I>, A, I>),
+    Node(#[omit_bounds] Link<Hamt<K, V, A, I, H>, A, I>),
 }
 
-#[derive(Clone, Archive, Serialize, Deserialize)]
+#[derive(Archive, Serialize, Deserialize)]
 #[archive_attr(derive(CheckBytes))]
-pub struct Hamt<K, V, A, I>([Bucket<K, V, A, I>; 4]);
+pub struct Hamt<K, V, A, I, H = SeaHash>(
+    [Bucket<K, V, A, I, H>; 4],
+    PhantomData<H>,
+);
 
-impl<K, V, A, I> Compound<A, I> for Hamt<K, V, A, I>
+// Implemented by hand, since the key hasher is not required to be `Clone`
+impl<K, V, A, I, H> Clone for Bucket<K, V, A, I, H>
+where
+    K: Clone,
+    V: Clone,
+    A: Clone,
+    I: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Bucket::Empty => Bucket::Empty,
+            Bucket::Leaf(kv) => Bucket::Leaf(kv.clone()),
+            Bucket::Node(link) => Bucket::Node(link.clone()),
+        }
+    }
+}
+
+impl<K, V, A, I, H> Clone for Hamt<K, V, A, I, H>
+where
+    K: Clone,
+    V: Clone,
+    A: Clone,
+    I: Clone,
+{
+    fn clone(&self) -> Self {
+        Hamt(self.0.clone(), PhantomData)
+    }
+}
+
+impl<K, V, A, I, H> Compound<A, I> for Hamt<K, V, A, I, H>
 where
     K: Archive,
     V: Archive,
@@ -96,7 +275,7 @@ where
 {
     type Leaf = KvPair<K, V>;
 
-    fn child(&self, ofs: usize) -> Child<Self, A, I> {
+    fn child(&self, ofs: usize) -> Child<'_, Self, A, I> {
         match self.0.get(ofs) {
             Some(Bucket::Empty) => Child::Empty,
             Some(Bucket::Leaf(ref kv)) => Child::Leaf(kv),
@@ -105,7 +284,7 @@ where
         }
     }
 
-    fn child_mut(&mut self, ofs: usize) -> ChildMut<Self, A, I> {
+    fn child_mut(&mut self, ofs: usize) -> ChildMut<'_, Self, A, I> {
         match self.0.get_mut(ofs) {
             Some(Bucket::Empty) => ChildMut::Empty,
             Some(Bucket::Leaf(ref mut kv)) => ChildMut::Leaf(kv),
@@ -115,14 +294,17 @@ where
     }
 }
 
-impl<K, V, A, I> ArchivedCompound<Hamt<K, V, A, I>, A, I>
-    for ArchivedHamt<K, V, A, I>
+impl<K, V, A, I, H> ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+    for ArchivedHamt<K, V, A, I, H>
 where
     K: Archive,
     V: Archive,
     A: Annotation<KvPair<K, V>>,
 {
-    fn child(&self, ofs: usize) -> ArchivedChild<Hamt<K, V, A, I>, A, I> {
+    fn child(
+        &self,
+        ofs: usize,
+    ) -> ArchivedChild<'_, Hamt<K, V, A, I, H>, A, I> {
         match self.0.get(ofs) {
             Some(ArchivedBucket::Leaf(l)) => ArchivedChild::Leaf(l),
             Some(ArchivedBucket::Node(n)) => ArchivedChild::Link(n),
@@ -132,7 +314,7 @@ where
     }
 }
 
-impl<K, V, A, I> Bucket<K, V, A, I>
+impl<K, V, A, I, H> Bucket<K, V, A, I, H>
 where
     A: Annotation<KvPair<K, V>>,
 {
@@ -141,7 +323,8 @@ where
     }
 }
 
-impl<K, V, A, I> Default for Bucket<K, V, A, I>
+#[allow(clippy::derivable_impls)]
+impl<K, V, A, I, H> Default for Bucket<K, V, A, I, H>
 where
     A: Annotation<KvPair<K, V>>,
 {
@@ -150,21 +333,54 @@ where
     }
 }
 
-impl<K, V, A, I> Default for Hamt<K, V, A, I>
+impl<K, V, A, I, H> Default for Hamt<K, V, A, I, H>
 where
     A: Annotation<KvPair<K, V>>,
 {
     fn default() -> Self {
-        Hamt(Default::default())
+        Hamt(Default::default(), PhantomData)
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H> {
+    /// Turns a node into the bucket representing it in its parent
+    ///
+    /// Empty nodes become empty buckets and nodes holding a single leaf are
+    /// collapsed into it.
+    pub fn into_bucket(self) -> Bucket<K, V, A, I, H> {
+        let mut leaves = 0;
+        let mut nodes = 0;
+        for bucket in self.0.iter() {
+            match bucket {
+                Bucket::Empty => (),
+                Bucket::Leaf(_) => leaves += 1,
+                Bucket::Node(_) => nodes += 1,
+            }
+        }
+        match (leaves, nodes) {
+            (0, 0) => Bucket::Empty,
+            (1, 0) => {
+                for bucket in self.0 {
+                    if let Bucket::Leaf(kv) = bucket {
+                        return Bucket::Leaf(kv);
+                    }
+                }
+                unreachable!("Counted exactly one `Bucket::Leaf` above")
+            }
+            _ => Bucket::Node(Link::new(self)),
+        }
     }
 }
 
 #[inline(always)]
-fn slot(from: u64, depth: usize) -> usize {
-    let derived = hash(&(from + depth as u64));
-    (derived % 4) as usize
+fn slot<H>(from: H::Digest, depth: usize) -> usize
+where
+    H: KeyHasher,
+{
+    H::digest(&from.at_depth(depth)).slot()
 }
 
+#[cfg(feature = "seahash")]
 #[inline(always)]
 fn hash<T>(t: &T) -> u64
 where
@@ -175,27 +391,64 @@ where
     hasher.finish()
 }
 
+/// Errors that can occur when moving a value between keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyError {
+    /// The key to move the value from is not present
+    NotFound,
+    /// The key to move the value to is already present
+    Occupied,
+}
+
+/// Errors that can occur when grafting a subtree between maps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraftError {
+    /// The source map holds no node at the given path
+    NoSourceNode,
+    /// The target map holds no node along the given path
+    NoTargetPath,
+}
+
 /// A walker
-pub struct PathWalker {
-    digest: u64,
+pub struct PathWalker<H>
+where
+    H: KeyHasher,
+{
+    digest: H::Digest,
     depth: usize,
+    _marker: PhantomData<H>,
 }
 
-impl PathWalker {
-    fn new(digest: u64) -> Self {
-        PathWalker { digest, depth: 0 }
+impl<H> PathWalker<H>
+where
+    H: KeyHasher,
+{
+    fn new<K>(key: &K) -> Self
+    where
+        K: Hash + ?Sized,
+    {
+        Self::from_digest(H::digest(key))
+    }
+
+    fn from_digest(digest: H::Digest) -> Self {
+        PathWalker {
+            digest,
+            depth: 0,
+            _marker: PhantomData,
+        }
     }
 }
 
-impl<'a, C, A, I> Walker<C, A, I> for PathWalker
+impl<C, A, I, H> Walker<C, A, I> for PathWalker<H>
 where
+    H: KeyHasher,
     C: Compound<A, I> + Archive,
     C::Archived: ArchivedCompound<C, A, I>,
     C::Leaf: Archive,
     A: Annotation<C::Leaf>,
 {
     fn walk(&mut self, level: impl Walkable<C, A, I>) -> Step {
-        let slot = slot(self.digest, self.depth);
+        let slot = slot::<H>(self.digest, self.depth);
         self.depth += 1;
         match level.probe(slot) {
             Discriminant::Leaf(_) | Discriminant::Annotation(_) => {
@@ -206,7 +459,7 @@ where
     }
 }
 
-impl<K, V, A, I> Hamt<K, V, A, I>
+impl<K, V, A, I, H> Hamt<K, V, A, I, H>
 where
     K: Archive<Archived = K>
         + Clone
@@ -217,10 +470,11 @@ where
     V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
     A: Annotation<KvPair<K, V>>,
     Self: Archive,
-    <Hamt<K, V, A, I> as Archive>::Archived: ArchivedCompound<Self, A, I>
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Self, A, I>
         + Deserialize<Self, StoreRef<I>>
         + for<'a> CheckBytes<DefaultValidator<'a>>,
     I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
 {
     /// Creates a new empty Hamt
     pub fn new() -> Self {
@@ -228,7 +482,7 @@ where
     }
 
     pub fn insert(&mut self, key: K, val: V) -> Option<V> {
-        let digest = hash(&key);
+        let digest = H::digest(&key);
         self._insert(key, val, digest, 0)
     }
 
@@ -236,10 +490,10 @@ where
         &mut self,
         key: K,
         val: V,
-        digest: u64,
+        digest: H::Digest,
         depth: usize,
     ) -> Option<V> {
-        let slot = slot(digest, depth);
+        let slot = slot::<H>(digest, depth);
         let bucket = &mut self.0[slot];
 
         match bucket.take() {
@@ -256,7 +510,7 @@ where
                     Some(old_val)
                 } else {
                     let mut new_node = Hamt::new();
-                    let old_digest = hash(&old_key);
+                    let old_digest = H::digest(&old_key);
 
                     new_node._insert(key, val, digest, depth + 1);
                     new_node._insert(old_key, old_val, old_digest, depth + 1);
@@ -274,6 +528,146 @@ where
         }
     }
 
+    /// Moves every entry of `other` into this map, replacing the values of
+    /// keys present in both
+    ///
+    /// Subtrees of `other` falling under empty slots of this map are grafted
+    /// whole, so only the leaves meeting existing entries are rehashed.
+    pub fn absorb(&mut self, other: Self) {
+        self._absorb(other, 0)
+    }
+
+    fn _absorb(&mut self, other: Self, depth: usize) {
+        for (bucket, theirs) in self.0.iter_mut().zip(other.0) {
+            match (bucket.take(), theirs) {
+                (ours, Bucket::Empty) => *bucket = ours,
+                (Bucket::Empty, theirs) => *bucket = theirs,
+                (Bucket::Node(mut node), Bucket::Node(theirs)) => {
+                    node.inner_mut()._absorb(theirs.unlink(), depth + 1);
+                    *bucket = Bucket::Node(node);
+                }
+                (Bucket::Node(mut node), Bucket::Leaf(KvPair { key, val })) => {
+                    let digest = H::digest(&key);
+                    node.inner_mut()._insert(key, val, digest, depth + 1);
+                    *bucket = Bucket::Node(node);
+                }
+                (Bucket::Leaf(KvPair { key, val }), Bucket::Node(mut node)) => {
+                    // our leaf goes into their subtree, without overwriting
+                    // their value for the same key
+                    let digest = H::digest(&key);
+                    let node_mut = node.inner_mut();
+                    if let Some(theirs) =
+                        node_mut._insert(key.clone(), val, digest, depth + 1)
+                    {
+                        node_mut._insert(key, theirs, digest, depth + 1);
+                    }
+                    *bucket = Bucket::Node(node);
+                }
+                (Bucket::Leaf(ours), Bucket::Leaf(theirs)) => {
+                    if ours.key == theirs.key {
+                        *bucket = Bucket::Leaf(theirs);
+                    } else {
+                        let mut node = Hamt::new();
+                        for KvPair { key, val } in [ours, theirs] {
+                            let digest = H::digest(&key);
+                            node._insert(key, val, digest, depth + 1);
+                        }
+                        *bucket = Bucket::Node(Link::new(node));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds `delta` to the value stored under `key`, inserting `delta` if
+    /// the key is missing, and returns the resulting value
+    ///
+    /// The map is left untouched if the addition overflows.
+    pub fn increment(&mut self, key: &K, delta: V) -> Result<V, Overflow>
+    where
+        V: CheckedAdd,
+    {
+        let digest = H::digest(key);
+        self._upsert(key, digest, 0, |old| match old {
+            Some(old) => old.checked_add(&delta).ok_or(Overflow),
+            None => Ok(delta),
+        })
+    }
+
+    /// Adds `delta` to the value stored under `key`, inserting `delta` if
+    /// the key is missing
+    ///
+    /// The map is left untouched if the addition overflows.
+    pub fn checked_add_assign(
+        &mut self,
+        key: &K,
+        delta: V,
+    ) -> Result<(), Overflow>
+    where
+        V: CheckedAdd,
+    {
+        self.increment(key, delta).map(|_| ())
+    }
+
+    /// Replaces the value stored under `key` by the one computed from it by
+    /// `f`, returning the previous value
+    ///
+    /// The entry is left untouched if `f` fails, and the error is returned.
+    /// Returns `Ok(None)` without calling `f` if the key is missing.
+    pub fn update_checked<E, F>(
+        &mut self,
+        key: &K,
+        f: F,
+    ) -> Result<Option<V>, E>
+    where
+        F: FnOnce(&V) -> Result<V, E>,
+    {
+        match self.get_mut(key) {
+            Some(mut value) => {
+                let val = f(value.leaf_mut())?;
+                Ok(Some(mem::replace(value.leaf_mut(), val)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the value computed by `f` from the current value under `key`,
+    /// if any, in a single walk, returning a copy of it
+    fn _upsert<E, F>(
+        &mut self,
+        key: &K,
+        digest: H::Digest,
+        depth: usize,
+        f: F,
+    ) -> Result<V, E>
+    where
+        F: FnOnce(Option<&V>) -> Result<V, E>,
+    {
+        let slot = slot::<H>(digest, depth);
+        let bucket = &mut self.0[slot];
+
+        match bucket {
+            Bucket::Empty => {
+                let val = f(None)?;
+                *bucket = Bucket::Leaf(KvPair::new(key.clone(), val.clone()));
+                Ok(val)
+            }
+            Bucket::Leaf(leaf) if leaf.key == *key => {
+                let val = f(Some(&leaf.val))?;
+                leaf.val = val.clone();
+                Ok(val)
+            }
+            Bucket::Leaf(_) => {
+                let val = f(None)?;
+                self._insert(key.clone(), val.clone(), digest, depth);
+                Ok(val)
+            }
+            Bucket::Node(node) => {
+                node.inner_mut()._upsert(key, digest, depth + 1, f)
+            }
+        }
+    }
+
     /// Collapse node into a leaf if singleton
     fn collapse(&mut self) -> Option<(K, V)> {
         match &mut self.0 {
@@ -294,14 +688,17 @@ where
     }
 
     pub fn remove(&mut self, key: &K) -> Option<V> {
-        let mut hasher = SeaHasher::new();
-        key.hash(&mut hasher);
-        let digest = hasher.finish();
+        let digest = H::digest(key);
         self._remove(key, digest, 0)
     }
 
-    fn _remove(&mut self, key: &K, digest: u64, depth: usize) -> Option<V> {
-        let slot = slot(digest, depth);
+    fn _remove(
+        &mut self,
+        key: &K,
+        digest: H::Digest,
+        depth: usize,
+    ) -> Option<V> {
+        let slot = slot::<H>(digest, depth);
         let bucket = &mut self.0[slot];
 
         match bucket.take() {
@@ -313,6 +710,10 @@ where
                 if *key == old_key {
                     Some(old_val)
                 } else {
+                    *bucket = Bucket::Leaf(KvPair {
+                        key: old_key,
+                        val: old_val,
+                    });
                     None
                 }
             }
@@ -324,7 +725,6 @@ where
                 if let Some((key, val)) = node.collapse() {
                     *bucket = Bucket::Leaf(KvPair { key, val });
                 } else {
-                    drop(node);
                     *bucket = Bucket::Node(link);
                 }
                 result
@@ -332,26 +732,934 @@ where
         }
     }
 
-    pub fn get_mut(
+    /// Removes the entries stored under each of `keys`, returning their
+    /// values in order
+    ///
+    /// Removals are applied in digest order, and nodes are collapsed once
+    /// all the keys below them are removed rather than once per key. Keys
+    /// given more than once yield their value the first time only.
+    pub fn multi_remove(&mut self, keys: &[&K]) -> Vec<Option<V>> {
+        let digests = H::digest_many(keys);
+        let mut indices: Vec<usize> = (0..keys.len()).collect();
+        let mut removed: Vec<_> = keys.iter().map(|_| None).collect();
+
+        self._multi_remove(keys, &digests, &mut indices, 0, &mut removed);
+        removed
+    }
+
+    fn _multi_remove(
+        &mut self,
+        keys: &[&K],
+        digests: &[H::Digest],
+        indices: &mut [usize],
+        depth: usize,
+        removed: &mut [Option<V>],
+    ) {
+        indices.sort_by_cached_key(|i| slot::<H>(digests[*i], depth));
+
+        let mut rest = indices;
+        while let Some(first) = rest.first() {
+            let ofs = slot::<H>(digests[*first], depth);
+            let len = rest
+                .iter()
+                .take_while(|i| slot::<H>(digests[**i], depth) == ofs)
+                .count();
+            let (group, tail) = mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+
+            let bucket = &mut self.0[ofs];
+            match bucket.take() {
+                Bucket::Empty => (),
+                Bucket::Leaf(KvPair { key, val }) => {
+                    match group.iter().find(|i| *keys[**i] == key) {
+                        Some(i) => removed[*i] = Some(val),
+                        None => *bucket = Bucket::Leaf(KvPair { key, val }),
+                    }
+                }
+                Bucket::Node(mut link) => {
+                    let node = link.inner_mut();
+                    node._multi_remove(
+                        keys,
+                        digests,
+                        group,
+                        depth + 1,
+                        removed,
+                    );
+                    // since we moved the bucket with `take()`, we need to put
+                    // it back, unless every leaf below it was removed.
+                    if let Some((key, val)) = node.collapse() {
+                        *bucket = Bucket::Leaf(KvPair { key, val });
+                    } else if node.0.iter().any(|b| !matches!(b, Bucket::Empty))
+                    {
+                        *bucket = Bucket::Node(link);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes and returns an arbitrary key-value pair from the map
+    ///
+    /// The first leaf found in slot order is returned, nodes left with a
+    /// single leaf are collapsed on the way back up.
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        for bucket in self.0.iter_mut() {
+            match bucket.take() {
+                Bucket::Empty => (),
+                Bucket::Leaf(KvPair { key, val }) => return Some((key, val)),
+                Bucket::Node(mut link) => {
+                    let node = link.inner_mut();
+                    let result = node.pop();
+                    // since we moved the bucket with `take()`, we need to put
+                    // it back.
+                    if let Some((key, val)) = node.collapse() {
+                        *bucket = Bucket::Leaf(KvPair { key, val });
+                    } else {
+                        *bucket = Bucket::Node(link);
+                    }
+                    return result;
+                }
+            }
+        }
+        None
+    }
+
+    /// Moves the value stored under `old` to the key `new`
+    ///
+    /// The map is left untouched if `old` is missing or if `new` is already
+    /// present.
+    pub fn replace_key(&mut self, old: &K, new: K) -> Result<(), KeyError> {
+        if !self.contains(old) {
+            return Err(KeyError::NotFound);
+        }
+        if *old == new {
+            return Ok(());
+        }
+        if self.contains(&new) {
+            return Err(KeyError::Occupied);
+        }
+        let val = self.remove(old).expect("presence checked above");
+        self.insert(new, val);
+        Ok(())
+    }
+
+    /// Transforms every value in the map, producing a new map
+    ///
+    /// The leaves keep the slots they occupy, so the shape of the tree is
+    /// preserved and no key is rehashed.
+    pub fn map_values<W, A2, F>(self, mut f: F) -> Hamt<K, W, A2, I, H>
+    where
+        F: FnMut(&K, V) -> W,
+    {
+        self._map_values(&mut f)
+    }
+
+    fn _map_values<W, A2, F>(self, f: &mut F) -> Hamt<K, W, A2, I, H>
+    where
+        F: FnMut(&K, V) -> W,
+    {
+        Hamt(
+            self.0.map(|bucket| match bucket {
+                Bucket::Empty => Bucket::Empty,
+                Bucket::Leaf(KvPair { key, val }) => {
+                    let val = f(&key, val);
+                    Bucket::Leaf(KvPair { key, val })
+                }
+                Bucket::Node(link) => {
+                    Bucket::Node(Link::new(link.unlink()._map_values(f)))
+                }
+            }),
+            PhantomData,
+        )
+    }
+
+    /// Filters and transforms the values in the map, producing a new map
+    ///
+    /// Entries for which `f` returns `None` are dropped, surviving leaves
+    /// keep their slots and nodes left empty or with a single leaf are
+    /// collapsed.
+    pub fn filter_map<W, A2, F>(self, mut f: F) -> Hamt<K, W, A2, I, H>
+    where
+        F: FnMut(&K, V) -> Option<W>,
+    {
+        self._filter_map(&mut f)
+    }
+
+    fn _filter_map<W, A2, F>(self, f: &mut F) -> Hamt<K, W, A2, I, H>
+    where
+        F: FnMut(&K, V) -> Option<W>,
+    {
+        Hamt(
+            self.0.map(|bucket| match bucket {
+                Bucket::Empty => Bucket::Empty,
+                Bucket::Leaf(KvPair { key, val }) => match f(&key, val) {
+                    Some(val) => Bucket::Leaf(KvPair { key, val }),
+                    None => Bucket::Empty,
+                },
+                Bucket::Node(link) => {
+                    link.unlink()._filter_map(f).into_bucket()
+                }
+            }),
+            PhantomData,
+        )
+    }
+
+    /// Removes the entries for which `f` returns true, returning them in a
+    /// new map
+    ///
+    /// Split off leaves keep their slots in the new map, so no key is
+    /// rehashed, and nodes left empty or with a single leaf on either side
+    /// are collapsed.
+    pub fn split_off<F>(&mut self, mut f: F) -> Self
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self._split_off(&mut f)
+    }
+
+    fn _split_off<F>(&mut self, f: &mut F) -> Self
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut split = Self::default();
+        for (bucket, split) in self.0.iter_mut().zip(split.0.iter_mut()) {
+            match bucket.take() {
+                Bucket::Empty => (),
+                Bucket::Leaf(leaf) => {
+                    if f(&leaf.key, &leaf.val) {
+                        *split = Bucket::Leaf(leaf);
+                    } else {
+                        *bucket = Bucket::Leaf(leaf);
+                    }
+                }
+                Bucket::Node(link) => {
+                    let mut node = link.unlink();
+                    *split = node._split_off(f).into_bucket();
+                    *bucket = node.into_bucket();
+                }
+            }
+        }
+        split
+    }
+
+    /// Removes the entries for which `f` returns true, returning an iterator
+    /// over the removed pairs
+    ///
+    /// Unlike its counterpart on the standard collections, the entries are
+    /// removed eagerly, before the iterator is returned, and nodes left
+    /// empty or with a single leaf are collapsed.
+    pub fn extract_if<F>(&mut self, mut f: F) -> vec::IntoIter<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut extracted = Vec::new();
+        self._extract_if(&mut f, &mut extracted);
+        extracted.into_iter()
+    }
+
+    fn _extract_if<F>(&mut self, f: &mut F, extracted: &mut Vec<(K, V)>)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        for bucket in self.0.iter_mut() {
+            match bucket.take() {
+                Bucket::Empty => (),
+                Bucket::Leaf(leaf) => {
+                    if f(&leaf.key, &leaf.val) {
+                        extracted.push(leaf.into_parts());
+                    } else {
+                        *bucket = Bucket::Leaf(leaf);
+                    }
+                }
+                Bucket::Node(link) => {
+                    let mut node = link.unlink();
+                    node._extract_if(f, extracted);
+                    *bucket = node.into_bucket();
+                }
+            }
+        }
+    }
+
+    /// Partitions the map into the entries for which `f` returns true and
+    /// those for which it returns false
+    ///
+    /// Each entry is classified once and keeps its slot, see
+    /// [`split_off`](Self::split_off).
+    pub fn partition<F>(mut self, mut f: F) -> (Self, Self)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let rejected = self.split_off(|key, val| !f(key, val));
+        (self, rejected)
+    }
+
+    /// Removes the entries whose key digests fall in `range`, returning them
+    /// in a new map
+    ///
+    /// Since the slot at each depth is derived by rehashing the digest, a
+    /// digest range does not map onto subtrees and every leaf is visited.
+    pub fn split_off_digests<R>(&mut self, range: R) -> Self
+    where
+        R: RangeBounds<H::Digest>,
+        H::Digest: PartialOrd,
+    {
+        self.split_off(|key, _| range.contains(&H::digest(key)))
+    }
+
+    /// Iterates over the union of the keys of `self` and `other`
+    ///
+    /// Both trees are walked side by side over the shared digest space, each
+    /// key is yielded once together with its value in either map.
+    pub fn join<'a, V2, A2>(
+        &'a self,
+        other: &'a Hamt<K, V2, A2, I, H>,
+    ) -> Join<'a, K, V, A, V2, A2, I, H>
+    where
+        V2: Archive,
+        <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        Join::new(self, other, false, false)
+    }
+
+    /// Iterates over the entries of `self` together with the value stored
+    /// under the same key in `other`, if any
+    ///
+    /// Lookups into `other` follow the walk over `self`, so subtrees of
+    /// `other` with no counterpart in `self` are never visited.
+    pub fn left_join<'a, V2, A2>(
+        &'a self,
+        other: &'a Hamt<K, V2, A2, I, H>,
+    ) -> LeftJoin<'a, K, V, A, V2, A2, I, H>
+    where
+        V2: Archive,
+        <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        LeftJoin::new(self, other)
+    }
+
+    /// Returns a map holding only the entries whose path starts with `path`
+    ///
+    /// A path is the sequence of slots, from the root down, derived from the
+    /// digest of a key. The subtree found at `path` is shared with `self`
+    /// rather than copied, and the returned map can be stored on its own.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any slot in `path` is larger than 3.
+    pub fn subtree(&self, path: &[usize]) -> Self {
+        if path.is_empty() {
+            return self.clone();
+        }
+        match self._subtree(path, 0) {
+            Bucket::Empty => Self::new(),
+            Bucket::Leaf(KvPair { key, val }) => {
+                let mut map = Self::new();
+                map.insert(key, val);
+                map
+            }
+            Bucket::Node(link) => {
+                let mut bucket = Bucket::Node(link);
+                for slot in path.iter().skip(1).rev() {
+                    let mut node = Self::new();
+                    node.0[*slot] = bucket;
+                    bucket = Bucket::Node(Link::new(node));
+                }
+                let mut root = Self::new();
+                root.0[path[0]] = bucket;
+                root
+            }
+        }
+    }
+
+    fn _subtree(&self, path: &[usize], depth: usize) -> Bucket<K, V, A, I, H> {
+        match &self.0[path[depth]] {
+            Bucket::Empty => Bucket::Empty,
+            Bucket::Leaf(kv) => {
+                let digest = H::digest(&kv.key);
+                let inside = path
+                    .iter()
+                    .enumerate()
+                    .skip(depth + 1)
+                    .all(|(d, s)| self::slot::<H>(digest, d) == *s);
+                if inside {
+                    Bucket::Leaf(kv.clone())
+                } else {
+                    Bucket::Empty
+                }
+            }
+            Bucket::Node(link) if depth + 1 == path.len() => {
+                Bucket::Node(link.clone())
+            }
+            Bucket::Node(link) => {
+                link.clone().unlink()._subtree(path, depth + 1)
+            }
+        }
+    }
+
+    /// Collapses nodes left empty or holding a single leaf anywhere in the
+    /// tree, returning the number of nodes dropped
+    ///
+    /// Removals collapse the nodes along their own path, this pass catches
+    /// degenerate nodes left behind otherwise, such as in maps written by
+    /// other implementations. Chains of nodes with a single child node are
+    /// kept, since the slots of the leaves below depend on their depth.
+    ///
+    /// Compact subtrees are checked in place, without being loaded into
+    /// memory or having their annotations invalidated.
+    pub fn compact(&mut self) -> usize {
+        let mut dirty = [false; 4];
+        let root = NodeRef::Memory(&*self);
+        for (slot, dirty) in dirty.iter_mut().enumerate() {
+            if let EntryRef::Node(child) = root.entry(slot) {
+                *dirty = child.is_degenerate() || !child.is_compact();
+            }
+        }
+
+        let mut dropped = 0;
+        for slot in (0..4).filter(|slot| dirty[*slot]) {
+            if let Bucket::Node(link) = self.0[slot].take() {
+                let mut node = link.unlink();
+                dropped += node.compact();
+                self.0[slot] = node.into_bucket();
+                if !matches!(self.0[slot], Bucket::Node(_)) {
+                    dropped += 1;
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Inserts a key-value pair into the map stored at `root`, returning the
+    /// root of the updated map together with the previous value under `key`
+    ///
+    /// Only the nodes along the path of `key` are read and written back,
+    /// the updated map shares all other nodes with the one at `root`, which
+    /// is left untouched.
+    pub fn insert_stored(
+        root: &Stored<Self, I>,
+        key: K,
+        val: V,
+    ) -> (Stored<Self, I>, Option<V>)
+    where
+        Self: Serialize<StoreSerializer<I>>,
+    {
+        let mut map = Self::load_root(root);
+        let old = map.insert(key, val);
+        (root.store().store(&map), old)
+    }
+
+    /// Removes the entry stored under `key` from the map stored at `root`,
+    /// returning the root of the updated map together with the value
+    ///
+    /// Copies on write as [`insert_stored`](Self::insert_stored) does.
+    pub fn remove_stored(
+        root: &Stored<Self, I>,
+        key: &K,
+    ) -> (Stored<Self, I>, Option<V>)
+    where
+        Self: Serialize<StoreSerializer<I>>,
+    {
+        let mut map = Self::load_root(root);
+        let old = map.remove(key);
+        (root.store().store(&map), old)
+    }
+
+    /// Replaces the subtree at `path` by the one at the same path in the
+    /// map stored at `source`, referring to its node by identifier
+    ///
+    /// The path is a sequence of slots starting from the root, as returned
+    /// by [`Value::path`] without its last slot. No node is read or copied,
+    /// so both maps must live in the same store when this one is persisted.
+    /// Both maps must also use the same key hasher, for the grafted keys to
+    /// belong at `path`. The entries previously under `path` are dropped.
+    pub fn graft(
+        &mut self,
+        path: &[usize],
+        source: &Stored<Self, I>,
+    ) -> Result<(), GraftError> {
+        let (last, parents) = match path.split_last() {
+            Some(split) => split,
+            None => {
+                *self = Self::load_root(source);
+                return Ok(());
+            }
+        };
+
+        let store = source.store();
+        let mut node = source.inner();
+        for slot in parents {
+            match node.child(*slot) {
+                ArchivedChild::Link(link) => node = store.get(link.ident()),
+                _ => return Err(GraftError::NoSourceNode),
+            }
+        }
+        let bucket = match node.child(*last) {
+            ArchivedChild::Link(link) => Bucket::Node(Link::Stored {
+                stored: Stored::new(store.clone(), link.ident().clone()),
+                a: link.annotation().clone(),
+            }),
+            _ => return Err(GraftError::NoSourceNode),
+        };
+
+        let mut target = self;
+        for slot in parents {
+            match target.0.get_mut(*slot) {
+                Some(Bucket::Node(link)) => target = link.inner_mut(),
+                _ => return Err(GraftError::NoTargetPath),
+            }
+        }
+        match target.0.get_mut(*last) {
+            Some(slot) => *slot = bucket,
+            None => return Err(GraftError::NoTargetPath),
+        }
+        Ok(())
+    }
+
+    /// Deserializes the root node only, its children being left in the
+    /// store until reached
+    fn load_root(root: &Stored<Self, I>) -> Self {
+        root.inner()
+            .deserialize(&mut root.store().clone())
+            .expect("deserializing from a store is infallible")
+    }
+
+    /// Reconstructs the map from its own leaves into freshly allocated nodes
+    ///
+    /// The shape of the tree only depends on its contents, but nodes of a
+    /// long lived map end up scattered in memory and shared with stale
+    /// copies, or spread over a store. The rebuilt map is entirely in memory,
+    /// shares no node with any other map, and is written out contiguously
+    /// when persisted.
+    pub fn rebuild(self) -> Self {
+        let mut map = Self::new();
+        self._drain_into(&mut map);
+        map
+    }
+
+    /// Moves the contents of the map into a new map, deriving the paths of
+    /// the keys with the key hasher `H2`
+    ///
+    /// Every key is rehashed and reinserted, so this costs as much as
+    /// building the new map from scratch.
+    pub fn migrate_hasher<H2>(self) -> Hamt<K, V, A, I, H2>
+    where
+        H2: KeyHasher,
+        Hamt<K, V, A, I, H2>: Archive,
+        <Hamt<K, V, A, I, H2> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H2>, A, I>
+            + Deserialize<Hamt<K, V, A, I, H2>, StoreRef<I>>
+            + for<'a> CheckBytes<DefaultValidator<'a>>,
+    {
+        let mut map = Hamt::new();
+        self._drain_into(&mut map);
+        map
+    }
+
+    fn _drain_into<H2>(self, map: &mut Hamt<K, V, A, I, H2>)
+    where
+        H2: KeyHasher,
+        Hamt<K, V, A, I, H2>: Archive,
+        <Hamt<K, V, A, I, H2> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H2>, A, I>
+            + Deserialize<Hamt<K, V, A, I, H2>, StoreRef<I>>
+            + for<'a> CheckBytes<DefaultValidator<'a>>,
+    {
+        for bucket in self.0 {
+            match bucket {
+                Bucket::Empty => (),
+                Bucket::Leaf(KvPair { key, val }) => {
+                    map.insert(key, val);
+                }
+                Bucket::Node(link) => link.unlink()._drain_into(map),
+            }
+        }
+    }
+
+    /// Iterates over the entries of the map in digest path order
+    ///
+    /// Entries are ordered by the sequence of slots derived from the digest
+    /// of their key, and the shape of the tree only depends on its contents.
+    /// The order is therefore identical for equal maps regardless of the
+    /// history of insertions and removals, and across platforms as long as
+    /// the `Hash` implementation of the keys is platform independent.
+    ///
+    /// This order is part of the stability contract of the crate and will
+    /// only change together with the hashing scheme.
+    pub fn iter_by_digest(&self) -> DigestOrder<'_, K, V, A, I, H> {
+        DigestOrder::new(self)
+    }
+
+    /// Iterates over the entries of the map in four groups, one per slot of
+    /// the root, each in digest path order
+    ///
+    /// The groups are disjoint and always yielded in slot order, empty ones
+    /// included, so the map can be processed in a fixed number of
+    /// independent chunks. Chained together they yield the entries in the
+    /// order of [`iter_by_digest`](Self::iter_by_digest).
+    pub fn iter_buckets(&self) -> Buckets<'_, K, V, A, I, H> {
+        Buckets::new(self)
+    }
+
+    /// Iterates over the entries of a snapshot of the map, in digest path
+    /// order, yielding copies of them
+    ///
+    /// Taking the snapshot copies the root node only, the others being
+    /// shared until either side changes them, so the map can be mutated
+    /// while the iteration proceeds without affecting it.
+    pub fn iter_snapshot(&self) -> Snapshot<K, V, A, I, H> {
+        Snapshot::new(self.clone())
+    }
+
+    /// Returns the occupancy of the nodes at each depth and the distribution
+    /// of leaf depths
+    ///
+    /// Useful to detect degenerate shapes caused by badly distributed keys.
+    /// Subtrees held in a store are read but not loaded into memory, and
+    /// shared subtrees are counted once per occurrence.
+    pub fn profile(&self) -> Profile {
+        Profile::new(self)
+    }
+
+    /// Iterates over the nodes of the map, parents first, with their path,
+    /// size, and identifier if they are in a store
+    ///
+    /// To analyze a stored map, deserialize its root and iterate over it:
+    /// the nodes below are read from the store without being loaded.
+    pub fn nodes(&self) -> Nodes<'_, K, V, A, I, H> {
+        Nodes::new(self)
+    }
+
+    /// Returns the changes turning `self` into `newer`
+    ///
+    /// Subtrees shared between both maps are not descended into, so the
+    /// cost is proportional to the parts of the maps that differ.
+    pub fn changes(&self, newer: &Self) -> Changelist<K, V>
+    where
+        V: PartialEq,
+        V::Archived: Deserialize<V, Infallible>,
+    {
+        Changelist::new(self, newer)
+    }
+
+    /// Returns true if every key of `self` is also a key of `other`
+    ///
+    /// Subtrees shared between both maps are not descended into.
+    pub fn is_subset<V2, A2>(&self, other: &Hamt<K, V2, A2, I, H>) -> bool
+    where
+        V2: Archive,
+        <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        Join::new(self, other, true, true).all(|(_, _, r)| r.is_some())
+    }
+
+    /// Returns true if every key of `other` is also a key of `self`
+    ///
+    /// Subtrees shared between both maps are not descended into.
+    pub fn is_superset<V2, A2>(&self, other: &Hamt<K, V2, A2, I, H>) -> bool
+    where
+        V2: Archive,
+        <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        Join::new(other, self, true, true).all(|(_, _, r)| r.is_some())
+    }
+
+    /// Returns true if `self` and `other` have no keys in common
+    pub fn is_disjoint<V2, A2>(&self, other: &Hamt<K, V2, A2, I, H>) -> bool
+    where
+        V2: Archive,
+        <Hamt<K, V2, A2, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        Join::new(self, other, true, false).all(|(_, _, r)| r.is_none())
+    }
+
+    /// Recomputes the annotations invalidated by previous mutations,
+    /// returning the annotation of the whole map
+    ///
+    /// Mutations do not propagate annotations, they only invalidate the ones
+    /// cached along the paths they touch. These are recomputed lazily on
+    /// the next read, or all at once by calling this method, so a burst of
+    /// mutations pays for every node only once.
+    pub fn recompute(&self) -> A {
+        A::from_node(self)
+    }
+
+    /// Returns the component `T` of the annotation of the whole map, such
+    /// as its [`Cardinality`] or the [`MaxOf`] a [`Projection`]
+    pub fn aggregate<T>(&self) -> T
+    where
+        A: Borrow<T>,
+        T: Clone,
+    {
+        self.recompute().borrow().clone()
+    }
+
+    /// Returns the component `T` of the annotation of the subtree at `path`,
+    /// or `None` if no entry lies under `path`
+    ///
+    /// Paths are as taken by [`subtree`](Self::subtree), the empty path
+    /// designating the whole map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any slot in `path` is larger than 3.
+    pub fn aggregate_of_subtree<T>(&self, path: &[usize]) -> Option<T>
+    where
+        A: Borrow<T>,
+        T: Clone,
+    {
+        if path.is_empty() {
+            return Some(self.aggregate());
+        }
+        let annotation = match self._subtree(path, 0) {
+            Bucket::Empty => return None,
+            Bucket::Leaf(kv) => A::from_leaf(&kv),
+            Bucket::Node(link) => link.annotation().clone(),
+        };
+        Some(annotation.borrow().clone())
+    }
+
+    /// Compares two maps by their root commitments
+    ///
+    /// Link annotations are cached, so for maps sharing most of their nodes
+    /// only the commitments of the nodes that differ are computed.
+    pub fn eq_fast<C>(&self, other: &Self) -> bool
+    where
+        A: Borrow<Commitment<C>>,
+    {
+        self.commitment() == other.commitment()
+    }
+
+    /// Returns the root commitment of the map
+    ///
+    /// Mutations only drop the commitments cached along the paths they
+    /// touch, so after a change this rehashes the ancestors of the changed
+    /// entries, reusing the cached commitments of every sibling subtree.
+    pub fn commitment<C>(&self) -> Commitment<C>
+    where
+        A: Borrow<Commitment<C>>,
+    {
+        *A::from_node(self).borrow()
+    }
+
+    /// Returns the number of entries in the map, or an error if the count
+    /// overflowed
+    pub fn checked_len(&self) -> Result<u64, Overflow>
+    where
+        A: Borrow<CheckedCardinality>,
+    {
+        A::from_node(self).borrow().get()
+    }
+
+    /// Returns a branch to the `n`th entry of the map, or an error if the
+    /// count of entries overflowed
+    pub fn checked_nth(
+        &self,
+        n: u64,
+    ) -> Result<Option<Branch<'_, Self, A, I>>, Overflow>
+    where
+        A: Borrow<CheckedCardinality>,
+    {
+        self.checked_len()?;
+        Ok(self.walk(CheckedNth(n)))
+    }
+
+    /// Returns the sum of the amounts `T` projected from the entries of the
+    /// map, or an error if the sum overflowed
+    pub fn checked_sum<T>(&self) -> Result<T, Overflow>
+    where
+        A: Borrow<CheckedSum<T>>,
+        T: Clone,
+    {
+        A::from_node(self).borrow().get().cloned()
+    }
+
+    /// Returns the estimated number of distinct values in the map
+    #[cfg(feature = "seahash")]
+    pub fn distinct_values(&self) -> u64
+    where
+        A: Borrow<DistinctValues>,
+    {
+        A::from_node(self).borrow().estimate()
+    }
+
+    /// Returns a branch to the leaf with the largest projection `P`
+    pub fn max_of<P>(&self) -> Option<Branch<'_, Self, A, I>>
+    where
+        A: Borrow<MaxOf<P>>,
+        P: Projection<K, V> + Ord + Clone,
+    {
+        self.walk(FindMaxOf::<P>::default())
+    }
+
+    /// Removes and returns the key-value pair with the largest projection `P`
+    pub fn pop_max_of<P>(&mut self) -> Option<(K, V)>
+    where
+        A: Borrow<MaxOf<P>>,
+        P: Projection<K, V> + Ord + Clone,
+    {
+        let key = self.max_of::<P>()?.leaf().key().clone();
+        let val = self.remove(&key).expect("key found by walking the map");
+        Some((key, val))
+    }
+
+    /// Returns a branch to the leaf with the smallest projection `P`
+    pub fn min_of<P>(&self) -> Option<Branch<'_, Self, A, I>>
+    where
+        A: Borrow<MinOf<P>>,
+        P: Projection<K, V> + Ord + Clone,
+    {
+        self.walk(FindMinOf::<P>::default())
+    }
+
+    /// Returns a branch to the leaf with the largest key
+    pub fn max(&self) -> Option<Branch<'_, Self, A, I>>
+    where
+        A: Borrow<MaxKey<K>>,
+        K: Ord,
+    {
+        self.walk(FindMaxKey::default())
+    }
+
+    /// Removes and returns the key-value pair with the largest key
+    pub fn pop_max(&mut self) -> Option<(K, V)>
+    where
+        A: Borrow<MaxKey<K>>,
+        K: Ord,
+    {
+        let key = self.max()?.leaf().key().clone();
+        let val = self.remove(&key).expect("key found by walking the map");
+        Some((key, val))
+    }
+
+    /// Removes and returns the key-value pair with the smallest projection `P`
+    pub fn pop_min_of<P>(&mut self) -> Option<(K, V)>
+    where
+        A: Borrow<MinOf<P>>,
+        P: Projection<K, V> + Ord + Clone,
+    {
+        let key = self.min_of::<P>()?.leaf().key().clone();
+        let val = self.remove(&key).expect("key found by walking the map");
+        Some((key, val))
+    }
+
+    /// Returns the value stored under `key`
+    ///
+    /// Unlike [`Lookup::get`], the map is descended without building a
+    /// branch, so no memory is allocated, which suits lookups in hot loops.
+    pub fn find(&self, key: &K) -> Option<MaybeArchived<'_, V>> {
+        NodeRef::Memory(self).find(key, H::digest(key), 0)
+    }
+
+    /// Looks up the values stored under each of `keys`, in order
+    ///
+    /// Keys are hashed once and grouped by path, so that keys sharing a
+    /// path prefix walk it once.
+    pub fn multi_get(&self, keys: &[&K]) -> Vec<Option<MaybeArchived<'_, V>>> {
+        let digests = H::digest_many(keys);
+        let mut indices: Vec<usize> = (0..keys.len()).collect();
+        let mut found: Vec<_> = keys.iter().map(|_| None).collect();
+
+        NodeRef::Memory(self).find_many(
+            keys,
+            &digests,
+            &mut indices,
+            0,
+            &mut found,
+        );
+        found
+    }
+
+    /// Looks up the values stored under each of `keys`, in order, into
+    /// `found`, using the buffers of `scratch`
+    ///
+    /// Behaves as [`multi_get`](Self::multi_get), but reuses the buffers
+    /// of `scratch` and `found` across calls instead of allocating them on
+    /// every call. The previous contents of `found` are dropped.
+    pub fn multi_get_with<'a>(
+        &'a self,
+        scratch: &mut LookupScratch<H>,
+        keys: &[&K],
+        found: &mut Vec<Option<MaybeArchived<'a, V>>>,
+    ) {
+        scratch.reset(keys);
+        found.clear();
+        found.resize_with(keys.len(), || None);
+
+        NodeRef::Memory(self).find_many(
+            keys,
+            &scratch.digests,
+            &mut scratch.indices,
+            0,
+            found,
+        );
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.walk(PathWalker::<H>::new(key))
+            .map(|b| *b.leaf().key() == *key)
+            .unwrap_or(false)
+    }
+
+    /// Returns a mutable handle to the value stored under `key`
+    pub fn get_mut(&mut self, key: &K) -> Option<ValueMut<'_, K, V, A, I, H>> {
+        self.walk_mut(PathWalker::<H>::new(key))
+            .and_then(|mut b| {
+                if b.leaf_mut().key == *key {
+                    Some(b)
+                } else {
+                    None
+                }
+            })
+            .map(|branch| ValueMut::new(key.clone(), branch))
+    }
+
+    /// Returns a guard over a copy of the value stored under `key`, written
+    /// back on drop only if it was changed, see [`ValueGuard`]
+    pub fn get_guarded(
         &mut self,
         key: &K,
-    ) -> Option<MappedBranchMut<Self, A, I, V>> {
-        self.walk_mut(PathWalker::new(hash(key)))
-            .and_then(|mut b| (b.leaf_mut().key == *key).then(|| b))
-            .and_then(|branch| Some(branch.map_leaf(|kv| kv.value_mut())))
+    ) -> Option<ValueGuard<'_, K, V, A, I, H>>
+    where
+        V::Archived: Deserialize<V, Infallible>,
+    {
+        let val = self
+            .walk(PathWalker::<H>::new(key))
+            .filter(|b| b.leaf().key() == key)
+            .map(Value::new)?
+            .into_owned();
+        Some(ValueGuard::new(self, key.clone(), val, |map, key, val| {
+            if let Some(mut entry) = map.get_mut(key) {
+                mem::swap(entry.leaf_mut(), val);
+            }
+        }))
     }
 }
 
 /// Trait for looking up values in the map
-pub trait Lookup<C, K, V, A, I>
+pub trait Lookup<C, K, V, A, I, H = SeaHash>
 where
     C: Compound<A, I>,
+    K: Archive,
     V: Archive,
 {
-    fn get(&self, key: &K) -> Option<MappedBranch<C, A, I, MaybeArchived<V>>>;
+    fn get(&self, key: &K) -> Option<Value<'_, K, V, A, I, H>>;
+
+    /// Returns the `n`th value of the map, in walk order
+    fn nth(&self, n: u64) -> Option<Value<'_, K, V, A, I, H>>
+    where
+        A: Borrow<Cardinality>;
+
+    /// Walks the whole map, failing as soon as it exceeds `limits`
+    ///
+    /// Meant to be called on maps read from untrusted sources, after
+    /// validation and before handing them to other walkers.
+    fn check_limits(&self, limits: &Limits) -> Result<(), LimitError>;
 }
 
-impl<K, V, A, I> Lookup<Self, K, V, A, I> for Hamt<K, V, A, I>
+impl<K, V, A, I, H> Lookup<Self, K, V, A, I, H> for Hamt<K, V, A, I, H>
 where
     K: Archive + Hash,
     K::Archived: for<'any> CheckBytes<DefaultValidator<'any>>,
@@ -362,31 +1670,31 @@ where
     I: Archive + for<'any> CheckBytes<DefaultValidator<'any>>,
     K: Eq,
     K: Archive<Archived = K>,
+    H: KeyHasher,
 {
-    fn get(
-        &self,
-        key: &K,
-    ) -> Option<MappedBranch<Self, A, I, MaybeArchived<V>>> {
-        self.walk(PathWalker::new(hash(key)))
+    fn get(&self, key: &K) -> Option<Value<'_, K, V, A, I, H>> {
+        self.walk(PathWalker::<H>::new(key))
             .filter(|b| match b.leaf() {
                 MaybeArchived::Memory(kv) => *kv.key() == *key,
                 MaybeArchived::Archived(kv) => kv.key == *key,
             })
-            .map(|branch| {
-                branch.map_leaf::<MaybeArchived<V>>(|kv| match kv {
-                    MaybeArchived::Memory(kv) => {
-                        MaybeArchived::Memory(kv.value())
-                    }
-                    MaybeArchived::Archived(kv) => {
-                        MaybeArchived::Archived(kv.value())
-                    }
-                })
-            })
+            .map(Value::new)
+    }
+
+    fn nth(&self, n: u64) -> Option<Value<'_, K, V, A, I, H>>
+    where
+        A: Borrow<Cardinality>,
+    {
+        self.walk(Nth(n)).map(Value::new)
+    }
+
+    fn check_limits(&self, limits: &Limits) -> Result<(), LimitError> {
+        limits.check(NodeRef::Memory(self))
     }
 }
 
-impl<K, V, A, I> Lookup<Hamt<K, V, A, I>, K, V, A, I>
-    for Stored<Hamt<K, V, A, I>, I>
+impl<K, V, A, I, H> Lookup<Hamt<K, V, A, I, H>, K, V, A, I, H>
+    for Stored<Hamt<K, V, A, I, H>, I>
 where
     K: 'static + Archive + Hash,
     K::Archived: for<'any> CheckBytes<DefaultValidator<'any>>,
@@ -397,25 +1705,25 @@ where
     I: Archive + for<'any> CheckBytes<DefaultValidator<'any>>,
     K: Eq,
     K: Archive<Archived = K>,
+    H: KeyHasher,
 {
-    fn get(
-        &self,
-        key: &K,
-    ) -> Option<MappedBranch<Hamt<K, V, A, I>, A, I, MaybeArchived<V>>> {
-        self.walk(PathWalker::new(hash(key)))
+    fn get(&self, key: &K) -> Option<Value<'_, K, V, A, I, H>> {
+        self.walk(PathWalker::<H>::new(key))
             .filter(|b| match b.leaf() {
                 MaybeArchived::Memory(kv) => *kv.key() == *key,
                 MaybeArchived::Archived(kv) => kv.key == *key,
             })
-            .map(|branch| {
-                branch.map_leaf(|kv| match kv {
-                    MaybeArchived::Memory(kv) => {
-                        MaybeArchived::Memory(kv.value())
-                    }
-                    MaybeArchived::Archived(kv) => {
-                        MaybeArchived::Archived(kv.value())
-                    }
-                })
-            })
+            .map(Value::new)
+    }
+
+    fn nth(&self, n: u64) -> Option<Value<'_, K, V, A, I, H>>
+    where
+        A: Borrow<Cardinality>,
+    {
+        self.walk(Nth(n)).map(Value::new)
+    }
+
+    fn check_limits(&self, limits: &Limits) -> Result<(), LimitError> {
+        limits.check(NodeRef::Archived(self.inner(), self.store()))
     }
 }
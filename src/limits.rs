@@ -0,0 +1,66 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Bounds on the shape of maps read from untrusted sources
+
+use bytecheck::CheckBytes;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::Archive;
+
+use crate::node::{EntryRef, NodeRef};
+use crate::Hamt;
+
+/// Bounds on the shape of a map, see [`Lookup::check_limits`]
+///
+/// [`Lookup::check_limits`]: crate::Lookup::check_limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum depth of a node, the root being at depth 0
+    pub max_depth: usize,
+    /// Maximum number of nodes, including the root
+    pub max_nodes: usize,
+}
+
+/// The bound of [`Limits`] a map exceeds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    /// A node lies deeper than `max_depth`
+    Depth,
+    /// The map has more than `max_nodes` nodes
+    Nodes,
+}
+
+impl Limits {
+    pub(crate) fn check<K, V, A, I, H>(
+        &self,
+        root: NodeRef<'_, K, V, A, I, H>,
+    ) -> Result<(), LimitError>
+    where
+        K: Archive<Archived = K>,
+        V: Archive,
+        <Hamt<K, V, A, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        let mut nodes = 0;
+        let mut stack = vec![(root, 0)];
+
+        while let Some((node, depth)) = stack.pop() {
+            nodes += 1;
+            if nodes > self.max_nodes {
+                return Err(LimitError::Nodes);
+            }
+            if depth > self.max_depth {
+                return Err(LimitError::Depth);
+            }
+            for slot in 0..4 {
+                if let EntryRef::Node(child) = node.entry(slot) {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Bounded map evicting the least recently used entries
+
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{ArchivedCompound, Compound, MappedBranchMut, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::stamp::StampedMap;
+use crate::{Hamt, MinOf, PathWalker, SeaHash, Stamp, Stamped};
+
+type ValueMut<'a, K, V, I> =
+    MappedBranchMut<'a, StampedMap<K, V, I>, MinOf<Stamp>, I, V>;
+
+/// A map holding at most `capacity` entries, evicting the least recently
+/// used one when full
+///
+/// Every entry is stamped with the value of a monotone access counter, the
+/// least recently used entry is found through the [`MinOf`] annotation of
+/// the stamps.
+#[derive(Clone)]
+pub struct LruHamt<K, V, I> {
+    map: StampedMap<K, V, I>,
+    clock: u64,
+    len: usize,
+    capacity: usize,
+}
+
+impl<K, V, I> LruHamt<K, V, I>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    StampedMap<K, V, I>: Archive,
+    <StampedMap<K, V, I> as Archive>::Archived: ArchivedCompound<StampedMap<K, V, I>, MinOf<Stamp>, I>
+        + Deserialize<StampedMap<K, V, I>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates a new empty cache holding at most `capacity` entries
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        LruHamt {
+            map: Hamt::new(),
+            clock: 0,
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// Returns the number of entries in the cache
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the cache holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum number of entries in the cache
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Inserts an entry, marking it as the most recently used
+    ///
+    /// Returns the previous entry under the same key or, if the cache was
+    /// full, the evicted least recently used entry.
+    pub fn push(&mut self, key: K, val: V) -> Option<(K, V)> {
+        let stamp = self.tick();
+        match self.map.insert(key.clone(), Stamped::new(stamp, val)) {
+            Some(old) => Some((key, old.into_value())),
+            None => {
+                self.len += 1;
+                if self.len > self.capacity {
+                    self.pop_lru()
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns the value stored under `key`, marking it as the most recently
+    /// used
+    pub fn get(&mut self, key: &K) -> Option<ValueMut<'_, K, V, I>> {
+        let stamp = self.tick();
+        self.map
+            .walk_mut(PathWalker::<SeaHash>::new(key))
+            .and_then(|mut b| {
+                if b.leaf_mut().key == *key {
+                    Some(b)
+                } else {
+                    None
+                }
+            })
+            .map(|mut branch| {
+                branch.leaf_mut().val.stamp = stamp;
+                branch.map_leaf(|kv| kv.val.value_mut())
+            })
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let val = self.map.remove(key)?;
+        self.len -= 1;
+        Some(val.into_value())
+    }
+
+    /// Removes and returns the least recently used entry
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let (key, val) = self.map.pop_min_of::<Stamp>()?;
+        self.len -= 1;
+        Some((key, val.into_value()))
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &StampedMap<K, V, I> {
+        &self.map
+    }
+}
@@ -0,0 +1,214 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Uniform read access to nodes, whether in memory or in a store
+
+use core::mem;
+
+use bytecheck::CheckBytes;
+use microkelvin::{MaybeArchived, MaybeStored, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::Archive;
+
+use crate::{slot, ArchivedBucket, ArchivedHamt, Bucket, Hamt, KeyHasher};
+
+/// A reference to a node that is either in memory or archived in a store
+pub(crate) enum NodeRef<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    Memory(&'a Hamt<K, V, A, I, H>),
+    Archived(&'a ArchivedHamt<K, V, A, I, H>, &'a StoreRef<I>),
+}
+
+/// The content of a single slot of a node
+pub(crate) enum EntryRef<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    Empty,
+    Leaf(&'a K, MaybeArchived<'a, V>),
+    Node(NodeRef<'a, K, V, A, I, H>),
+}
+
+impl<'a, K, V, A, I, H> NodeRef<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    /// Returns the address of the node, identical for shared subtrees
+    pub fn addr(&self) -> *const u8 {
+        match *self {
+            NodeRef::Memory(node) => node as *const _ as *const u8,
+            NodeRef::Archived(node, _) => node as *const _ as *const u8,
+        }
+    }
+}
+
+impl<'a, K, V, A, I, H> NodeRef<'a, K, V, A, I, H>
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Returns the entry at `slot`
+    pub fn entry(&self, slot: usize) -> EntryRef<'a, K, V, A, I, H> {
+        match *self {
+            NodeRef::Memory(node) => match &node.0[slot] {
+                Bucket::Empty => EntryRef::Empty,
+                Bucket::Leaf(kv) => {
+                    EntryRef::Leaf(&kv.key, MaybeArchived::Memory(&kv.val))
+                }
+                Bucket::Node(link) => match link.inner() {
+                    MaybeStored::Memory(node) => {
+                        EntryRef::Node(NodeRef::Memory(node))
+                    }
+                    MaybeStored::Stored(stored) => EntryRef::Node(
+                        NodeRef::Archived(stored.inner(), stored.store()),
+                    ),
+                },
+            },
+            NodeRef::Archived(node, store) => match &node.0[slot] {
+                ArchivedBucket::Empty => EntryRef::Empty,
+                ArchivedBucket::Leaf(kv) => {
+                    EntryRef::Leaf(&kv.key, MaybeArchived::Archived(&kv.val))
+                }
+                ArchivedBucket::Node(link) => EntryRef::Node(
+                    NodeRef::Archived(store.get(link.ident()), store),
+                ),
+            },
+        }
+    }
+
+    /// Returns the identifier of the node linked at `slot`, if it is in a
+    /// store
+    pub fn ident(&self, slot: usize) -> Option<&'a I> {
+        match *self {
+            NodeRef::Memory(node) => match &node.0[slot] {
+                Bucket::Node(link) => match link.inner() {
+                    MaybeStored::Memory(_) => None,
+                    MaybeStored::Stored(stored) => Some(stored.ident().erase()),
+                },
+                _ => None,
+            },
+            NodeRef::Archived(node, _) => match &node.0[slot] {
+                ArchivedBucket::Node(link) => Some(link.ident().erase()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns the size in bytes of the node, in memory or in its store
+    pub fn size(&self) -> usize {
+        match self {
+            NodeRef::Memory(_) => mem::size_of::<Hamt<K, V, A, I, H>>(),
+            NodeRef::Archived(..) => {
+                mem::size_of::<ArchivedHamt<K, V, A, I, H>>()
+            }
+        }
+    }
+
+    /// Returns the value stored under `key` below this node, the root
+    /// being at depth 0
+    pub fn find(
+        &self,
+        key: &K,
+        digest: H::Digest,
+        mut depth: usize,
+    ) -> Option<MaybeArchived<'a, V>>
+    where
+        K: Eq,
+        H: KeyHasher,
+    {
+        let mut entry = self.entry(slot::<H>(digest, depth));
+        loop {
+            match entry {
+                EntryRef::Empty => return None,
+                EntryRef::Leaf(k, v) if k == key => return Some(v),
+                EntryRef::Leaf(..) => return None,
+                EntryRef::Node(node) => {
+                    depth += 1;
+                    entry = node.entry(slot::<H>(digest, depth));
+                }
+            }
+        }
+    }
+
+    /// Looks up the keys at `indices` below this node, the root being at
+    /// depth 0, storing their values in `found`
+    ///
+    /// Keys sharing a path prefix walk it once.
+    pub fn find_many(
+        &self,
+        keys: &[&K],
+        digests: &[H::Digest],
+        indices: &mut [usize],
+        depth: usize,
+        found: &mut [Option<MaybeArchived<'a, V>>],
+    ) where
+        K: Eq,
+        H: KeyHasher,
+    {
+        indices.sort_by_cached_key(|i| slot::<H>(digests[*i], depth));
+
+        let mut rest = indices;
+        while let Some(first) = rest.first() {
+            let ofs = slot::<H>(digests[*first], depth);
+            let len = rest
+                .iter()
+                .take_while(|i| slot::<H>(digests[**i], depth) == ofs)
+                .count();
+            let (group, tail) = mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+
+            match self.entry(ofs) {
+                EntryRef::Empty => (),
+                EntryRef::Leaf(key, val) => {
+                    for i in group.iter().filter(|i| *keys[**i] == *key) {
+                        found[*i] = Some(match val {
+                            MaybeArchived::Memory(val) => {
+                                MaybeArchived::Memory(val)
+                            }
+                            MaybeArchived::Archived(val) => {
+                                MaybeArchived::Archived(val)
+                            }
+                        });
+                    }
+                }
+                EntryRef::Node(node) => {
+                    node.find_many(keys, digests, group, depth + 1, found)
+                }
+            }
+        }
+    }
+
+    /// Returns true if the node is empty or holds a single leaf and no
+    /// other node
+    pub fn is_degenerate(&self) -> bool {
+        let mut leaves = 0;
+        for slot in 0..4 {
+            match self.entry(slot) {
+                EntryRef::Empty => (),
+                EntryRef::Leaf(..) => leaves += 1,
+                EntryRef::Node(_) => return false,
+            }
+        }
+        leaves < 2
+    }
+
+    /// Returns true if no node below this one is degenerate
+    pub fn is_compact(&self) -> bool {
+        (0..4).all(|slot| match self.entry(slot) {
+            EntryRef::Node(child) => {
+                !child.is_degenerate() && child.is_compact()
+            }
+            _ => true,
+        })
+    }
+}
@@ -0,0 +1,148 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! In-memory changes layered over a map kept in a store
+
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    Annotation, ArchivedCompound, Compound, Keyed, StoreRef, StoreSerializer,
+    Stored,
+};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Infallible, Serialize};
+
+use crate::{Bucket, Hamt, KeyHasher, KvPair, PathWalker, SeaHash, Value};
+
+/// The pending changes of an [`OverlayHamt`], removed keys being mapped to
+/// `None`
+pub(crate) type Overlay<K, V, I, H> = Hamt<K, Option<V>, (), I, H>;
+
+/// A map of pending changes over a map kept in a store
+///
+/// Reads look into the changes first and fall through to the stored map,
+/// which is only read along the paths of the keys looked up. Writes only
+/// touch the changes, until [`flush`](Self::flush) applies them to the
+/// stored map, writing the nodes along their paths to the store and
+/// returning the new root. [`discard`](Self::discard) drops them instead,
+/// leaving the stored map as it was.
+pub struct OverlayHamt<K, V, A, I, H = SeaHash> {
+    root: Stored<Hamt<K, V, A, I, H>, I>,
+    base: Hamt<K, V, A, I, H>,
+    overlay: Overlay<K, V, I, H>,
+}
+
+impl<K, V, A, I, H> OverlayHamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived:
+        Deserialize<V, Infallible> + for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I, H>: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+        + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    Overlay<K, V, I, H>: Archive,
+    <Overlay<K, V, I, H> as Archive>::Archived: ArchivedCompound<Overlay<K, V, I, H>, (), I>
+        + Deserialize<Overlay<K, V, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Layers an empty set of changes over the map stored at `root`
+    pub fn new(root: Stored<Hamt<K, V, A, I, H>, I>) -> Self {
+        let base = Hamt::load_root(&root);
+        OverlayHamt {
+            root,
+            base,
+            overlay: Hamt::new(),
+        }
+    }
+
+    /// Returns a copy of the value stored under `key`, taking pending
+    /// changes into account
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.pending(key) {
+            Some(val) => val,
+            None => self
+                .base
+                .walk(PathWalker::<H>::new(key))
+                .filter(|b| b.leaf().key() == key)
+                .map(Value::new)
+                .map(Value::into_owned),
+        }
+    }
+
+    /// Stores `val` under `key`, returning the value it replaces
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.get(&key);
+        self.overlay.insert(key, Some(val));
+        old
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let old = self.get(key)?;
+        self.overlay.insert(key.clone(), None);
+        Some(old)
+    }
+
+    /// Returns true if there are changes waiting to be flushed
+    pub fn is_dirty(&self) -> bool {
+        self.overlay.0.iter().any(|b| !matches!(b, Bucket::Empty))
+    }
+
+    /// Drops the pending changes
+    pub fn discard(&mut self) {
+        self.overlay = Hamt::new();
+    }
+
+    /// Returns the root of the stored map, without the pending changes
+    pub fn root(&self) -> &Stored<Hamt<K, V, A, I, H>, I> {
+        &self.root
+    }
+
+    /// Applies the pending changes to the stored map and writes it back to
+    /// the store, returning its new root
+    ///
+    /// Only the nodes along the paths of the changed keys are written, the
+    /// new root shares all other nodes with the previous one, which is left
+    /// untouched.
+    pub fn flush(&mut self) -> Stored<Hamt<K, V, A, I, H>, I>
+    where
+        Hamt<K, V, A, I, H>: Serialize<StoreSerializer<I>>,
+    {
+        if self.is_dirty() {
+            for (key, val) in self.overlay.extract_if(|_, _| true) {
+                match val {
+                    Some(val) => {
+                        self.base.insert(key, val);
+                    }
+                    None => {
+                        self.base.remove(&key);
+                    }
+                }
+            }
+            self.root = self.root.store().store(&self.base);
+            self.base = Hamt::load_root(&self.root);
+        }
+        self.root.clone()
+    }
+
+    fn pending(&self, key: &K) -> Option<Option<V>> {
+        self.overlay
+            .walk(PathWalker::<H>::new(key))
+            .filter(|b| b.leaf().key() == key)
+            .map(Value::new)
+            .map(Value::into_owned)
+    }
+}
@@ -0,0 +1,177 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Statistics on the shape of a map
+
+use alloc::vec::Vec;
+
+use bytecheck::CheckBytes;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::Archive;
+
+use crate::node::{EntryRef, NodeRef};
+use crate::Hamt;
+
+/// A node of a map, see [`Hamt::nodes`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo<'a, I> {
+    /// Depth of the node, the root being at depth 0
+    pub depth: usize,
+    /// Slots taken from the root to reach the node
+    pub path: Vec<usize>,
+    /// Identifier of the node in its store, or `None` if it is in memory
+    pub ident: Option<&'a I>,
+    /// Size of the node in bytes, in memory or in its store
+    pub size: usize,
+    /// Number of slots holding a leaf
+    pub leaves: usize,
+    /// Number of slots holding a link to a node one level deeper
+    pub links: usize,
+}
+
+/// A node waiting to be visited, with its path and identifier
+type Pending<'a, K, V, A, I, H> =
+    (NodeRef<'a, K, V, A, I, H>, Vec<usize>, Option<&'a I>);
+
+/// Iterator over the nodes of a map, parents first, see [`Hamt::nodes`]
+pub struct Nodes<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    stack: Vec<Pending<'a, K, V, A, I, H>>,
+}
+
+impl<'a, K, V, A, I, H> Nodes<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    pub(crate) fn new(root: &'a Hamt<K, V, A, I, H>) -> Self {
+        Nodes {
+            stack: vec![(NodeRef::Memory(root), Vec::new(), None)],
+        }
+    }
+}
+
+impl<'a, K, V, A, I, H> Iterator for Nodes<'a, K, V, A, I, H>
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    type Item = NodeInfo<'a, I>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, path, ident) = self.stack.pop()?;
+
+        let mut leaves = 0;
+        let mut links = 0;
+        for slot in (0..4).rev() {
+            match node.entry(slot) {
+                EntryRef::Empty => (),
+                EntryRef::Leaf(..) => leaves += 1,
+                EntryRef::Node(child) => {
+                    links += 1;
+                    let mut child_path = path.clone();
+                    child_path.push(slot);
+                    self.stack.push((child, child_path, node.ident(slot)));
+                }
+            }
+        }
+
+        Some(NodeInfo {
+            depth: path.len(),
+            path,
+            ident,
+            size: node.size(),
+            leaves,
+            links,
+        })
+    }
+}
+
+/// Slot occupancy of all nodes at a given depth
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelProfile {
+    /// Number of nodes at this depth
+    pub nodes: usize,
+    /// Number of empty slots
+    pub empty: usize,
+    /// Number of slots holding a leaf
+    pub leaves: usize,
+    /// Number of slots holding a link to a node one level deeper
+    pub links: usize,
+}
+
+/// The shape of a map, see [`Hamt::profile`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    levels: Vec<LevelProfile>,
+}
+
+impl Profile {
+    pub(crate) fn new<K, V, A, I, H>(root: &Hamt<K, V, A, I, H>) -> Self
+    where
+        K: Archive<Archived = K>,
+        V: Archive,
+        <Hamt<K, V, A, I, H> as Archive>::Archived:
+            for<'any> CheckBytes<DefaultValidator<'any>>,
+    {
+        let mut levels: Vec<LevelProfile> = Vec::new();
+        let mut stack = vec![(NodeRef::Memory(root), 0)];
+
+        while let Some((node, depth)) = stack.pop() {
+            if levels.len() == depth {
+                levels.push(LevelProfile::default());
+            }
+            levels[depth].nodes += 1;
+
+            for slot in 0..4 {
+                match node.entry(slot) {
+                    EntryRef::Empty => levels[depth].empty += 1,
+                    EntryRef::Leaf(..) => levels[depth].leaves += 1,
+                    EntryRef::Node(child) => {
+                        levels[depth].links += 1;
+                        stack.push((child, depth + 1));
+                    }
+                }
+            }
+        }
+
+        Profile { levels }
+    }
+
+    /// Returns the occupancy of the nodes at each depth, starting with the
+    /// root
+    pub fn levels(&self) -> &[LevelProfile] {
+        &self.levels
+    }
+
+    /// Returns the depth of the deepest node, the root being at depth 0
+    ///
+    /// A default profile holds no level, and has depth 0 too.
+    pub fn depth(&self) -> usize {
+        self.levels.len().saturating_sub(1)
+    }
+
+    /// Returns the total number of leaves
+    pub fn len(&self) -> usize {
+        self.levels.iter().map(|level| level.leaves).sum()
+    }
+
+    /// Returns true if the profiled map holds no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of leaves found at each depth, starting with the
+    /// root
+    pub fn leaf_depths(&self) -> impl Iterator<Item = usize> + '_ {
+        self.levels.iter().map(|level| level.leaves)
+    }
+}
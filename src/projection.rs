@@ -0,0 +1,251 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Annotations tracking the extremes of a value projected from the leaves
+
+use core::borrow::Borrow;
+use core::marker::PhantomData;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    Annotation, Combine, Discriminant, MaybeArchived, Primitive, Step,
+    Walkable, Walker,
+};
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{Hamt, KvPair};
+
+/// A value projected from a key-value pair, such as a timestamp stored
+/// inside of the value
+///
+/// The projected type itself is used to parameterize [`MaxOf`] and
+/// [`MinOf`].
+pub trait Projection<K, V>
+where
+    V: Archive,
+{
+    /// Projects a key-value pair in memory
+    fn project(key: &K, val: &V) -> Self;
+
+    /// Projects a key-value pair archived in a store
+    fn project_archived(key: &K, val: &V::Archived) -> Self;
+}
+
+fn project<K, V, P>(leaf: MaybeArchived<KvPair<K, V>>) -> P
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    P: Projection<K, V>,
+{
+    match leaf {
+        MaybeArchived::Memory(kv) => P::project(&kv.key, &kv.val),
+        MaybeArchived::Archived(kv) => P::project_archived(&kv.key, &kv.val),
+    }
+}
+
+/// The maximum projection over the leaves of a subtree
+#[derive(
+    PartialEq, Eq, Clone, Debug, Archive, Serialize, Deserialize, CheckBytes,
+)]
+#[repr(u8)]
+#[archive(as = "Self")]
+#[archive(bound(archive = "
+  P: Primitive"))]
+pub enum MaxOf<P> {
+    /// Identity of max, everything else is larger
+    NegativeInfinity,
+    /// Actual max value
+    Maximum(P),
+}
+
+/// The minimum projection over the leaves of a subtree
+#[derive(
+    PartialEq, Eq, Clone, Debug, Archive, Serialize, Deserialize, CheckBytes,
+)]
+#[repr(u8)]
+#[archive(as = "Self")]
+#[archive(bound(archive = "
+  P: Primitive"))]
+pub enum MinOf<P> {
+    /// Identity of min, everything else is smaller
+    PositiveInfinity,
+    /// Actual min value
+    Minimum(P),
+}
+
+#[allow(clippy::derivable_impls)]
+impl<P> Default for MaxOf<P> {
+    fn default() -> Self {
+        MaxOf::NegativeInfinity
+    }
+}
+
+#[allow(clippy::derivable_impls)]
+impl<P> Default for MinOf<P> {
+    fn default() -> Self {
+        MinOf::PositiveInfinity
+    }
+}
+
+impl<P> MaxOf<P> {
+    /// Returns the maximum, or `None` for an empty subtree
+    pub fn get(&self) -> Option<&P> {
+        match self {
+            MaxOf::NegativeInfinity => None,
+            MaxOf::Maximum(p) => Some(p),
+        }
+    }
+}
+
+impl<P> MinOf<P> {
+    /// Returns the minimum, or `None` for an empty subtree
+    pub fn get(&self) -> Option<&P> {
+        match self {
+            MinOf::PositiveInfinity => None,
+            MinOf::Minimum(p) => Some(p),
+        }
+    }
+}
+
+impl<K, V, P> Annotation<KvPair<K, V>> for MaxOf<P>
+where
+    V: Archive,
+    P: Projection<K, V> + Primitive + Clone + Ord,
+{
+    fn from_leaf(leaf: &KvPair<K, V>) -> Self {
+        MaxOf::Maximum(P::project(&leaf.key, &leaf.val))
+    }
+}
+
+impl<K, V, P> Annotation<KvPair<K, V>> for MinOf<P>
+where
+    V: Archive,
+    P: Projection<K, V> + Primitive + Clone + Ord,
+{
+    fn from_leaf(leaf: &KvPair<K, V>) -> Self {
+        MinOf::Minimum(P::project(&leaf.key, &leaf.val))
+    }
+}
+
+impl<P, A> Combine<A> for MaxOf<P>
+where
+    P: Ord + Clone,
+    A: Borrow<Self>,
+{
+    fn combine(&mut self, other: &A) {
+        if let MaxOf::Maximum(b) = other.borrow() {
+            match self {
+                MaxOf::Maximum(a) if &*a >= b => (),
+                _ => *self = MaxOf::Maximum(b.clone()),
+            }
+        }
+    }
+}
+
+impl<P, A> Combine<A> for MinOf<P>
+where
+    P: Ord + Clone,
+    A: Borrow<Self>,
+{
+    fn combine(&mut self, other: &A) {
+        if let MinOf::Minimum(b) = other.borrow() {
+            match self {
+                MinOf::Minimum(a) if &*a <= b => (),
+                _ => *self = MinOf::Minimum(b.clone()),
+            }
+        }
+    }
+}
+
+/// Walker to find the leaf with the maximum projection
+pub struct FindMaxOf<P>(PhantomData<P>);
+
+/// Walker to find the leaf with the minimum projection
+pub struct FindMinOf<P>(PhantomData<P>);
+
+impl<P> Default for FindMaxOf<P> {
+    fn default() -> Self {
+        FindMaxOf(PhantomData)
+    }
+}
+
+impl<P> Default for FindMinOf<P> {
+    fn default() -> Self {
+        FindMinOf(PhantomData)
+    }
+}
+
+impl<K, V, A, I, H, P> Walker<Hamt<K, V, A, I, H>, A, I> for FindMaxOf<P>
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    A: Annotation<KvPair<K, V>> + Borrow<MaxOf<P>>,
+    P: Projection<K, V> + Ord + Clone,
+{
+    fn walk(&mut self, walk: impl Walkable<Hamt<K, V, A, I, H>, A, I>) -> Step {
+        let mut current_max: Option<P> = None;
+        let mut current_step = Step::Abort;
+
+        for i in 0.. {
+            let candidate = match walk.probe(i) {
+                Discriminant::Leaf(leaf) => project(leaf),
+                Discriminant::Annotation(a) => match (*a).borrow().get() {
+                    Some(p) => p.clone(),
+                    None => continue,
+                },
+                Discriminant::Empty => continue,
+                Discriminant::End => return current_step,
+            };
+
+            let larger = match &current_max {
+                Some(max) => candidate > *max,
+                None => true,
+            };
+
+            if larger {
+                current_max = Some(candidate);
+                current_step = Step::Found(i);
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl<K, V, A, I, H, P> Walker<Hamt<K, V, A, I, H>, A, I> for FindMinOf<P>
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    A: Annotation<KvPair<K, V>> + Borrow<MinOf<P>>,
+    P: Projection<K, V> + Ord + Clone,
+{
+    fn walk(&mut self, walk: impl Walkable<Hamt<K, V, A, I, H>, A, I>) -> Step {
+        let mut current_min: Option<P> = None;
+        let mut current_step = Step::Abort;
+
+        for i in 0.. {
+            let candidate = match walk.probe(i) {
+                Discriminant::Leaf(leaf) => project(leaf),
+                Discriminant::Annotation(a) => match (*a).borrow().get() {
+                    Some(p) => p.clone(),
+                    None => continue,
+                },
+                Discriminant::Empty => continue,
+                Discriminant::End => return current_step,
+            };
+
+            let smaller = match &current_min {
+                Some(min) => candidate < *min,
+                None => true,
+            };
+
+            if smaller {
+                current_min = Some(candidate);
+                current_step = Step::Found(i);
+            }
+        }
+        unreachable!()
+    }
+}
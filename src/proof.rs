@@ -0,0 +1,963 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Merkle proofs of the entries of a map
+
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::mem;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, Link, MaybeStored, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::commitment::NodeHasher;
+use crate::{
+    slot, Bucket, Commitment, CommitmentEncode, CommitmentHasher, Hamt,
+    HashedKey, KeyHasher, KvPair, SeaHash,
+};
+
+/// Version of the encoding produced by [`BatchProof::to_bytes`]
+pub const PROOF_FORMAT_VERSION: u8 = 1;
+
+/// Depth of the deepest node a proof may hold, the root being at depth 0
+///
+/// Two keys share their slot at each level with odds of one in four, so no
+/// map reaches this depth in practice. Deeper proofs are rejected, rather
+/// than risking the stack on hostile inputs.
+pub const MAX_PROOF_DEPTH: usize = 64;
+
+const KIND_BATCH: u8 = 1;
+
+const TAG_EMPTY: u8 = 0;
+const TAG_LEAF: u8 = 1;
+const TAG_NODE: u8 = 2;
+const TAG_OPEN: u8 = 3;
+
+/// The content of a slot of a node in a proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofSlot {
+    /// An empty slot
+    Empty,
+    /// A leaf, by its commitment
+    Leaf([u8; 32]),
+    /// A subtree left out of the proof, by its commitment
+    Node([u8; 32]),
+    /// A node included in the proof, following its parent
+    Open,
+}
+
+/// The reason a proof is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The proof is not a well formed tree of nodes
+    Malformed,
+    /// The proof does not lead to the expected root commitment
+    RootMismatch,
+    /// An entry is not on the paths covered by the proof
+    NotIncluded,
+    /// The proof holds nodes deeper than [`MAX_PROOF_DEPTH`]
+    TooDeep,
+}
+
+/// The reason bytes cannot be decoded into a proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The encoding is of a version this crate does not know
+    Version(u8),
+    /// The bytes encode another kind of proof
+    Kind(u8),
+    /// A slot has an unknown tag
+    Tag(u8),
+    /// The bytes end before the proof does
+    Truncated,
+    /// Bytes are left after the end of the proof
+    Trailing,
+    /// The proof holds nodes deeper than [`MAX_PROOF_DEPTH`]
+    TooDeep,
+}
+
+/// Proof that a set of entries is part of the map with a given root
+/// commitment
+///
+/// The proof holds the nodes on the paths of the proven keys, in depth
+/// first order, with the commitments of the subtrees and leaves next to
+/// them. Paths shared by several keys are only included once, so proving
+/// many keys together is much smaller than proving them one by one.
+pub struct BatchProof<C, H = SeaHash> {
+    nodes: Vec<[ProofSlot; 4]>,
+    _marker: PhantomData<(C, H)>,
+}
+
+impl<C, H> Clone for BatchProof<C, H> {
+    fn clone(&self) -> Self {
+        BatchProof {
+            nodes: self.nodes.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C, H> PartialEq for BatchProof<C, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes
+    }
+}
+
+impl<C, H> Eq for BatchProof<C, H> {}
+
+impl<C, H> fmt::Debug for BatchProof<C, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BatchProof").field(&self.nodes).finish()
+    }
+}
+
+impl<C, H> BatchProof<C, H>
+where
+    C: CommitmentHasher,
+    H: KeyHasher,
+{
+    /// Creates a proof from its nodes, in depth first order
+    pub fn new(nodes: Vec<[ProofSlot; 4]>) -> Self {
+        BatchProof {
+            nodes,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the nodes of the proof, in depth first order
+    pub fn nodes(&self) -> &[[ProofSlot; 4]] {
+        &self.nodes
+    }
+
+    /// Encodes the proof in a canonical format, independent of the memory
+    /// layout of the proof
+    ///
+    /// The encoding starts with the format version and the kind of proof,
+    /// each a single byte, followed by the number of nodes as a little
+    /// endian `u32`. Each node is then written as its four slots, in order:
+    /// a tag byte, followed by the 32 bytes of the commitment for leaves and
+    /// subtrees left out.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(6 + self.nodes.len() * 4 * 33);
+        bytes.push(PROOF_FORMAT_VERSION);
+        bytes.push(KIND_BATCH);
+        bytes.extend_from_slice(&(self.nodes.len() as u32).to_le_bytes());
+        for proof_slot in self.nodes.iter().flatten() {
+            match proof_slot {
+                ProofSlot::Empty => bytes.push(TAG_EMPTY),
+                ProofSlot::Leaf(digest) => {
+                    bytes.push(TAG_LEAF);
+                    bytes.extend_from_slice(digest);
+                }
+                ProofSlot::Node(digest) => {
+                    bytes.push(TAG_NODE);
+                    bytes.extend_from_slice(digest);
+                }
+                ProofSlot::Open => bytes.push(TAG_OPEN),
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a proof encoded by [`to_bytes`](Self::to_bytes)
+    ///
+    /// Only the encoding and the depth of the nodes are checked, a decoded
+    /// proof may still fail verification.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader(bytes);
+
+        let version = reader.byte()?;
+        if version != PROOF_FORMAT_VERSION {
+            return Err(DecodeError::Version(version));
+        }
+        let kind = reader.byte()?;
+        if kind != KIND_BATCH {
+            return Err(DecodeError::Kind(kind));
+        }
+
+        let mut len = [0; 4];
+        len.copy_from_slice(reader.take(4)?);
+        let len = u32::from_le_bytes(len) as usize;
+
+        // every node takes at least four bytes, bounding the allocation
+        if len > reader.0.len() / 4 {
+            return Err(DecodeError::Truncated);
+        }
+
+        let mut nodes = Vec::with_capacity(len);
+        // open slots left to follow in each ancestor of the next node
+        let mut open: Vec<usize> = Vec::new();
+        for _ in 0..len {
+            let mut node = [ProofSlot::Empty; 4];
+            for proof_slot in node.iter_mut() {
+                *proof_slot = match reader.byte()? {
+                    TAG_EMPTY => ProofSlot::Empty,
+                    TAG_LEAF => ProofSlot::Leaf(reader.digest()?),
+                    TAG_NODE => ProofSlot::Node(reader.digest()?),
+                    TAG_OPEN => ProofSlot::Open,
+                    tag => return Err(DecodeError::Tag(tag)),
+                };
+            }
+
+            while open.last() == Some(&0) {
+                open.pop();
+            }
+            if let Some(parent) = open.last_mut() {
+                *parent -= 1;
+            }
+            if open.len() > MAX_PROOF_DEPTH {
+                return Err(DecodeError::TooDeep);
+            }
+            open.push(node.iter().filter(|s| **s == ProofSlot::Open).count());
+            nodes.push(node);
+        }
+
+        if !reader.0.is_empty() {
+            return Err(DecodeError::Trailing);
+        }
+        Ok(BatchProof::new(nodes))
+    }
+
+    /// Returns the root commitment the proof leads to, or an error if it
+    /// is malformed
+    pub fn root(&self) -> Result<Commitment<C>, VerifyError> {
+        self.resolve().map(|(root, _)| root)
+    }
+
+    /// Checks that every entry of `entries` is part of the map with root
+    /// commitment `root`
+    pub fn verify<K, V>(
+        &self,
+        root: &Commitment<C>,
+        entries: &[(&K, &V)],
+    ) -> Result<(), VerifyError>
+    where
+        K: Hash + CommitmentEncode,
+        V: CommitmentEncode,
+    {
+        let (computed, children) = self.resolve()?;
+        if computed != *root {
+            return Err(VerifyError::RootMismatch);
+        }
+
+        for (key, val) in entries {
+            if !includes::<_, _, C, H>(&self.nodes, &children, key, val) {
+                return Err(VerifyError::NotIncluded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Recomputes the root commitment, along with the index of the node
+    /// following each open slot
+    fn resolve(&self) -> Result<(Commitment<C>, Vec<[usize; 4]>), VerifyError> {
+        let mut children = vec![[0; 4]; self.nodes.len()];
+        let mut next = 0;
+        let root = self.resolve_node(&mut next, &mut children, 0)?;
+        if next != self.nodes.len() {
+            return Err(VerifyError::Malformed);
+        }
+        Ok((root, children))
+    }
+
+    fn resolve_node(
+        &self,
+        next: &mut usize,
+        children: &mut [[usize; 4]],
+        depth: usize,
+    ) -> Result<Commitment<C>, VerifyError> {
+        if depth > MAX_PROOF_DEPTH {
+            return Err(VerifyError::TooDeep);
+        }
+        let index = *next;
+        let node = self.nodes.get(index).ok_or(VerifyError::Malformed)?;
+        *next += 1;
+
+        let mut hasher = NodeHasher::new();
+        for (ofs, proof_slot) in node.iter().enumerate() {
+            match proof_slot {
+                ProofSlot::Empty => hasher.empty(),
+                ProofSlot::Leaf(digest) => {
+                    hasher.leaf(&Commitment::new(*digest))
+                }
+                ProofSlot::Node(digest) => {
+                    hasher.node(&Commitment::new(*digest))
+                }
+                ProofSlot::Open => {
+                    children[index][ofs] = *next;
+                    let child = self.resolve_node(next, children, depth + 1)?;
+                    hasher.node(&child);
+                }
+            }
+        }
+        Ok(hasher.finish())
+    }
+}
+
+/// Returns true if the entry is on a path through `nodes`, given the
+/// index of the node following each open slot
+fn includes<K, V, C, H>(
+    nodes: &[[ProofSlot; 4]],
+    children: &[[usize; 4]],
+    key: &K,
+    val: &V,
+) -> bool
+where
+    K: Hash + CommitmentEncode,
+    V: CommitmentEncode,
+    C: CommitmentHasher,
+    H: KeyHasher,
+{
+    let digest = H::digest(key);
+    let leaf = *Commitment::<C>::from_entry(key, val).as_bytes();
+
+    let mut node = 0;
+    let mut depth = 0;
+    loop {
+        let ofs = slot::<H>(digest, depth);
+        match nodes[node][ofs] {
+            ProofSlot::Leaf(found) if found == leaf => return true,
+            ProofSlot::Open => {
+                node = children[node][ofs];
+                depth += 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// A proof along with the entries it should include, see [`verify_many`]
+pub(crate) type ProofOf<'a, K, V, C, H> =
+    (&'a BatchProof<C, H>, &'a [(&'a K, &'a V)]);
+
+/// Checks many proofs against the same root commitment, each along with
+/// the entries it should include
+///
+/// The proofs are merged into a single tree, so the nodes they share are
+/// only hashed once. If any proof is rejected, they are checked one by one
+/// to report the index of the first one [`BatchProof::verify`] rejects,
+/// and why.
+pub fn verify_many<K, V, C, H>(
+    root: &Commitment<C>,
+    proofs: &[ProofOf<'_, K, V, C, H>],
+) -> Result<(), (usize, VerifyError)>
+where
+    K: Hash + CommitmentEncode,
+    V: CommitmentEncode,
+    C: CommitmentHasher,
+    H: KeyHasher,
+{
+    if Merged::verify(root, proofs) {
+        return Ok(());
+    }
+    for (i, (proof, entries)) in proofs.iter().enumerate() {
+        proof.verify(root, entries).map_err(|e| (i, e))?;
+    }
+    Ok(())
+}
+
+/// The union of the nodes of proofs of the same map, see [`verify_many`]
+struct Merged {
+    nodes: Vec<[ProofSlot; 4]>,
+    children: Vec<[usize; 4]>,
+    /// Subtrees one proof opens and another only gives the commitment of,
+    /// by the index of the merged node and the commitment expected
+    claims: Vec<(usize, [u8; 32])>,
+}
+
+impl Merged {
+    fn verify<K, V, C, H>(
+        root: &Commitment<C>,
+        proofs: &[ProofOf<'_, K, V, C, H>],
+    ) -> bool
+    where
+        K: Hash + CommitmentEncode,
+        V: CommitmentEncode,
+        C: CommitmentHasher,
+        H: KeyHasher,
+    {
+        let mut merged = Merged {
+            nodes: Vec::new(),
+            children: Vec::new(),
+            claims: Vec::new(),
+        };
+
+        for (proof, _) in proofs {
+            let mut next = 0;
+            let target = if merged.nodes.is_empty() {
+                None
+            } else {
+                Some(0)
+            };
+            if merged.merge(proof.nodes(), &mut next, target, 0).is_none()
+                || next != proof.nodes().len()
+            {
+                return false;
+            }
+        }
+        if merged.nodes.is_empty() {
+            return true;
+        }
+
+        let mut commitments = vec![[0; 32]; merged.nodes.len()];
+        merged.commit::<C>(0, &mut commitments);
+
+        commitments[0] == *root.as_bytes()
+            && merged
+                .claims
+                .iter()
+                .all(|(node, claim)| commitments[*node] == *claim)
+            && proofs.iter().all(|(_, entries)| {
+                entries.iter().all(|(key, val)| {
+                    includes::<_, _, C, H>(
+                        &merged.nodes,
+                        &merged.children,
+                        key,
+                        val,
+                    )
+                })
+            })
+    }
+
+    /// Merges the node of `src` at `next`, at `depth`, and the nodes
+    /// following its open slots, into the merged node at `target`, or into
+    /// a new one
+    ///
+    /// Nothing deeper than [`MAX_PROOF_DEPTH`] is merged, so computing the
+    /// commitments of the merged nodes is bounded too.
+    fn merge(
+        &mut self,
+        src: &[[ProofSlot; 4]],
+        next: &mut usize,
+        target: Option<usize>,
+        depth: usize,
+    ) -> Option<usize> {
+        if depth > MAX_PROOF_DEPTH {
+            return None;
+        }
+        let node = *src.get(*next)?;
+        *next += 1;
+
+        let target = match target {
+            Some(target) => target,
+            None => {
+                self.nodes.push(node);
+                self.children.push([0; 4]);
+                let target = self.nodes.len() - 1;
+                for (ofs, proof_slot) in node.iter().enumerate() {
+                    if *proof_slot == ProofSlot::Open {
+                        let child = self.merge(src, next, None, depth + 1)?;
+                        self.children[target][ofs] = child;
+                    }
+                }
+                return Some(target);
+            }
+        };
+
+        for (ofs, proof_slot) in node.iter().enumerate() {
+            match (self.nodes[target][ofs], *proof_slot) {
+                (ProofSlot::Open, ProofSlot::Open) => {
+                    let child = self.children[target][ofs];
+                    self.merge(src, next, Some(child), depth + 1)?;
+                }
+                (ProofSlot::Open, ProofSlot::Node(claim)) => {
+                    self.claims.push((self.children[target][ofs], claim));
+                }
+                (ProofSlot::Node(claim), ProofSlot::Open) => {
+                    let child = self.merge(src, next, None, depth + 1)?;
+                    self.nodes[target][ofs] = ProofSlot::Open;
+                    self.children[target][ofs] = child;
+                    self.claims.push((child, claim));
+                }
+                (merged, other) if merged == other => (),
+                _ => return None,
+            }
+        }
+        Some(target)
+    }
+
+    /// Computes the commitment of the merged node at `node` and the ones
+    /// below it
+    fn commit<C>(&self, node: usize, commitments: &mut [[u8; 32]])
+    where
+        C: CommitmentHasher,
+    {
+        let mut hasher = NodeHasher::<C>::new();
+        for (ofs, proof_slot) in self.nodes[node].iter().enumerate() {
+            match proof_slot {
+                ProofSlot::Empty => hasher.empty(),
+                ProofSlot::Leaf(digest) => {
+                    hasher.leaf(&Commitment::new(*digest))
+                }
+                ProofSlot::Node(digest) => {
+                    hasher.node(&Commitment::new(*digest))
+                }
+                ProofSlot::Open => {
+                    let child = self.children[node][ofs];
+                    self.commit::<C>(child, commitments);
+                    hasher.node(&Commitment::new(commitments[child]));
+                }
+            }
+        }
+        commitments[node] = *hasher.finish().as_bytes();
+    }
+}
+
+/// A change to a single entry of a map, see [`verify_update`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Update<'a, K, V> {
+    /// Stores `val` under `key`, replacing any previous value
+    Insert(&'a K, &'a V),
+    /// Removes the entry stored under `key`, if any
+    Remove(&'a K),
+}
+
+impl<'a, K, V> Update<'a, K, V> {
+    fn key(&self) -> &'a K {
+        match *self {
+            Update::Insert(key, _) | Update::Remove(key) => key,
+        }
+    }
+}
+
+/// Proof of the path of a key in a map, from which the effect of a single
+/// update to that key can be verified, see [`Hamt::prove_update`]
+///
+/// The proof holds one node per level down to the slot where the key is or
+/// would be stored, and the entry found in that slot, if any.
+pub struct UpdateProof<K, V, C, H = SeaHash> {
+    path: BatchProof<C, H>,
+    leaf: Option<KvPair<K, V>>,
+}
+
+impl<K, V, C, H> Clone for UpdateProof<K, V, C, H>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        UpdateProof {
+            path: self.path.clone(),
+            leaf: self.leaf.clone(),
+        }
+    }
+}
+
+impl<K, V, C, H> fmt::Debug for UpdateProof<K, V, C, H>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpdateProof")
+            .field("path", &self.path)
+            .field("leaf", &self.leaf)
+            .finish()
+    }
+}
+
+impl<K, V, C, H> UpdateProof<K, V, C, H> {
+    /// Creates a proof from the nodes on the path of a key, and the entry
+    /// at its end
+    pub fn new(path: BatchProof<C, H>, leaf: Option<KvPair<K, V>>) -> Self {
+        UpdateProof { path, leaf }
+    }
+
+    /// Returns the nodes on the path of the key
+    pub fn path(&self) -> &BatchProof<C, H> {
+        &self.path
+    }
+
+    /// Returns the entry at the end of the path, if any
+    pub fn leaf(&self) -> Option<&KvPair<K, V>> {
+        self.leaf.as_ref()
+    }
+}
+
+/// Checks, from `proof` alone, that applying `update` to the map with root
+/// commitment `old_root` yields the map with root commitment `new_root`
+///
+/// The path in the proof is checked against `old_root`, then the
+/// commitments along it are recomputed with the update applied, exactly as
+/// [`Hamt::insert`] and [`Hamt::remove`] reshape the tree.
+pub fn verify_update<K, V, C, H>(
+    old_root: &Commitment<C>,
+    proof: &UpdateProof<K, V, C, H>,
+    update: Update<'_, K, V>,
+    new_root: &Commitment<C>,
+) -> Result<(), VerifyError>
+where
+    K: Eq + Hash + CommitmentEncode,
+    V: CommitmentEncode,
+    C: CommitmentHasher,
+    H: KeyHasher,
+{
+    let nodes = proof.path.nodes();
+    let (root, children) = proof.path.resolve()?;
+    if root != *old_root {
+        return Err(VerifyError::RootMismatch);
+    }
+
+    let key = update.key();
+    let digest = H::digest(key);
+
+    // follow the key down to the slot it is or would be stored in
+    let mut path = Vec::new();
+    let mut node = 0;
+    let end = loop {
+        let ofs = slot::<H>(digest, path.len());
+        path.push((node, ofs));
+        match nodes[node][ofs] {
+            ProofSlot::Open => node = children[node][ofs],
+            ProofSlot::Node(_) => return Err(VerifyError::Malformed),
+            end => break end,
+        }
+    };
+    if path.len() != nodes.len() {
+        return Err(VerifyError::Malformed);
+    }
+
+    let found = match (end, &proof.leaf) {
+        (ProofSlot::Empty, None) => None,
+        (ProofSlot::Leaf(leaf), Some(kv))
+            if *Commitment::<C>::from_entry(&kv.key, &kv.val).as_bytes()
+                == leaf =>
+        {
+            Some(kv)
+        }
+        _ => return Err(VerifyError::Malformed),
+    };
+
+    // only removals collapse nodes left with a single leaf
+    let collapse = matches!(update, Update::Remove(_));
+    let mut replacement = match (update, found) {
+        (Update::Insert(key, val), None) => {
+            ProofSlot::Leaf(*Commitment::<C>::from_entry(key, val).as_bytes())
+        }
+        (Update::Insert(key, val), Some(kv)) if kv.key == *key => {
+            ProofSlot::Leaf(*Commitment::<C>::from_entry(key, val).as_bytes())
+        }
+        (Update::Insert(key, val), Some(kv)) => split::<_, _, C, H>(
+            (key, val),
+            (&kv.key, &Commitment::from_entry(&kv.key, &kv.val)),
+            path.len(),
+        )?,
+        (Update::Remove(key), Some(kv)) if kv.key == *key => ProofSlot::Empty,
+        (Update::Remove(_), _) => {
+            return if old_root == new_root {
+                Ok(())
+            } else {
+                Err(VerifyError::RootMismatch)
+            };
+        }
+    };
+
+    for (depth, (node, ofs)) in path.into_iter().enumerate().rev() {
+        let mut slots = nodes[node];
+        slots[ofs] = replacement;
+
+        replacement = match single_leaf(&slots) {
+            Some(leaf) if collapse && depth > 0 => leaf,
+            _ => ProofSlot::Node(*commit_slots::<C>(&slots).as_bytes()),
+        };
+    }
+
+    match replacement {
+        ProofSlot::Node(root) if root == *new_root.as_bytes() => Ok(()),
+        _ => Err(VerifyError::RootMismatch),
+    }
+}
+
+/// Returns the slot holding the only leaf of a node with no other entry
+fn single_leaf(slots: &[ProofSlot; 4]) -> Option<ProofSlot> {
+    let mut leaves = slots.iter().filter(|s| **s != ProofSlot::Empty);
+    match (leaves.next(), leaves.next()) {
+        (Some(leaf @ ProofSlot::Leaf(_)), None) => Some(*leaf),
+        _ => None,
+    }
+}
+
+/// Returns the commitment of a node whose slots are all resolved
+fn commit_slots<C>(slots: &[ProofSlot; 4]) -> Commitment<C>
+where
+    C: CommitmentHasher,
+{
+    let mut hasher = NodeHasher::new();
+    for proof_slot in slots {
+        match proof_slot {
+            ProofSlot::Empty => hasher.empty(),
+            ProofSlot::Leaf(digest) => hasher.leaf(&Commitment::new(*digest)),
+            ProofSlot::Node(digest) => hasher.node(&Commitment::new(*digest)),
+            ProofSlot::Open => unreachable!("Only resolved slots are hashed"),
+        }
+    }
+    hasher.finish()
+}
+
+/// Returns the slot replacing a leaf when an entry under another key is
+/// inserted on its path, holding the nodes from `depth` down that split the
+/// two apart
+///
+/// Keys whose digests are equal share their path at every depth, so their
+/// split is rejected once past [`MAX_PROOF_DEPTH`].
+fn split<K, V, C, H>(
+    (key, val): (&K, &V),
+    (other, leaf): (&K, &Commitment<C>),
+    depth: usize,
+) -> Result<ProofSlot, VerifyError>
+where
+    K: Hash + CommitmentEncode,
+    V: CommitmentEncode,
+    C: CommitmentHasher,
+    H: KeyHasher,
+{
+    let digest = H::digest(key);
+    let other_digest = H::digest(other);
+
+    let mut bottom = depth;
+    while slot::<H>(digest, bottom) == slot::<H>(other_digest, bottom) {
+        bottom += 1;
+        if bottom > MAX_PROOF_DEPTH {
+            return Err(VerifyError::Malformed);
+        }
+    }
+
+    let mut slots = [ProofSlot::Empty; 4];
+    slots[slot::<H>(digest, bottom)] =
+        ProofSlot::Leaf(*Commitment::<C>::from_entry(key, val).as_bytes());
+    slots[slot::<H>(other_digest, bottom)] = ProofSlot::Leaf(*leaf.as_bytes());
+    let mut node = commit_slots::<C>(&slots);
+
+    // the nodes above hold nothing but the path shared by both keys
+    for shared in (depth..bottom).rev() {
+        let mut slots = [ProofSlot::Empty; 4];
+        slots[slot::<H>(digest, shared)] = ProofSlot::Node(*node.as_bytes());
+        node = commit_slots::<C>(&slots);
+    }
+    Ok(ProofSlot::Node(*node.as_bytes()))
+}
+
+/// Reads the encoding of a proof from the front of a slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn digest(&mut self) -> Result<[u8; 32], DecodeError> {
+        let mut digest = [0; 32];
+        digest.copy_from_slice(self.take(32)?);
+        Ok(digest)
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + CommitmentEncode
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone + CommitmentEncode,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Self: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Self, A, I>
+        + Deserialize<Self, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Proves that all of `keys` are in the map, in a single proof sharing
+    /// the nodes common to their paths
+    ///
+    /// Returns `None` if any of the keys is missing.
+    pub fn prove_many<C>(&self, keys: &[&K]) -> Option<BatchProof<C, H>>
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        let digests = H::digest_many(keys);
+        let mut indices: Vec<_> = (0..keys.len()).collect();
+        let mut nodes = Vec::new();
+
+        if self.prove_node(keys, &digests, &mut indices, 0, &mut nodes) {
+            Some(BatchProof::new(nodes))
+        } else {
+            None
+        }
+    }
+
+    /// Proves the path of `key` in the map, from which the effect of an
+    /// insertion or removal of `key` can be checked with [`verify_update`]
+    pub fn prove_update<C>(&self, key: &K) -> UpdateProof<K, V, C, H>
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        self.prove_digest(H::digest(key))
+    }
+
+    /// Proves the current state of a hashed key, like
+    /// [`prove_update`](Self::prove_update)
+    pub fn prove_update_hashed<C>(
+        &self,
+        key: &HashedKey<K, H>,
+    ) -> UpdateProof<K, V, C, H>
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        self.prove_digest(key.digest())
+    }
+
+    fn prove_digest<C>(&self, digest: H::Digest) -> UpdateProof<K, V, C, H>
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        let mut nodes = Vec::new();
+        let leaf = self.prove_path(digest, 0, &mut nodes);
+        UpdateProof::new(BatchProof::new(nodes), leaf)
+    }
+
+    fn prove_path<C>(
+        &self,
+        digest: H::Digest,
+        depth: usize,
+        nodes: &mut Vec<[ProofSlot; 4]>,
+    ) -> Option<KvPair<K, V>>
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        let ofs = slot::<H>(digest, depth);
+        let mut slots = [ProofSlot::Empty; 4];
+        for (proof_slot, bucket) in slots.iter_mut().zip(self.0.iter()) {
+            *proof_slot = Self::proof_slot(bucket);
+        }
+
+        match &self.0[ofs] {
+            Bucket::Node(link) => {
+                slots[ofs] = ProofSlot::Open;
+                nodes.push(slots);
+                Self::with_child(link, |child| {
+                    child.prove_path(digest, depth + 1, nodes)
+                })
+            }
+            Bucket::Leaf(kv) => {
+                nodes.push(slots);
+                Some(kv.clone())
+            }
+            Bucket::Empty => {
+                nodes.push(slots);
+                None
+            }
+        }
+    }
+
+    /// Returns the slot standing for `bucket` in a proof, without opening
+    /// it
+    fn proof_slot<C>(bucket: &Bucket<K, V, A, I, H>) -> ProofSlot
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        match bucket {
+            Bucket::Empty => ProofSlot::Empty,
+            Bucket::Leaf(kv) => {
+                let leaf = Commitment::<C>::from_entry(&kv.key, &kv.val);
+                ProofSlot::Leaf(*leaf.as_bytes())
+            }
+            Bucket::Node(link) => {
+                let annotation = link.annotation();
+                ProofSlot::Node(*(*annotation).borrow().as_bytes())
+            }
+        }
+    }
+
+    /// Calls `f` with the node behind `link`, deserializing it if stored
+    fn with_child<R, F>(link: &Link<Self, A, I>, f: F) -> R
+    where
+        F: FnOnce(&Self) -> R,
+    {
+        match link.inner() {
+            MaybeStored::Memory(node) => f(node),
+            MaybeStored::Stored(stored) => {
+                let node: Self = stored
+                    .inner()
+                    .deserialize(&mut stored.store().clone())
+                    .expect("deserializing from a store is infallible");
+                f(&node)
+            }
+        }
+    }
+
+    fn prove_node<C>(
+        &self,
+        keys: &[&K],
+        digests: &[H::Digest],
+        indices: &mut [usize],
+        depth: usize,
+        nodes: &mut Vec<[ProofSlot; 4]>,
+    ) -> bool
+    where
+        A: Borrow<Commitment<C>>,
+        C: CommitmentHasher,
+    {
+        indices.sort_by_cached_key(|i| slot::<H>(digests[*i], depth));
+
+        let index = nodes.len();
+        nodes.push([ProofSlot::Empty; 4]);
+
+        let mut rest = indices;
+        for (ofs, bucket) in self.0.iter().enumerate() {
+            let len = rest
+                .iter()
+                .take_while(|i| slot::<H>(digests[**i], depth) == ofs)
+                .count();
+            let (group, tail) = mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+
+            nodes[index][ofs] = match bucket {
+                _ if group.is_empty() => Self::proof_slot(bucket),
+                Bucket::Empty => return false,
+                Bucket::Leaf(kv) => {
+                    if group.iter().any(|i| *keys[*i] != kv.key) {
+                        return false;
+                    }
+                    Self::proof_slot(bucket)
+                }
+                Bucket::Node(link) => {
+                    let proven = Self::with_child(link, |child| {
+                        child.prove_node(keys, digests, group, depth + 1, nodes)
+                    });
+                    if !proven {
+                        return false;
+                    }
+                    ProofSlot::Open
+                }
+            };
+        }
+        true
+    }
+}
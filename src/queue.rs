@@ -0,0 +1,83 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Priority queue over a keyed map
+
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, Branch, Primitive, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::{Hamt, KvPair, MaxOf, Projection};
+
+type Map<K, V, P, I> = Hamt<K, V, MaxOf<P>, I>;
+type MapBranch<'a, K, V, P, I> = Branch<'a, Map<K, V, P, I>, MaxOf<P>, I>;
+
+/// A keyed priority queue, ordered by the priority `P` projected from each
+/// entry
+///
+/// Entries are stored in a map annotated with [`MaxOf`], so the entry with
+/// the highest priority is found by descending a single path.
+#[derive(Clone)]
+pub struct HamtPriorityQueue<K, V, P, I>(Map<K, V, P, I>);
+
+impl<K, V, P, I> HamtPriorityQueue<K, V, P, I>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    P: Projection<K, V> + Primitive + Clone + Ord,
+    Map<K, V, P, I>: Archive,
+    <Map<K, V, P, I> as Archive>::Archived: ArchivedCompound<Map<K, V, P, I>, MaxOf<P>, I>
+        + Deserialize<Map<K, V, P, I>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates a new empty queue
+    pub fn new() -> Self {
+        HamtPriorityQueue(Hamt::new())
+    }
+
+    /// Pushes an entry into the queue, returning the value it replaced
+    pub fn push(&mut self, key: K, val: V) -> Option<V> {
+        self.0.insert(key, val)
+    }
+
+    /// Returns a branch to the entry with the highest priority
+    pub fn peek_max(&self) -> Option<MapBranch<'_, K, V, P, I>> {
+        self.0.max_of::<P>()
+    }
+
+    /// Removes and returns the entry with the highest priority
+    pub fn pop_max(&mut self) -> Option<(K, V)> {
+        self.0.pop_max_of::<P>()
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.0.remove(key)
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &Map<K, V, P, I> {
+        &self.0
+    }
+}
+
+impl<K, V, P, I> Default for HamtPriorityQueue<K, V, P, I>
+where
+    MaxOf<P>: Annotation<KvPair<K, V>>,
+{
+    fn default() -> Self {
+        HamtPriorityQueue(Default::default())
+    }
+}
@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Low-level access to nodes, to build other structures on the same nodes
+//!
+//! Maps built this way are only valid if every leaf lies on the path its
+//! key hashes to, and no node below the root is empty or holds a single
+//! leaf alone. Upholding this is up to the caller, `check_invariants` of
+//! the `testing` feature verifies it.
+
+use core::cell::RefCell;
+use core::hash::Hash;
+
+use alloc::rc::Rc;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, Link, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::{slot, Bucket, Hamt, KeyHasher, KvPair};
+
+impl<K, V, A, I, H> Bucket<K, V, A, I, H> {
+    /// Creates a bucket holding a single entry
+    pub fn leaf(key: K, val: V) -> Self {
+        Bucket::Leaf(KvPair::new(key, val))
+    }
+
+    /// Creates a bucket linking to `node`, whose annotation is computed
+    /// when first read
+    pub fn node(node: Hamt<K, V, A, I, H>) -> Self {
+        Bucket::Node(Link::new(node))
+    }
+
+    /// Creates a bucket linking to `node`, with `annotation` as its
+    /// annotation
+    ///
+    /// The annotation is trusted rather than computed, it must be the one
+    /// [`Annotation::from_node`] computes for `node`.
+    pub fn node_with_annotation(
+        node: Hamt<K, V, A, I, H>,
+        annotation: A,
+    ) -> Self {
+        Bucket::Node(Link::Memory {
+            rc: Rc::new(node),
+            annotation: RefCell::new(Some(annotation)),
+        })
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H> {
+    /// Creates a node from its four buckets
+    pub fn from_buckets(buckets: [Bucket<K, V, A, I, H>; 4]) -> Self {
+        Hamt(buckets, Default::default())
+    }
+
+    /// Returns the buckets of the node
+    pub fn buckets(&self) -> &[Bucket<K, V, A, I, H>; 4] {
+        &self.0
+    }
+
+    /// Returns the buckets of the node mutably
+    ///
+    /// The annotations cached in the links to the nodes above are not
+    /// dropped, so changing a node reached through a link must go through
+    /// [`Link::inner_mut`], as walks do.
+    pub fn buckets_mut(&mut self) -> &mut [Bucket<K, V, A, I, H>; 4] {
+        &mut self.0
+    }
+
+    /// Consumes the node, returning its buckets
+    pub fn into_buckets(self) -> [Bucket<K, V, A, I, H>; 4] {
+        self.0
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Self: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Self, A, I>
+        + Deserialize<Self, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Returns the slot `key` takes in a node at `depth`, the root being at
+    /// depth 0
+    pub fn slot_of(key: &K, depth: usize) -> usize {
+        slot::<H>(H::digest(key), depth)
+    }
+
+    /// Inserts a key-value pair into this node as if it lay at `depth`,
+    /// returning the previous value under `key`
+    ///
+    /// Leaves meeting the key are split into nodes, as
+    /// [`insert`](Self::insert) does at the root.
+    pub fn insert_at(&mut self, key: K, val: V, depth: usize) -> Option<V> {
+        let digest = H::digest(&key);
+        self._insert(key, val, digest, depth)
+    }
+
+    /// Removes `key` from this node as if it lay at `depth`, returning its
+    /// value
+    ///
+    /// Nodes below this one are collapsed as [`remove`](Self::remove) does,
+    /// but not this node itself, which the caller turns into the bucket
+    /// representing it with [`into_bucket`](Self::into_bucket).
+    pub fn remove_at(&mut self, key: &K, depth: usize) -> Option<V> {
+        let digest = H::digest(key);
+        self._remove(key, digest, depth)
+    }
+}
@@ -0,0 +1,214 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Named roots of the maps kept in a store
+
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Ident, StoreRef, StoreSerializer, Stored};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Serialize};
+
+/// The store of a [`RootRegistry`] failed to persist its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PersistError;
+
+#[derive(Archive, Serialize)]
+#[archive_attr(derive(CheckBytes))]
+struct RootEntry<I> {
+    name: String,
+    root: I,
+}
+
+/// A table of named roots, letting a single store hold many maps
+///
+/// Roots are saved and loaded by name. The table itself is written to the
+/// store by [`RootRegistry::commit`], so all the changes made since the last
+/// commit become visible together once the caller switches over to the
+/// single identifier it returns.
+///
+/// The maps may be of different types, and are written to the same store
+/// as the table. [`RootRegistry::persist`] makes all of them durable before
+/// returning the identifier, so a crash never leaves a table referring to
+/// roots that were not written.
+///
+/// Clones of a registry are handles to the same table, a root saved through
+/// any of them being seen by all the others. The store has no mutable head,
+/// so this is how writers in a process share the latest roots, and what
+/// [`RootRegistry::compare_and_apply`] checks against.
+pub struct RootRegistry<I> {
+    store: StoreRef<I>,
+    roots: Rc<RefCell<Roots<I>>>,
+}
+
+/// The table shared by the handles to a registry
+///
+/// Every saved root is tagged with a fresh revision, telling apart two
+/// saves of the same root under a name.
+struct Roots<I> {
+    entries: BTreeMap<String, (I, u64)>,
+    revision: u64,
+}
+
+impl<I> Roots<I> {
+    fn from_entries(roots: BTreeMap<String, I>) -> Self {
+        let mut table = Roots {
+            entries: BTreeMap::new(),
+            revision: 0,
+        };
+        for (name, root) in roots {
+            table.save(name, root);
+        }
+        table
+    }
+
+    fn save(&mut self, name: String, root: I) {
+        self.revision += 1;
+        self.entries.insert(name, (root, self.revision));
+    }
+}
+
+impl<I> Clone for RootRegistry<I> {
+    fn clone(&self) -> Self {
+        RootRegistry {
+            store: self.store.clone(),
+            roots: self.roots.clone(),
+        }
+    }
+}
+
+impl<I> RootRegistry<I>
+where
+    I: Archive<Archived = I>
+        + Clone
+        + Serialize<StoreSerializer<I>>
+        + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates an empty registry of roots in `store`
+    pub fn new(store: StoreRef<I>) -> Self {
+        RootRegistry {
+            store,
+            roots: Rc::new(RefCell::new(Roots::from_entries(BTreeMap::new()))),
+        }
+    }
+
+    /// Opens the registry committed to `store` under `ident`
+    pub fn open(store: StoreRef<I>, ident: &I) -> Self {
+        let entries =
+            store.get(&Ident::<Vec<RootEntry<I>>, I>::new(ident.clone()));
+        let roots = entries
+            .iter()
+            .map(|entry| {
+                (String::from(entry.name.as_str()), entry.root.clone())
+            })
+            .collect();
+        RootRegistry {
+            store,
+            roots: Rc::new(RefCell::new(Roots::from_entries(roots))),
+        }
+    }
+
+    /// Writes `map` to the store of the registry and saves its root under
+    /// `name`, returning the root
+    pub fn store_map<T>(&mut self, name: &str, map: &T) -> Stored<T, I>
+    where
+        T: Serialize<StoreSerializer<I>>,
+    {
+        let root = self.store.store(map);
+        self.save_root(name, &root);
+        root
+    }
+
+    /// Saves `root` under `name`, replacing any root previously saved
+    /// under the same name
+    pub fn save_root<T>(&mut self, name: &str, root: &Stored<T, I>) {
+        let root = root.ident().erase().clone();
+        self.roots.borrow_mut().save(name.into(), root);
+    }
+
+    /// Returns the root saved under `name`
+    pub fn load_root<T>(&self, name: &str) -> Option<Stored<T, I>> {
+        self.revision_of(name).map(|(root, _)| root)
+    }
+
+    /// Forgets the root saved under `name`, returning true if there was one
+    pub fn remove_root(&mut self, name: &str) -> bool {
+        self.roots.borrow_mut().entries.remove(name).is_some()
+    }
+
+    /// Iterates over the names of the saved roots, in order
+    pub fn names(&self) -> impl Iterator<Item = String> {
+        let names: Vec<String> =
+            self.roots.borrow().entries.keys().cloned().collect();
+        names.into_iter()
+    }
+
+    /// Returns the store the maps of the registry are written to
+    pub(crate) fn store(&self) -> &StoreRef<I> {
+        &self.store
+    }
+
+    /// Returns the root saved under `name`, along with its revision
+    pub(crate) fn revision_of<T>(
+        &self,
+        name: &str,
+    ) -> Option<(Stored<T, I>, u64)> {
+        self.roots.borrow().entries.get(name).map(|(id, revision)| {
+            let root = Stored::new(self.store.clone(), Ident::new(id.clone()));
+            (root, *revision)
+        })
+    }
+
+    /// Saves `root` under `name` only if the root saved under it is still
+    /// at `revision`, `None` standing for no root, returning true if saved
+    pub(crate) fn replace_root<T>(
+        &self,
+        name: &str,
+        revision: Option<u64>,
+        root: &Stored<T, I>,
+    ) -> bool {
+        let mut roots = self.roots.borrow_mut();
+        let current = roots.entries.get(name).map(|(_, revision)| *revision);
+        if current != revision {
+            return false;
+        }
+        roots.save(name.into(), root.ident().erase().clone());
+        true
+    }
+
+    /// Writes the registry to the store, returning the identifier to
+    /// [`open`](Self::open) it again with
+    pub fn commit(&self) -> I {
+        let entries: Vec<RootEntry<I>> = self
+            .roots
+            .borrow()
+            .entries
+            .iter()
+            .map(|(name, (root, _))| RootEntry {
+                name: name.clone(),
+                root: root.clone(),
+            })
+            .collect();
+        self.store.put(&entries).erase().clone()
+    }
+
+    /// Writes the registry to the store, then persists the store, returning
+    /// the identifier to [`open`](Self::open) the registry again with
+    ///
+    /// The identifier is only returned once the table and every map written
+    /// to the store before it are durable, so recording it is the single
+    /// switch-over committing all the maps at once.
+    pub fn persist(&self) -> Result<I, PersistError> {
+        let ident = self.commit();
+        self.store.persist().map_err(|_| PersistError)?;
+        Ok(ident)
+    }
+}
@@ -0,0 +1,160 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Values stored with the version of their layout, upgraded on read
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, MaybeArchived};
+use rkyv::rend::LittleEndian;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{Hamt, KvPair, Value};
+
+/// A value whose layout changes over time, stored as bytes tagged with
+/// the version of their layout
+///
+/// Values encoded with an older layout are handed to
+/// [`migrate`](Migrate::migrate) when read, so maps written before an
+/// upgrade keep being readable after it, without rewriting them.
+pub trait Migrate: Sized {
+    /// The version of the current layout
+    const VERSION: u16;
+
+    /// Encodes the value in the current layout
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decodes bytes in the layout of `version`, upgrading them to the
+    /// current one, or returns `None` if they cannot be decoded
+    fn migrate(version: u16, bytes: &[u8]) -> Option<Self>;
+}
+
+/// A value stored as up to `N` bytes in the layout of a given version, see
+/// [`Migrate`]
+///
+/// The bytes are stored inline rather than in a separate allocation, since
+/// stores keep every node and its leaves in a single contiguous region.
+#[derive(Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct Upgradable<V, const N: usize> {
+    version: LittleEndian<u16>,
+    len: LittleEndian<u16>,
+    bytes: [u8; N],
+    _marker: PhantomData<V>,
+}
+
+impl<V, const N: usize> Clone for Upgradable<V, N> {
+    fn clone(&self) -> Self {
+        Upgradable {
+            version: self.version,
+            len: self.len,
+            bytes: self.bytes,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<V, const N: usize> PartialEq for Upgradable<V, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version && self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<V, const N: usize> Eq for Upgradable<V, N> {}
+
+impl<V, const N: usize> fmt::Debug for Upgradable<V, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgradable")
+            .field("version", &self.version.value())
+            .field("bytes", &self.as_bytes())
+            .finish()
+    }
+}
+
+impl<V, const N: usize> Upgradable<V, N> {
+    /// Wraps bytes in the layout of `version`
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than `N` bytes.
+    pub fn from_parts(version: u16, bytes: &[u8]) -> Self {
+        assert!(bytes.len() <= N, "the encoding exceeds the capacity");
+        let mut inline = [0; N];
+        inline[..bytes.len()].copy_from_slice(bytes);
+        Upgradable {
+            version: version.into(),
+            len: (bytes.len() as u16).into(),
+            bytes: inline,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the version of the layout of the bytes
+    pub fn version(&self) -> u16 {
+        self.version.value()
+    }
+
+    /// Returns the bytes of the value
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len.value() as usize]
+    }
+}
+
+impl<V, const N: usize> Upgradable<V, N>
+where
+    V: Migrate,
+{
+    /// Encodes `val` in the current layout
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoding takes more than `N` bytes.
+    pub fn new(val: &V) -> Self {
+        Self::from_parts(V::VERSION, &val.encode())
+    }
+
+    /// Decodes the value, upgrading it if stored in an older layout
+    pub fn decode(&self) -> Option<V> {
+        V::migrate(self.version(), self.as_bytes())
+    }
+}
+
+impl<V, const N: usize> ArchivedUpgradable<V, N>
+where
+    V: Migrate,
+{
+    /// Returns the version of the layout of the bytes
+    pub fn version(&self) -> u16 {
+        self.version.value()
+    }
+
+    /// Decodes the value, upgrading it if stored in an older layout
+    pub fn decode(&self) -> Option<V> {
+        let len = usize::from(self.len.value()).min(N);
+        V::migrate(self.version(), &self.bytes[..len])
+    }
+}
+
+impl<'a, K, V, A, I, H, const N: usize> Value<'a, K, Upgradable<V, N>, A, I, H>
+where
+    K: Archive<Archived = K>,
+    V: Migrate,
+    A: Annotation<KvPair<K, Upgradable<V, N>>>,
+    <Hamt<K, Upgradable<V, N>, A, I, H> as Archive>::Archived:
+        ArchivedCompound<Hamt<K, Upgradable<V, N>, A, I, H>, A, I>,
+{
+    /// Decodes the value, in memory or archived, upgrading it if stored in
+    /// an older layout
+    pub fn decode(&self) -> Option<V> {
+        match self.leaf() {
+            MaybeArchived::Memory(val) => val.decode(),
+            MaybeArchived::Archived(val) => val.decode(),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Buffers reused across lookups
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::Hash;
+
+use crate::KeyHasher;
+
+/// Buffers for looking up many keys at once, kept across calls to
+/// [`Hamt::multi_get_with`](crate::Hamt::multi_get_with) so they are only
+/// allocated once
+pub struct LookupScratch<H>
+where
+    H: KeyHasher,
+{
+    pub(crate) digests: Vec<H::Digest>,
+    pub(crate) indices: Vec<usize>,
+}
+
+impl<H> LookupScratch<H>
+where
+    H: KeyHasher,
+{
+    /// Creates empty buffers, allocated on first use
+    pub fn new() -> Self {
+        LookupScratch {
+            digests: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Creates buffers for looking up to `keys` keys without allocating
+    pub fn with_capacity(keys: usize) -> Self {
+        LookupScratch {
+            digests: Vec::with_capacity(keys),
+            indices: Vec::with_capacity(keys),
+        }
+    }
+
+    /// Fills the buffers with the digests and indices of `keys`
+    pub(crate) fn reset<K>(&mut self, keys: &[&K])
+    where
+        K: Hash,
+    {
+        self.digests.clear();
+        self.digests.extend(keys.iter().map(|key| H::digest(*key)));
+        self.indices.clear();
+        self.indices.extend(0..keys.len());
+    }
+}
+
+impl<H> Default for LookupScratch<H>
+where
+    H: KeyHasher,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H> fmt::Debug for LookupScratch<H>
+where
+    H: KeyHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LookupScratch")
+            .field("capacity", &self.indices.capacity())
+            .finish()
+    }
+}
@@ -0,0 +1,157 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map remembering the order in which keys were inserted
+
+use alloc::vec::{IntoIter, Vec};
+use core::hash::Hash;
+use core::mem;
+
+use bytecheck::CheckBytes;
+use microkelvin::{All, ArchivedCompound, Compound, MaybeArchived, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::stamp::{get_stamped, StampedBranch, StampedMap, StampedValue};
+use crate::{Hamt, MinOf, Stamp, Stamped};
+
+/// A map stamping every new key with a monotonically increasing sequence
+/// number, to recover the order in which keys were inserted
+///
+/// The oldest entry is found through the [`MinOf`] annotation of the
+/// sequence numbers. Replacing the value of a key keeps its position.
+#[derive(Clone)]
+pub struct SequencedHamt<K, V, I> {
+    map: StampedMap<K, V, I>,
+    next: u64,
+}
+
+/// Iterator over the entries of a [`SequencedHamt`] in insertion order
+pub struct InsertionOrder<'a, K, V>(IntoIter<(&'a K, MaybeArchived<'a, V>)>)
+where
+    V: Archive;
+
+impl<'a, K, V> Iterator for InsertionOrder<'a, K, V>
+where
+    V: Archive,
+{
+    type Item = (&'a K, MaybeArchived<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<K, V, I> SequencedHamt<K, V, I>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    StampedMap<K, V, I>: Archive,
+    <StampedMap<K, V, I> as Archive>::Archived: ArchivedCompound<StampedMap<K, V, I>, MinOf<Stamp>, I>
+        + Deserialize<StampedMap<K, V, I>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates a new empty map
+    pub fn new() -> Self {
+        SequencedHamt {
+            map: Hamt::new(),
+            next: 0,
+        }
+    }
+
+    /// Inserts a value, returning the previous value stored under the same
+    /// key
+    ///
+    /// New keys are placed last in insertion order, existing keys keep their
+    /// position.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if let Some(mut branch) = self.map.get_mut(&key) {
+            return Some(mem::replace(branch.leaf_mut().value_mut(), val));
+        }
+        let seq = self.next;
+        self.next += 1;
+        self.map.insert(key, Stamped::new(seq, val));
+        None
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get(&self, key: &K) -> Option<StampedValue<'_, K, V, I>> {
+        get_stamped(&self.map, key, |_| true)
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.map.remove(key).map(Stamped::into_value)
+    }
+
+    /// Returns a branch to the oldest entry of the map
+    pub fn first(&self) -> Option<StampedBranch<'_, K, V, I>> {
+        self.map.min_of::<Stamp>()
+    }
+
+    /// Iterates over the entries of the map in the order their keys were
+    /// inserted
+    ///
+    /// The entries are collected and sorted by sequence number up front,
+    /// taking `O(n log n)` time and `O(n)` memory before the first entry is
+    /// returned. The [`MinOf`] annotation leads straight to the oldest
+    /// entry, see [`first`](Self::first), but not to the one following a
+    /// given sequence number: subtrees holding older entries may hold newer
+    /// ones too, so each step of a guided walk could have to search most of
+    /// the map.
+    pub fn iter_in_insertion_order(&self) -> InsertionOrder<'_, K, V> {
+        let mut entries: Vec<_> = match self.map.walk(All) {
+            Some(branch) => branch
+                .into_iter()
+                .map(|leaf| match leaf {
+                    MaybeArchived::Memory(kv) => (
+                        kv.val.stamp(),
+                        &kv.key,
+                        MaybeArchived::Memory(kv.val.value()),
+                    ),
+                    MaybeArchived::Archived(kv) => (
+                        kv.val.stamp(),
+                        &kv.key,
+                        MaybeArchived::Archived(kv.val.value()),
+                    ),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        entries.sort_unstable_by_key(|(seq, _, _)| *seq);
+
+        let ordered: Vec<_> = entries
+            .into_iter()
+            .map(|(_, key, val)| (key, val))
+            .collect();
+
+        InsertionOrder(ordered.into_iter())
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &StampedMap<K, V, I> {
+        &self.map
+    }
+}
+
+impl<K, V, I> Default for SequencedHamt<K, V, I>
+where
+    V: Archive,
+{
+    fn default() -> Self {
+        SequencedHamt {
+            map: Default::default(),
+            next: 0,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map scanning a flat array of pairs while small
+
+use core::hash::Hash;
+use core::mem;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, MaybeArchived, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::node::NodeRef;
+use crate::{Hamt, KeyHasher, KvPair, SeaHash};
+
+#[derive(Clone)]
+enum Repr<K, V, A, I, const N: usize> {
+    Small([Option<KvPair<K, V>>; N]),
+    Trie(Hamt<K, V, A, I>),
+}
+
+/// A map keeping up to `N` entries in a flat array, scanned linearly
+///
+/// Small maps pay neither for hashing nor for allocating nodes. Once an
+/// insertion exceeds `N` entries the map converts itself into a [`Hamt`],
+/// and stays one from then on.
+#[derive(Clone)]
+pub struct SmallHamt<K, V, A, I, const N: usize> {
+    repr: Repr<K, V, A, I, N>,
+    len: usize,
+}
+
+impl<K, V, A, I, const N: usize> SmallHamt<K, V, A, I, N> {
+    const VACANT: Option<KvPair<K, V>> = None;
+
+    /// Creates a new empty map
+    pub fn new() -> Self {
+        SmallHamt {
+            repr: Repr::Small([Self::VACANT; N]),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the map holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the map has been converted into a trie
+    pub fn is_trie(&self) -> bool {
+        matches!(self.repr, Repr::Trie(_))
+    }
+}
+
+impl<K, V, A, I, const N: usize> SmallHamt<K, V, A, I, N>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I>: Archive,
+    <Hamt<K, V, A, I> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I>, A, I>
+        + Deserialize<Hamt<K, V, A, I>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    fn drain(pairs: &mut [Option<KvPair<K, V>>]) -> Hamt<K, V, A, I> {
+        let mut map = Hamt::new();
+        for kv in pairs.iter_mut() {
+            if let Some(KvPair { key, val }) = kv.take() {
+                map.insert(key, val);
+            }
+        }
+        map
+    }
+
+    /// Inserts a key-value pair, returning the previous value under `key`
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let pairs = match &mut self.repr {
+            Repr::Trie(map) => {
+                let old = map.insert(key, val);
+                if old.is_none() {
+                    self.len += 1;
+                }
+                return old;
+            }
+            Repr::Small(pairs) => pairs,
+        };
+
+        for kv in pairs.iter_mut().flatten() {
+            if kv.key == key {
+                return Some(mem::replace(&mut kv.val, val));
+            }
+        }
+
+        self.len += 1;
+        match pairs.iter_mut().find(|kv| kv.is_none()) {
+            Some(vacant) => *vacant = Some(KvPair::new(key, val)),
+            None => {
+                let mut map = Self::drain(pairs);
+                map.insert(key, val);
+                self.repr = Repr::Trie(map);
+            }
+        }
+        None
+    }
+
+    /// Returns the value stored under `key`, either in memory or archived
+    pub fn get(&self, key: &K) -> Option<MaybeArchived<'_, V>> {
+        match &self.repr {
+            Repr::Small(pairs) => pairs
+                .iter()
+                .flatten()
+                .find(|kv| kv.key == *key)
+                .map(|kv| MaybeArchived::Memory(&kv.val)),
+            Repr::Trie(map) => {
+                NodeRef::Memory(map).find(key, SeaHash::digest(key), 0)
+            }
+        }
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let val = match &mut self.repr {
+            Repr::Small(pairs) => pairs
+                .iter_mut()
+                .find(|kv| matches!(kv, Some(kv) if kv.key == *key))
+                .and_then(Option::take)
+                .map(|kv| kv.val),
+            Repr::Trie(map) => map.remove(key),
+        }?;
+        self.len -= 1;
+        Some(val)
+    }
+
+    /// Converts the map into a [`Hamt`], for instance to persist it
+    pub fn into_hamt(self) -> Hamt<K, V, A, I> {
+        match self.repr {
+            Repr::Trie(map) => map,
+            Repr::Small(mut pairs) => Self::drain(&mut pairs),
+        }
+    }
+}
+
+impl<K, V, A, I, const N: usize> Default for SmallHamt<K, V, A, I, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,150 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Values tagged with a stamp, such as an access counter or a deadline
+
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    ArchivedCompound, Branch, Compound, MappedBranch, MaybeArchived,
+};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{Hamt, MinOf, PathWalker, Projection, SeaHash};
+
+/// A map of stamped values, annotated with the smallest stamp
+pub(crate) type StampedMap<K, V, I> = Hamt<K, Stamped<V>, MinOf<Stamp>, I>;
+
+/// A branch to an entry of a [`StampedMap`]
+pub(crate) type StampedBranch<'a, K, V, I> =
+    Branch<'a, StampedMap<K, V, I>, MinOf<Stamp>, I>;
+
+/// A branch to the value of an entry of a [`StampedMap`]
+pub(crate) type StampedValue<'a, K, V, I> = MappedBranch<
+    'a,
+    StampedMap<K, V, I>,
+    MinOf<Stamp>,
+    I,
+    MaybeArchived<'a, V>,
+>;
+
+/// A value together with a stamp
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct Stamped<V> {
+    pub(crate) stamp: u64,
+    val: V,
+}
+
+impl<V> Stamped<V> {
+    /// Creates a new stamped value
+    pub fn new(stamp: u64, val: V) -> Self {
+        Stamped { stamp, val }
+    }
+
+    /// Returns the stamp
+    pub fn stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    /// Returns a reference to the value
+    pub fn value(&self) -> &V {
+        &self.val
+    }
+
+    /// Returns a mutable reference to the value
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.val
+    }
+
+    /// Consumes the stamped value, returning the value
+    pub fn into_value(self) -> V {
+        self.val
+    }
+}
+
+impl<V> ArchivedStamped<V>
+where
+    V: Archive,
+{
+    /// Returns the stamp
+    pub fn stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    /// Returns a reference to the archived value
+    pub fn value(&self) -> &V::Archived {
+        &self.val
+    }
+}
+
+/// The stamp of a [`Stamped`] value, projected to be tracked by
+/// [`MaxOf`](crate::MaxOf) or [`MinOf`](crate::MinOf)
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Archive,
+    Serialize,
+    Deserialize,
+    CheckBytes,
+)]
+#[archive(as = "Self")]
+pub struct Stamp(pub u64);
+
+impl<K, V> Projection<K, Stamped<V>> for Stamp
+where
+    V: Archive,
+{
+    fn project(_: &K, val: &Stamped<V>) -> Self {
+        Stamp(val.stamp)
+    }
+
+    fn project_archived(_: &K, val: &ArchivedStamped<V>) -> Self {
+        Stamp(val.stamp())
+    }
+}
+
+/// Looks up the value stored under `key`, if its stamp satisfies `live`
+pub(crate) fn get_stamped<'a, K, V, I, F>(
+    map: &'a StampedMap<K, V, I>,
+    key: &K,
+    live: F,
+) -> Option<StampedValue<'a, K, V, I>>
+where
+    K: Archive<Archived = K> + Eq + Hash,
+    V: Archive,
+    StampedMap<K, V, I>: Archive,
+    <StampedMap<K, V, I> as Archive>::Archived: ArchivedCompound<StampedMap<K, V, I>, MinOf<Stamp>, I>
+        + for<'any> CheckBytes<DefaultValidator<'any>>,
+    F: Fn(u64) -> bool,
+{
+    map.walk(PathWalker::<SeaHash>::new(key))
+        .filter(|b| match b.leaf() {
+            MaybeArchived::Memory(kv) => {
+                *kv.key() == *key && live(kv.val.stamp())
+            }
+            MaybeArchived::Archived(kv) => {
+                kv.key == *key && live(kv.val.stamp())
+            }
+        })
+        .map(|branch| {
+            branch.map_leaf(|kv| match kv {
+                MaybeArchived::Memory(kv) => {
+                    MaybeArchived::Memory(kv.val.value())
+                }
+                MaybeArchived::Archived(kv) => {
+                    MaybeArchived::Archived(kv.val.value())
+                }
+            })
+        })
+}
@@ -0,0 +1,218 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Maps generated ahead of time and kept in static memory
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::Hash;
+use core::mem;
+
+use crate::{slot, KeyHasher, SeaHash};
+
+/// A slot of a node of a [`StaticHamt`]
+///
+/// Public to be constructed by the code written by [`StaticHamtBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaticSlot<K, V> {
+    /// Empty slot
+    Empty,
+    /// A key-value pair
+    Leaf(K, V),
+    /// The index of a child node
+    Node(usize),
+}
+
+/// A node of a [`StaticHamt`]
+pub type StaticNode<K, V> = [StaticSlot<K, V>; 4];
+
+/// A read-only map over nodes in static memory
+///
+/// The nodes are generated ahead of time with a [`StaticHamtBuilder`],
+/// typically from a build script, so the map costs nothing to construct.
+/// Keys take the same paths as in a [`Hamt`](crate::Hamt) using the default
+/// key hasher.
+pub struct StaticHamt<K: 'static, V: 'static> {
+    nodes: &'static [StaticNode<K, V>],
+}
+
+impl<K, V> StaticHamt<K, V> {
+    /// Creates a map over `nodes`, the first one being the root
+    pub const fn new(nodes: &'static [StaticNode<K, V>]) -> Self {
+        StaticHamt { nodes }
+    }
+
+    /// Iterates over the entries of the map, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&'static K, &'static V)> {
+        self.nodes.iter().flatten().filter_map(|slot| match slot {
+            StaticSlot::Leaf(key, val) => Some((key, val)),
+            _ => None,
+        })
+    }
+}
+
+impl<K, V> StaticHamt<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns a reference to the value stored under `key`
+    pub fn get(&self, key: &K) -> Option<&'static V> {
+        let digest = SeaHash::digest(key);
+        let mut node = self.nodes.first()?;
+        let mut depth = 0;
+
+        loop {
+            match &node[slot::<SeaHash>(digest, depth)] {
+                StaticSlot::Leaf(k, v) if k == key => return Some(v),
+                StaticSlot::Node(child) => {
+                    node = &self.nodes[*child];
+                    depth += 1;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl<K, V> Clone for StaticHamt<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for StaticHamt<K, V> {}
+
+/// Builds the nodes of a [`StaticHamt`] and writes them out as Rust source
+pub struct StaticHamtBuilder<K, V> {
+    nodes: Vec<StaticNode<K, V>>,
+}
+
+impl<K, V> StaticHamtBuilder<K, V> {
+    /// Creates a builder for an empty map
+    pub fn new() -> Self {
+        StaticHamtBuilder {
+            nodes: vec![Self::empty()],
+        }
+    }
+
+    fn empty() -> StaticNode<K, V> {
+        [
+            StaticSlot::Empty,
+            StaticSlot::Empty,
+            StaticSlot::Empty,
+            StaticSlot::Empty,
+        ]
+    }
+
+    /// Returns the nodes built, the first one being the root
+    pub fn into_nodes(self) -> Vec<StaticNode<K, V>> {
+        self.nodes
+    }
+
+    /// Writes the nodes built as a Rust expression of type
+    /// `&'static [StaticNode<K, V>]`, to be passed to [`StaticHamt::new`]
+    ///
+    /// Keys and values are written out as Rust expressions by `key` and
+    /// `val` respectively.
+    pub fn write<W, FK, FV>(
+        &self,
+        out: &mut W,
+        mut key: FK,
+        mut val: FV,
+    ) -> fmt::Result
+    where
+        W: fmt::Write,
+        FK: FnMut(&mut W, &K) -> fmt::Result,
+        FV: FnMut(&mut W, &V) -> fmt::Result,
+    {
+        writeln!(out, "&[")?;
+        for node in self.nodes.iter() {
+            write!(out, "    [")?;
+            for (i, slot) in node.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                match slot {
+                    StaticSlot::Empty => {
+                        write!(out, "::dusk_hamt::StaticSlot::Empty")?
+                    }
+                    StaticSlot::Leaf(k, v) => {
+                        write!(out, "::dusk_hamt::StaticSlot::Leaf(")?;
+                        key(out, k)?;
+                        write!(out, ", ")?;
+                        val(out, v)?;
+                        write!(out, ")")?;
+                    }
+                    StaticSlot::Node(child) => {
+                        write!(out, "::dusk_hamt::StaticSlot::Node({})", child)?
+                    }
+                }
+            }
+            writeln!(out, "],")?;
+        }
+        write!(out, "]")
+    }
+}
+
+impl<K, V> StaticHamtBuilder<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Inserts a key-value pair, returning the previous value under `key`
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let digest = SeaHash::digest(&key);
+        self._insert(0, key, val, digest, 0)
+    }
+
+    fn _insert(
+        &mut self,
+        node: usize,
+        key: K,
+        val: V,
+        digest: u64,
+        depth: usize,
+    ) -> Option<V> {
+        let ofs = slot::<SeaHash>(digest, depth);
+        match &mut self.nodes[node][ofs] {
+            StaticSlot::Empty => {
+                self.nodes[node][ofs] = StaticSlot::Leaf(key, val);
+                None
+            }
+            StaticSlot::Leaf(old_key, old_val) if *old_key == key => {
+                Some(mem::replace(old_val, val))
+            }
+            StaticSlot::Leaf(..) => {
+                let child = self.nodes.len();
+                self.nodes.push(Self::empty());
+                let old = mem::replace(
+                    &mut self.nodes[node][ofs],
+                    StaticSlot::Node(child),
+                );
+                if let StaticSlot::Leaf(old_key, old_val) = old {
+                    let old_digest = SeaHash::digest(&old_key);
+                    self._insert(
+                        child,
+                        old_key,
+                        old_val,
+                        old_digest,
+                        depth + 1,
+                    );
+                }
+                self._insert(child, key, val, digest, depth + 1)
+            }
+            StaticSlot::Node(child) => {
+                let child = *child;
+                self._insert(child, key, val, digest, depth + 1)
+            }
+        }
+    }
+}
+
+impl<K, V> Default for StaticHamtBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
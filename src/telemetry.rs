@@ -0,0 +1,125 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Counters of the traffic between maps and their store
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use microkelvin::{Store, Token, TokenBuffer};
+
+/// A snapshot of the counters of a [`Telemetry`] handle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Number of nodes read from the store
+    pub reads: u64,
+    /// Number of bytes read from the store
+    pub bytes_read: u64,
+    /// Number of nodes written to the store
+    pub writes: u64,
+    /// Number of bytes written to the store
+    pub bytes_written: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    reads: AtomicU64,
+    bytes_read: AtomicU64,
+    writes: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// A handle to the counters of a [`CountingStore`], kept after the store is
+/// moved into a `StoreRef`
+#[derive(Clone, Default)]
+pub struct Telemetry(Arc<Counters>);
+
+impl Telemetry {
+    /// Returns the current value of the counters
+    pub fn stats(&self) -> StoreStats {
+        StoreStats {
+            reads: self.0.reads.load(Ordering::Relaxed),
+            bytes_read: self.0.bytes_read.load(Ordering::Relaxed),
+            writes: self.0.writes.load(Ordering::Relaxed),
+            bytes_written: self.0.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets all counters to zero
+    pub fn reset(&self) {
+        self.0.reads.store(0, Ordering::Relaxed);
+        self.0.bytes_read.store(0, Ordering::Relaxed);
+        self.0.writes.store(0, Ordering::Relaxed);
+        self.0.bytes_written.store(0, Ordering::Relaxed);
+    }
+}
+
+/// A store counting the reads and writes going through to another store
+///
+/// Giving each map its own `StoreRef` over a counting store yields
+/// statistics per map. The stores of this crate read nodes in place rather
+/// than through a cache, so every read reaches the inner store.
+pub struct CountingStore<S> {
+    inner: S,
+    telemetry: Telemetry,
+}
+
+impl<S> CountingStore<S> {
+    /// Wraps `inner`, with all counters at zero
+    pub fn new(inner: S) -> Self {
+        CountingStore {
+            inner,
+            telemetry: Telemetry::default(),
+        }
+    }
+
+    /// Returns a handle to the counters
+    pub fn telemetry(&self) -> Telemetry {
+        self.telemetry.clone()
+    }
+}
+
+impl<S> Store for CountingStore<S>
+where
+    S: Store,
+{
+    type Identifier = S::Identifier;
+
+    fn get(&self, ident: &Self::Identifier) -> &[u8] {
+        let bytes = self.inner.get(ident);
+        let counters = &self.telemetry.0;
+        counters.reads.fetch_add(1, Ordering::Relaxed);
+        counters
+            .bytes_read
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        bytes
+    }
+
+    fn request_buffer(&self) -> TokenBuffer {
+        self.inner.request_buffer()
+    }
+
+    fn persist(&self) -> Result<(), ()> {
+        self.inner.persist()
+    }
+
+    fn commit(&self, buffer: &mut TokenBuffer) -> Self::Identifier {
+        let counters = &self.telemetry.0;
+        counters.writes.fetch_add(1, Ordering::Relaxed);
+        counters
+            .bytes_written
+            .fetch_add(buffer.written_bytes().len() as u64, Ordering::Relaxed);
+        self.inner.commit(buffer)
+    }
+
+    fn extend(&self, buffer: &mut TokenBuffer) -> Result<(), ()> {
+        self.inner.extend(buffer)
+    }
+
+    fn return_token(&self, token: Token) {
+        self.inner.return_token(token)
+    }
+}
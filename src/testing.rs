@@ -0,0 +1,297 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Utilities to test code built on maps against a reference model
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, MaybeArchived, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Infallible};
+
+use crate::node::{EntryRef, NodeRef};
+use crate::{slot, DigestOrder, Hamt, KeyHasher, KvPair};
+
+/// An operation on a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<K, V> {
+    /// Inserts a value under a key
+    Insert(K, V),
+    /// Removes a key
+    Remove(K),
+}
+
+impl<K, V> Op<K, V> {
+    /// Converts the key and value of the operation
+    pub fn map<K2, V2, F, G>(self, mut f: F, mut g: G) -> Op<K2, V2>
+    where
+        F: FnMut(K) -> K2,
+        G: FnMut(V) -> V2,
+    {
+        match self {
+            Op::Insert(key, val) => Op::Insert(f(key), g(val)),
+            Op::Remove(key) => Op::Remove(f(key)),
+        }
+    }
+}
+
+/// A deterministic stream of random operations over a bounded key space
+///
+/// Keys are drawn below `keys`, so that insertions replace values and
+/// removals hit present keys often enough. Two insertions are generated
+/// for every removal, letting maps grow. Use [`Op::map`] to turn the
+/// generated integers into the keys and values of the map under test.
+#[derive(Debug, Clone)]
+pub struct Ops {
+    state: u64,
+    keys: u64,
+}
+
+impl Ops {
+    /// Creates a stream of operations from `seed`, with keys below `keys`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` is zero.
+    pub fn new(seed: u64, keys: u64) -> Self {
+        assert!(keys > 0, "the key space must not be empty");
+        Ops {
+            // xorshift never leaves the zero state
+            state: seed | 1,
+            keys,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl Iterator for Ops {
+    type Item = Op<u64, u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let draw = self.next_u64();
+        let key = self.next_u64() % self.keys;
+        Some(match draw % 3 {
+            0 => Op::Remove(key),
+            _ => Op::Insert(key, draw >> 2),
+        })
+    }
+}
+
+/// A map kept as a plain list of entries, the reference to compare maps
+/// against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model<K, V>(Vec<(K, V)>);
+
+impl<K, V> Default for Model<K, V> {
+    fn default() -> Self {
+        Model(Vec::new())
+    }
+}
+
+impl<K, V> Model<K, V>
+where
+    K: Eq,
+{
+    /// Creates a new empty model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `val` under `key`, returning the value it replaces
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => Some(core::mem::replace(v, val)),
+            None => {
+                self.0.push((key, val));
+                None
+            }
+        }
+    }
+
+    /// Removes `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let pos = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.swap_remove(pos).1)
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the model holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+
+    /// Applies `op`, returning the value it replaces or removes
+    pub fn apply(&mut self, op: Op<K, V>) -> Option<V> {
+        match op {
+            Op::Insert(key, val) => self.insert(key, val),
+            Op::Remove(key) => self.remove(&key),
+        }
+    }
+}
+
+/// A way in which a map differs from its [`Model`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence<K> {
+    /// An operation on the key returned another value than on the model
+    Returned(K),
+    /// The key holds another value than in the model, or is missing
+    Value(K),
+    /// The map holds another number of entries than the model
+    Len {
+        /// Number of entries in the map
+        map: usize,
+        /// Number of entries in the model
+        model: usize,
+    },
+}
+
+/// A broken structural invariant of a map, see [`check_invariants`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// A leaf lies off the path its key hashes to, at the given path
+    Misplaced(Vec<usize>),
+    /// A node below the root is empty or holds a single leaf and no other
+    /// node, at the given path
+    Degenerate(Vec<usize>),
+}
+
+/// Applies `op` to both `map` and `model`, failing if they return
+/// different values
+pub fn apply<K, V, A, I, H>(
+    map: &mut Hamt<K, V, A, I, H>,
+    model: &mut Model<K, V>,
+    op: Op<K, V>,
+) -> Result<(), Divergence<K>>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone + PartialEq,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I, H>: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+        + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    let (key, returned) = match op.clone() {
+        Op::Insert(key, val) => (key.clone(), map.insert(key, val)),
+        Op::Remove(key) => {
+            let returned = map.remove(&key);
+            (key, returned)
+        }
+    };
+    if returned == model.apply(op) {
+        Ok(())
+    } else {
+        Err(Divergence::Returned(key))
+    }
+}
+
+/// Checks that `map` holds exactly the entries of `model`
+pub fn check_model<K, V, A, I, H>(
+    map: &Hamt<K, V, A, I, H>,
+    model: &Model<K, V>,
+) -> Result<(), Divergence<K>>
+where
+    K: Archive<Archived = K> + Clone + Eq,
+    V: Archive + Clone + PartialEq,
+    V::Archived: Deserialize<V, Infallible>,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    let entries: Vec<(&K, V)> = DigestOrder::new(map)
+        .map(|(key, val)| match val {
+            MaybeArchived::Memory(val) => (key, val.clone()),
+            MaybeArchived::Archived(val) => (
+                key,
+                val.deserialize(&mut Infallible)
+                    .expect("deserializing is infallible"),
+            ),
+        })
+        .collect();
+
+    if entries.len() != model.len() {
+        return Err(Divergence::Len {
+            map: entries.len(),
+            model: model.len(),
+        });
+    }
+    for (key, val) in entries {
+        if model.get(key) != Some(&val) {
+            return Err(Divergence::Value(key.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the structural invariants of `map`, in memory or stored
+///
+/// Every leaf must lie on the path its key hashes to, and no node below
+/// the root may be degenerate, as removals collapse them.
+pub fn check_invariants<K, V, A, I, H>(
+    map: &Hamt<K, V, A, I, H>,
+) -> Result<(), InvariantError>
+where
+    K: Archive<Archived = K> + Hash,
+    V: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    let mut stack = vec![(NodeRef::Memory(map), Vec::new())];
+
+    while let Some((node, path)) = stack.pop() {
+        if !path.is_empty() && node.is_degenerate() {
+            return Err(InvariantError::Degenerate(path));
+        }
+        for ofs in 0..4 {
+            let mut child_path = path.clone();
+            child_path.push(ofs);
+            match node.entry(ofs) {
+                EntryRef::Empty => (),
+                EntryRef::Leaf(key, _) => {
+                    let digest = H::digest(key);
+                    let on_path = child_path
+                        .iter()
+                        .enumerate()
+                        .all(|(depth, ofs)| slot::<H>(digest, depth) == *ofs);
+                    if !on_path {
+                        return Err(InvariantError::Misplaced(child_path));
+                    }
+                }
+                EntryRef::Node(child) => stack.push((child, child_path)),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,256 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map leaving tombstones behind removed keys, to merge replicas
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{
+    All, ArchivedCompound, Compound, Keyed, MappedBranch, MaybeArchived,
+    StoreRef,
+};
+use rkyv::option::ArchivedOption;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Infallible};
+
+use crate::commitment::encoding;
+use crate::stamp::StampedMap;
+use crate::{
+    CommitmentEncode, Hamt, KvPair, MinOf, PathWalker, SeaHash, Stamp, Stamped,
+};
+
+/// A branch to a live value of a [`TombstoneHamt`]
+pub(crate) type LiveValue<'a, K, V, I> = MappedBranch<
+    'a,
+    StampedMap<K, Option<V>, I>,
+    MinOf<Stamp>,
+    I,
+    MaybeArchived<'a, V>,
+>;
+
+/// A map of replicated entries, where removals leave a tombstone tagged with
+/// the sequence number of the removal
+///
+/// Every insertion and removal is tagged with a sequence number, such as a
+/// block height or a Lamport clock. [`TombstoneHamt::merge`] keeps, for
+/// each key, the change with the highest sequence number, so a removal on
+/// one replica is not undone by merging an older value from another.
+/// Tombstones are kept until dropped by
+/// [`TombstoneHamt::purge_tombstones`].
+#[derive(Clone)]
+pub struct TombstoneHamt<K, V, I>(StampedMap<K, Option<V>, I>);
+
+impl<K, V, I> TombstoneHamt<K, V, I>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived:
+        Deserialize<V, Infallible> + for<'a> CheckBytes<DefaultValidator<'a>>,
+    StampedMap<K, Option<V>, I>: Archive,
+    <StampedMap<K, Option<V>, I> as Archive>::Archived: ArchivedCompound<StampedMap<K, Option<V>, I>, MinOf<Stamp>, I>
+        + Deserialize<StampedMap<K, Option<V>, I>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates a new empty map
+    pub fn new() -> Self {
+        TombstoneHamt(Hamt::new())
+    }
+
+    /// Stores `val` under `key` at sequence number `seq`, returning the
+    /// live value it replaces
+    pub fn insert(&mut self, key: K, val: V, seq: u64) -> Option<V> {
+        self.0
+            .insert(key, Stamped::new(seq, Some(val)))
+            .and_then(Stamped::into_value)
+    }
+
+    /// Replaces the entry under `key` by a tombstone tagged with `seq`,
+    /// returning the live value it replaces
+    pub fn remove(&mut self, key: K, seq: u64) -> Option<V> {
+        self.0
+            .insert(key, Stamped::new(seq, None))
+            .and_then(Stamped::into_value)
+    }
+
+    /// Returns the value stored under `key`, unless it was removed
+    pub fn get(&self, key: &K) -> Option<LiveValue<'_, K, V, I>> {
+        self.0
+            .walk(PathWalker::<SeaHash>::new(key))
+            .filter(|b| matches!(status(b.leaf()), (k, _, true) if k == key))
+            .map(|branch| {
+                branch.map_leaf(|kv| match kv {
+                    MaybeArchived::Memory(kv) => match kv.val.value() {
+                        Some(val) => MaybeArchived::Memory(val),
+                        None => unreachable!("Tombstones are filtered out"),
+                    },
+                    MaybeArchived::Archived(kv) => match kv.val.value() {
+                        ArchivedOption::Some(val) => {
+                            MaybeArchived::Archived(val)
+                        }
+                        ArchivedOption::None => {
+                            unreachable!("Tombstones are filtered out")
+                        }
+                    },
+                })
+            })
+    }
+
+    /// Returns the sequence number of the tombstone left under `key`, if
+    /// the key was removed
+    pub fn tombstone(&self, key: &K) -> Option<u64> {
+        match self.entry(key) {
+            Some((seq, false)) => Some(seq),
+            _ => None,
+        }
+    }
+
+    /// Merges the entries and tombstones of `other` into this map
+    ///
+    /// For each key, the change with the highest sequence number wins.
+    /// When a removal and an insertion share a sequence number the removal
+    /// wins, and between two insertions the value with the greatest
+    /// [`CommitmentEncode`] encoding wins, so merging replicas in any order
+    /// yields the same map.
+    pub fn merge(&mut self, other: &Self)
+    where
+        V: CommitmentEncode,
+    {
+        let incoming: Vec<(K, Stamped<Option<V>>)> = match other.0.walk(All) {
+            Some(branch) => branch
+                .into_iter()
+                .map(|leaf| match leaf {
+                    MaybeArchived::Memory(kv) => {
+                        (kv.key().clone(), kv.val.clone())
+                    }
+                    MaybeArchived::Archived(kv) => (
+                        kv.key().clone(),
+                        Stamped::new(
+                            kv.val.stamp(),
+                            kv.val
+                                .value()
+                                .deserialize(&mut Infallible)
+                                .expect("deserializing is infallible"),
+                        ),
+                    ),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        for (key, change) in incoming {
+            let wins = match self.entry(&key) {
+                None => true,
+                Some((seq, _)) if change.stamp() != seq => change.stamp() > seq,
+                Some((_, false)) => false,
+                Some((_, true)) => match change.value() {
+                    None => true,
+                    Some(val) => Some(encoding(val)) > self.encoded(&key),
+                },
+            };
+            if wins {
+                self.0.insert(key, change);
+            }
+        }
+    }
+
+    /// Drops the tombstones with a sequence number below `before`,
+    /// returning how many were dropped
+    ///
+    /// Only tombstones that every replica has merged should be dropped,
+    /// or a replica still holding the removed value brings it back.
+    pub fn purge_tombstones(&mut self, before: u64) -> usize {
+        let stale: Vec<K> = match self.0.walk(All) {
+            Some(branch) => branch
+                .into_iter()
+                .filter_map(|leaf| match status(leaf) {
+                    (key, seq, false) if seq < before => Some(key.clone()),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let keys: Vec<&K> = stale.iter().collect();
+        self.0.multi_remove(&keys);
+        stale.len()
+    }
+
+    /// Returns the underlying map, tombstones included
+    pub fn as_map(&self) -> &StampedMap<K, Option<V>, I> {
+        &self.0
+    }
+
+    /// Returns the encoding of the live value under `key`, if any
+    fn encoded(&self, key: &K) -> Option<Vec<u8>>
+    where
+        V: CommitmentEncode,
+    {
+        let branch = self.0.walk(PathWalker::<SeaHash>::new(key))?;
+        match branch.leaf() {
+            MaybeArchived::Memory(kv) if kv.key() == key => {
+                kv.val.value().as_ref().map(encoding)
+            }
+            MaybeArchived::Archived(kv) if kv.key() == key => {
+                match kv.val.value() {
+                    ArchivedOption::Some(val) => {
+                        let val: V = val
+                            .deserialize(&mut Infallible)
+                            .expect("deserializing is infallible");
+                        Some(encoding(&val))
+                    }
+                    ArchivedOption::None => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the sequence number of the entry under `key`, and whether it
+    /// is live rather than a tombstone
+    fn entry(&self, key: &K) -> Option<(u64, bool)> {
+        self.0
+            .walk(PathWalker::<SeaHash>::new(key))
+            .and_then(|branch| match status(branch.leaf()) {
+                (k, seq, live) if k == key => Some((seq, live)),
+                _ => None,
+            })
+    }
+}
+
+/// Returns the key of an entry, its sequence number, and whether it is live
+/// rather than a tombstone
+fn status<K, V>(
+    leaf: MaybeArchived<'_, KvPair<K, Stamped<Option<V>>>>,
+) -> (&K, u64, bool)
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+{
+    match leaf {
+        MaybeArchived::Memory(kv) => {
+            (kv.key(), kv.val.stamp(), kv.val.value().is_some())
+        }
+        MaybeArchived::Archived(kv) => {
+            (kv.key(), kv.val.stamp(), kv.val.value().is_some())
+        }
+    }
+}
+
+impl<K, V, I> Default for TombstoneHamt<K, V, I>
+where
+    V: Archive,
+{
+    fn default() -> Self {
+        TombstoneHamt(Default::default())
+    }
+}
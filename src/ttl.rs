@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map with entries expiring at a given time
+
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{ArchivedCompound, Keyed, MaybeArchived, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::stamp::{get_stamped, StampedMap, StampedValue};
+use crate::{Hamt, MinOf, Stamp, Stamped};
+
+/// A map whose entries expire at a time given on insertion
+///
+/// Time is an opaque `u64` supplied by the caller, an entry is expired once
+/// `now` reaches its expiry. Expired entries are treated as absent by
+/// lookups and removed lazily by [`TtlHamt::purge_expired`], which finds
+/// them through the [`MinOf`] annotation of the expiries.
+#[derive(Clone)]
+pub struct TtlHamt<K, V, I>(StampedMap<K, V, I>);
+
+impl<K, V, I> TtlHamt<K, V, I>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    StampedMap<K, V, I>: Archive,
+    <StampedMap<K, V, I> as Archive>::Archived: ArchivedCompound<StampedMap<K, V, I>, MinOf<Stamp>, I>
+        + Deserialize<StampedMap<K, V, I>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates a new empty map
+    pub fn new() -> Self {
+        TtlHamt(Hamt::new())
+    }
+
+    /// Inserts a value expiring at `expiry`, returning the previous value
+    /// stored under the same key, expired or not
+    pub fn insert(&mut self, key: K, val: V, expiry: u64) -> Option<V> {
+        self.0
+            .insert(key, Stamped::new(expiry, val))
+            .map(Stamped::into_value)
+    }
+
+    /// Returns the value stored under `key` if it has not expired at `now`
+    pub fn get(&self, key: &K, now: u64) -> Option<StampedValue<'_, K, V, I>> {
+        get_stamped(&self.0, key, |expiry| expiry > now)
+    }
+
+    /// Removes the entry stored under `key`, returning its value if it has
+    /// not expired at `now`
+    pub fn remove(&mut self, key: &K, now: u64) -> Option<V> {
+        self.0
+            .remove(key)
+            .filter(|val| val.stamp() > now)
+            .map(Stamped::into_value)
+    }
+
+    /// Removes all entries expired at `now`, returning how many were removed
+    pub fn purge_expired(&mut self, now: u64) -> usize {
+        let mut purged = 0;
+        loop {
+            let key = match self.0.min_of::<Stamp>() {
+                Some(branch) => match branch.leaf() {
+                    MaybeArchived::Memory(kv) if kv.val.stamp() <= now => {
+                        kv.key().clone()
+                    }
+                    MaybeArchived::Archived(kv) if kv.val.stamp() <= now => {
+                        kv.key().clone()
+                    }
+                    _ => return purged,
+                },
+                None => return purged,
+            };
+            self.0.remove(&key);
+            purged += 1;
+        }
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &StampedMap<K, V, I> {
+        &self.0
+    }
+}
+
+impl<K, V, I> Default for TtlHamt<K, V, I>
+where
+    V: Archive,
+{
+    fn default() -> Self {
+        TtlHamt(Default::default())
+    }
+}
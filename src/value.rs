@@ -0,0 +1,239 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Handles to values found in the map
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::{Deref, DerefMut};
+
+use microkelvin::{
+    Annotation, ArchivedCompound, Branch, BranchMut, MaybeArchived,
+};
+use rkyv::{Archive, Deserialize, Fallible, Infallible};
+
+use crate::{slot, Hamt, KeyHasher, KvPair};
+
+/// A handle to a value in the map, see [`Lookup::get`](crate::Lookup::get)
+pub struct Value<'a, K, V, A, I, H>(Branch<'a, Hamt<K, V, A, I, H>, A, I>)
+where
+    K: Archive,
+    V: Archive;
+
+impl<'a, K, V, A, I, H> Value<'a, K, V, A, I, H>
+where
+    K: Archive,
+    V: Archive,
+{
+    pub(crate) fn new(branch: Branch<'a, Hamt<K, V, A, I, H>, A, I>) -> Self {
+        Value(branch)
+    }
+}
+
+impl<'a, K, V, A, I, H> Value<'a, K, V, A, I, H>
+where
+    K: Archive<Archived = K>,
+    V: Archive,
+    A: Annotation<KvPair<K, V>>,
+    <Hamt<K, V, A, I, H> as Archive>::Archived:
+        ArchivedCompound<Hamt<K, V, A, I, H>, A, I>,
+{
+    /// Returns the key of the entry
+    pub fn key(&self) -> &K {
+        match self.0.leaf() {
+            MaybeArchived::Memory(kv) => &kv.key,
+            MaybeArchived::Archived(kv) => &kv.key,
+        }
+    }
+
+    /// Returns the depth of the node holding the entry, the root being at
+    /// depth 0
+    pub fn depth(&self) -> usize {
+        self.0.depth() - 1
+    }
+
+    /// Returns the slots taken from the root to reach the entry, one per
+    /// level, the last one being the slot of the entry itself
+    pub fn path(&self) -> Vec<usize> {
+        self.0.levels().iter().map(|level| level.offset()).collect()
+    }
+
+    /// Returns the value, either in memory or archived
+    pub fn leaf(&self) -> MaybeArchived<'_, V> {
+        match self.0.leaf() {
+            MaybeArchived::Memory(kv) => MaybeArchived::Memory(&kv.val),
+            MaybeArchived::Archived(kv) => MaybeArchived::Archived(&kv.val),
+        }
+    }
+
+    /// Returns an owned copy of the value, cloning it if in memory and
+    /// deserializing it if archived
+    pub fn to_owned(&self) -> V
+    where
+        V: Clone,
+        V::Archived: Deserialize<V, Infallible>,
+    {
+        match self.leaf() {
+            MaybeArchived::Memory(val) => val.clone(),
+            MaybeArchived::Archived(val) => val
+                .deserialize(&mut Infallible)
+                .expect("deserializing is infallible"),
+        }
+    }
+
+    /// Consumes the handle, releasing the borrow of the map, and returns an
+    /// owned copy of the value
+    pub fn into_owned(self) -> V
+    where
+        V: Clone,
+        V::Archived: Deserialize<V, Infallible>,
+    {
+        self.to_owned()
+    }
+
+    /// Returns an owned copy of the value, cloning it if in memory and
+    /// deserializing it with `deserializer` if archived
+    ///
+    /// Only the value is deserialized, not the nodes above it. This is the
+    /// way to extract values needing more than [`Infallible`], such as
+    /// values holding links, which need the [`StoreRef`] of their store.
+    ///
+    /// [`StoreRef`]: microkelvin::StoreRef
+    pub fn deserialize_with<D>(
+        &self,
+        deserializer: &mut D,
+    ) -> Result<V, D::Error>
+    where
+        V: Clone,
+        V::Archived: Deserialize<V, D>,
+        D: Fallible + ?Sized,
+    {
+        match self.leaf() {
+            MaybeArchived::Memory(val) => Ok(val.clone()),
+            MaybeArchived::Archived(val) => val.deserialize(deserializer),
+        }
+    }
+}
+
+/// A mutable handle to a value in the map, see [`Hamt::get_mut`]
+pub struct ValueMut<'a, K, V, A, I, H> {
+    key: K,
+    branch: BranchMut<'a, Hamt<K, V, A, I, H>, A, I>,
+}
+
+impl<'a, K, V, A, I, H> ValueMut<'a, K, V, A, I, H> {
+    pub(crate) fn new(
+        key: K,
+        branch: BranchMut<'a, Hamt<K, V, A, I, H>, A, I>,
+    ) -> Self {
+        ValueMut { key, branch }
+    }
+
+    /// Returns the key of the entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the depth of the node holding the entry, the root being at
+    /// depth 0
+    pub fn depth(&self) -> usize {
+        self.branch.depth() - 1
+    }
+
+    /// Returns the slots taken from the root to reach the entry, one per
+    /// level, the last one being the slot of the entry itself
+    pub fn path(&self) -> Vec<usize>
+    where
+        K: Hash,
+        H: KeyHasher,
+    {
+        let digest = H::digest(&self.key);
+        (0..self.branch.depth())
+            .map(|d| slot::<H>(digest, d))
+            .collect()
+    }
+}
+
+impl<'a, K, V, A, I, H> ValueMut<'a, K, V, A, I, H>
+where
+    K: Archive + Clone,
+    V: Archive + Clone,
+    A: Annotation<KvPair<K, V>>,
+    I: Clone,
+{
+    /// Returns a mutable reference to the value
+    pub fn leaf_mut(&mut self) -> &mut V {
+        self.branch.leaf_mut().value_mut()
+    }
+}
+
+/// Writes the copy held by a [`ValueGuard`] back into the map
+type WriteBack<K, V, A, I, H> = fn(&mut Hamt<K, V, A, I, H>, &K, &mut V);
+
+/// A guard over a copy of a value in the map, see [`Hamt::get_guarded`]
+///
+/// Unlike [`ValueMut`], reaching the value does not invalidate the cached
+/// annotations along its path. The copy is written back when the guard is
+/// dropped, and only if it was mutably dereferenced, so read-mostly code
+/// does not pay for writes it does not make.
+pub struct ValueGuard<'a, K, V, A, I, H> {
+    map: &'a mut Hamt<K, V, A, I, H>,
+    key: K,
+    val: V,
+    dirty: bool,
+    write_back: WriteBack<K, V, A, I, H>,
+}
+
+impl<'a, K, V, A, I, H> ValueGuard<'a, K, V, A, I, H> {
+    pub(crate) fn new(
+        map: &'a mut Hamt<K, V, A, I, H>,
+        key: K,
+        val: V,
+        write_back: WriteBack<K, V, A, I, H>,
+    ) -> Self {
+        ValueGuard {
+            map,
+            key,
+            val,
+            dirty: false,
+            write_back,
+        }
+    }
+
+    /// Returns the key of the entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns true if the value was mutably dereferenced, and will be
+    /// written back on drop
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+impl<'a, K, V, A, I, H> Deref for ValueGuard<'a, K, V, A, I, H> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.val
+    }
+}
+
+impl<'a, K, V, A, I, H> DerefMut for ValueGuard<'a, K, V, A, I, H> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.dirty = true;
+        &mut self.val
+    }
+}
+
+impl<'a, K, V, A, I, H> Drop for ValueGuard<'a, K, V, A, I, H> {
+    fn drop(&mut self) {
+        if self.dirty {
+            (self.write_back)(self.map, &self.key, &mut self.val);
+        }
+    }
+}
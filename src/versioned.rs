@@ -0,0 +1,262 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map keeping a bounded history of the values of each key
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{ArchivedCompound, MaybeArchived, StoreRef};
+use rkyv::option::ArchivedOption;
+use rkyv::rend::LittleEndian;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Infallible, Serialize};
+
+use crate::{Hamt, Lookup};
+
+/// A map of the histories of values, see [`VersionedHamt`]
+pub(crate) type VersionedMap<K, V, I, const N: usize> =
+    Hamt<K, Versions<V, N>, (), I>;
+
+/// A value tagged with the sequence number, such as a block height, at
+/// which it was stored
+#[derive(Clone, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct Version<V> {
+    seq: u64,
+    val: V,
+}
+
+impl<V> Version<V> {
+    /// Creates a new version of a value
+    pub fn new(seq: u64, val: V) -> Self {
+        Version { seq, val }
+    }
+
+    /// Returns the sequence number the value was stored at
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns a reference to the value
+    pub fn value(&self) -> &V {
+        &self.val
+    }
+
+    /// Consumes the version, returning the value
+    pub fn into_value(self) -> V {
+        self.val
+    }
+}
+
+impl<V> ArchivedVersion<V>
+where
+    V: Archive,
+{
+    /// Returns the sequence number the value was stored at
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns a reference to the archived value
+    pub fn value(&self) -> &V::Archived {
+        &self.val
+    }
+}
+
+/// Up to `N` versions of the value of a key, oldest first
+///
+/// The versions are stored inline rather than in a separate allocation,
+/// since stores keep every node and its leaves in a single contiguous
+/// region.
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub struct Versions<V, const N: usize> {
+    len: LittleEndian<u16>,
+    versions: [Option<Version<V>>; N],
+}
+
+impl<V, const N: usize> Versions<V, N> {
+    fn new(version: Version<V>) -> Self {
+        let mut versions = [(); N].map(|_| None);
+        versions[0] = Some(version);
+        Versions {
+            len: 1.into(),
+            versions,
+        }
+    }
+
+    /// Appends `version`, dropping the oldest one if already holding `N`
+    fn push(&mut self, version: Version<V>) {
+        let len = self.len();
+        if len == N {
+            self.versions.rotate_left(1);
+            self.versions[N - 1] = Some(version);
+        } else {
+            self.versions[len] = Some(version);
+            self.len = (len as u16 + 1).into();
+        }
+    }
+
+    /// Returns the number of versions
+    pub fn len(&self) -> usize {
+        usize::from(self.len.value())
+    }
+
+    /// Returns false, a key always having a current version
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the versions, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Version<V>> {
+        self.versions[..self.len()].iter().flatten()
+    }
+
+    /// Returns the current version
+    pub fn current(&self) -> &Version<V> {
+        self.versions[self.len() - 1]
+            .as_ref()
+            .expect("a key always has a current version")
+    }
+}
+
+impl<V, const N: usize> ArchivedVersions<V, N>
+where
+    V: Archive,
+{
+    /// Iterates over the versions, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &ArchivedVersion<V>> {
+        let len = usize::from(self.len.value()).min(N);
+        self.versions[..len]
+            .iter()
+            .filter_map(|version| match version {
+                ArchivedOption::Some(version) => Some(version),
+                ArchivedOption::None => None,
+            })
+    }
+}
+
+/// A map keeping, for every key, the last `N` values stored under it along
+/// with the sequence number they were stored at
+///
+/// Older versions are dropped as new ones are stored. Removing a key drops
+/// its whole history.
+#[derive(Clone)]
+pub struct VersionedHamt<K, V, I, const N: usize> {
+    map: VersionedMap<K, V, I, N>,
+}
+
+impl<K, V, I, const N: usize> VersionedHamt<K, V, I, N>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived:
+        Deserialize<V, Infallible> + for<'a> CheckBytes<DefaultValidator<'a>>,
+    VersionedMap<K, V, I, N>: Archive,
+    <VersionedMap<K, V, I, N> as Archive>::Archived: ArchivedCompound<VersionedMap<K, V, I, N>, (), I>
+        + Deserialize<VersionedMap<K, V, I, N>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Archive + Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+{
+    /// Creates a new empty map keeping up to `N` versions of each value,
+    /// the current one included
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    pub fn new() -> Self {
+        Self::from_map(Hamt::new())
+    }
+
+    /// Keeps the histories held by `map`, such as one loaded from a store
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    pub fn from_map(map: VersionedMap<K, V, I, N>) -> Self {
+        assert!(N > 0, "at least the current version must be kept");
+        VersionedHamt { map }
+    }
+
+    /// Stores `val` under `key` at sequence number `seq`, returning the
+    /// value it replaces
+    ///
+    /// The replaced value is kept as a previous version, dropping the
+    /// oldest one if the history is full.
+    pub fn insert(&mut self, key: K, val: V, seq: u64) -> Option<V> {
+        let version = Version::new(seq, val);
+        if let Some(mut branch) = self.map.get_mut(&key) {
+            let versions = branch.leaf_mut();
+            let previous = versions.current().val.clone();
+            versions.push(version);
+            return Some(previous);
+        }
+        self.map.insert(key, Versions::new(version));
+        None
+    }
+
+    /// Returns a copy of the current value stored under `key`
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.map.get(key).map(|value| match value.leaf() {
+            MaybeArchived::Memory(versions) => versions.current().val.clone(),
+            MaybeArchived::Archived(versions) => versions
+                .iter()
+                .last()
+                .expect("a key always has a current version")
+                .val
+                .deserialize(&mut Infallible)
+                .expect("deserializing is infallible"),
+        })
+    }
+
+    /// Returns a copy of the versions of the value stored under `key`,
+    /// oldest first, the last one being the current value
+    pub fn get_versions(&self, key: &K) -> Option<Vec<Version<V>>> {
+        self.map.get(key).map(|value| match value.leaf() {
+            MaybeArchived::Memory(versions) => {
+                versions.iter().cloned().collect()
+            }
+            MaybeArchived::Archived(versions) => versions
+                .iter()
+                .map(|version| {
+                    version
+                        .deserialize(&mut Infallible)
+                        .expect("deserializing is infallible")
+                })
+                .collect(),
+        })
+    }
+
+    /// Removes `key` and its history, returning its current value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.map
+            .remove(key)
+            .map(|versions| versions.current().val.clone())
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &VersionedMap<K, V, I, N> {
+        &self.map
+    }
+}
+
+impl<K, V, I, const N: usize> Default for VersionedHamt<K, V, I, N>
+where
+    V: Archive,
+{
+    fn default() -> Self {
+        assert!(N > 0, "at least the current version must be kept");
+        VersionedHamt {
+            map: Default::default(),
+        }
+    }
+}
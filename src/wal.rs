@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map recording every mutation to a write-ahead log
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::{Hamt, KeyHasher, KvPair, SeaHash};
+
+/// A mutation of a map, as recorded in a write-ahead log
+#[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+pub enum Mutation<K, V> {
+    /// `val` was stored under `key`, replacing any previous value
+    Insert {
+        /// The key stored under
+        key: K,
+        /// The value stored
+        val: V,
+    },
+    /// The entry under `key` was removed, if present
+    Remove {
+        /// The key removed
+        key: K,
+    },
+    /// The map was persisted, see [`LoggedHamt::checkpoint`]
+    Checkpoint {
+        /// Identifies the persisted root among the caller's records
+        id: u64,
+    },
+}
+
+/// Destination of the records of a [`LoggedHamt`]
+pub trait WalSink<K, V> {
+    /// Error returned when a record cannot be appended
+    type Error;
+
+    /// Appends `record` to the log
+    fn append(&mut self, record: &Mutation<K, V>) -> Result<(), Self::Error>;
+}
+
+impl<K, V> WalSink<K, V> for Vec<Mutation<K, V>>
+where
+    K: Clone,
+    V: Clone,
+{
+    type Error = Infallible;
+
+    fn append(&mut self, record: &Mutation<K, V>) -> Result<(), Infallible> {
+        self.push(record.clone());
+        Ok(())
+    }
+}
+
+/// A map appending every mutation to a [`WalSink`] before applying it
+///
+/// A mutation whose record cannot be appended is not applied, so the log
+/// always covers the state of the map.
+pub struct LoggedHamt<K, V, A, I, S, H = SeaHash> {
+    map: Hamt<K, V, A, I, H>,
+    sink: S,
+}
+
+impl<K, V, A, I, S, H> LoggedHamt<K, V, A, I, S, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I, H>: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+        + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    S: WalSink<K, V>,
+    H: KeyHasher,
+{
+    /// Creates a new empty map logging to `sink`
+    pub fn new(sink: S) -> Self {
+        Self::from_parts(Hamt::new(), sink)
+    }
+
+    /// Logs further mutations of `map` to `sink`
+    pub fn from_parts(map: Hamt<K, V, A, I, H>, sink: S) -> Self {
+        LoggedHamt { map, sink }
+    }
+
+    /// Inserts a key-value pair, returning the previous value under `key`
+    pub fn insert(&mut self, key: K, val: V) -> Result<Option<V>, S::Error> {
+        self.sink.append(&Mutation::Insert {
+            key: key.clone(),
+            val: val.clone(),
+        })?;
+        Ok(self.map.insert(key, val))
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, S::Error> {
+        self.sink.append(&Mutation::Remove { key: key.clone() })?;
+        Ok(self.map.remove(key))
+    }
+
+    /// Replaces the value stored under `key` by the one computed from it by
+    /// `f`, returning the previous value
+    ///
+    /// The update is logged as an insertion of the new value. Nothing is
+    /// logged if the key is missing.
+    pub fn update<F>(&mut self, key: &K, f: F) -> Result<Option<V>, S::Error>
+    where
+        F: FnOnce(&V) -> V,
+    {
+        let val = match self.map.get_mut(key) {
+            Some(mut value) => f(value.leaf_mut()),
+            None => return Ok(None),
+        };
+        self.insert(key.clone(), val)
+    }
+
+    /// Records that the map in its current state was persisted, under `id`
+    ///
+    /// Persisting the map, as returned by [`as_map`](Self::as_map), is left
+    /// to the caller. Replaying the log with
+    /// [`Hamt::replay_from_checkpoint`] may then start from that root.
+    pub fn checkpoint(&mut self, id: u64) -> Result<(), S::Error> {
+        self.sink.append(&Mutation::Checkpoint { id })
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &Hamt<K, V, A, I, H> {
+        &self.map
+    }
+
+    /// Returns the sink the mutations are logged to
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Consumes the wrapper, returning the map and the sink
+    pub fn into_parts(self) -> (Hamt<K, V, A, I, H>, S) {
+        (self.map, self.sink)
+    }
+}
+
+impl<K, V, A, I, H> Hamt<K, V, A, I, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I, H>: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+        + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    H: KeyHasher,
+{
+    /// Rebuilds a map by applying every mutation of `log` to an empty map
+    ///
+    /// Checkpoints are skipped.
+    pub fn replay<L>(log: L) -> Self
+    where
+        L: IntoIterator<Item = Mutation<K, V>>,
+    {
+        let mut map = Self::new();
+        map.apply(log);
+        map
+    }
+
+    /// Rebuilds a map from the last checkpoint of `log` that `restore`
+    /// returns a persisted map for, applying the mutations logged after it
+    ///
+    /// Falls back to replaying the whole log if no checkpoint is restored.
+    pub fn replay_from_checkpoint<F>(
+        log: &[Mutation<K, V>],
+        mut restore: F,
+    ) -> Self
+    where
+        F: FnMut(u64) -> Option<Self>,
+    {
+        for (i, record) in log.iter().enumerate().rev() {
+            if let Mutation::Checkpoint { id } = record {
+                if let Some(mut map) = restore(*id) {
+                    map.apply(log[i + 1..].iter().cloned());
+                    return map;
+                }
+            }
+        }
+        Self::replay(log.iter().cloned())
+    }
+
+    fn apply<L>(&mut self, log: L)
+    where
+        L: IntoIterator<Item = Mutation<K, V>>,
+    {
+        for record in log {
+            match record {
+                Mutation::Insert { key, val } => {
+                    self.insert(key, val);
+                }
+                Mutation::Remove { key } => {
+                    self.remove(&key);
+                }
+                Mutation::Checkpoint { .. } => (),
+            }
+        }
+    }
+}
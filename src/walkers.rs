@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Walkers completing the ones provided by microkelvin
+//!
+//! Maps are compounds, so microkelvin's own walkers, such as [`All`] and
+//! [`Nth`], walk them directly, in memory as well as stored.
+//!
+//! [`All`]: microkelvin::All
+//! [`Nth`]: microkelvin::Nth
+
+use microkelvin::{Compound, Discriminant, Step, Walkable, Walker};
+
+/// Walker finding the first leaf in walk order
+///
+/// Unlike [`All`](microkelvin::All), which it otherwise matches, the walk
+/// ends at the first leaf rather than being resumable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct First;
+
+impl<C, A, S> Walker<C, A, S> for First
+where
+    C: Compound<A, S>,
+{
+    fn walk(&mut self, walk: impl Walkable<C, A, S>) -> Step {
+        for i in 0.. {
+            match walk.probe(i) {
+                Discriminant::End => return Step::Abort,
+                Discriminant::Empty => (),
+                _ => return Step::Found(i),
+            }
+        }
+        unreachable!("nodes end with `Discriminant::End`")
+    }
+}
+
+/// Walker finding the last leaf in walk order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Last;
+
+impl<C, A, S> Walker<C, A, S> for Last
+where
+    C: Compound<A, S>,
+{
+    fn walk(&mut self, walk: impl Walkable<C, A, S>) -> Step {
+        let mut last = None;
+        for i in 0.. {
+            match walk.probe(i) {
+                Discriminant::End => break,
+                Discriminant::Empty => (),
+                _ => last = Some(i),
+            }
+        }
+        match last {
+            Some(i) => Step::Found(i),
+            None => Step::Abort,
+        }
+    }
+}
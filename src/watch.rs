@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Map notifying subscribers of the changes to the keys they watch
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use bytecheck::CheckBytes;
+use microkelvin::{Annotation, ArchivedCompound, StoreRef};
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, Deserialize};
+
+use crate::{slot, Change, Hamt, KeyHasher, KvPair, SeaHash};
+
+/// The keys a subscription of a [`WatchedHamt`] is interested in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watch<K> {
+    /// A single key
+    Key(K),
+    /// Every key whose path starts with the given slots, as returned by
+    /// [`Value::path`](crate::Value::path)
+    Path(Vec<usize>),
+}
+
+/// Destination of the notifications of a subscription to a [`WatchedHamt`]
+pub trait Subscriber<K, V> {
+    /// Delivers `change`, returning false if the subscriber is gone and
+    /// should be dropped
+    fn notify(&mut self, change: &Change<K, V>) -> bool;
+}
+
+impl<K, V> Subscriber<K, V> for Vec<Change<K, V>>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn notify(&mut self, change: &Change<K, V>) -> bool {
+        self.push(change.clone());
+        true
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Subscriber<K, V> for std::sync::mpsc::Sender<Change<K, V>>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn notify(&mut self, change: &Change<K, V>) -> bool {
+        self.send(change.clone()).is_ok()
+    }
+}
+
+/// Identifies a subscription to a [`WatchedHamt`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubscriptionId(u64);
+
+/// A map notifying subscribers of every insertion and removal touching the
+/// keys they watch
+///
+/// Subscribers are notified after the change is applied, in the order they
+/// subscribed. Those reporting that they are gone, such as channels whose
+/// receiver was dropped, are unsubscribed.
+pub struct WatchedHamt<K, V, A, I, S, H = SeaHash> {
+    map: Hamt<K, V, A, I, H>,
+    subscriptions: Vec<(SubscriptionId, Watch<K>, S)>,
+    next_id: u64,
+}
+
+impl<K, V, A, I, S, H> WatchedHamt<K, V, A, I, S, H>
+where
+    K: Archive<Archived = K>
+        + Clone
+        + Eq
+        + Hash
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    V: Archive + Clone,
+    V::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    A: Annotation<KvPair<K, V>>,
+    Hamt<K, V, A, I, H>: Archive,
+    <Hamt<K, V, A, I, H> as Archive>::Archived: ArchivedCompound<Hamt<K, V, A, I, H>, A, I>
+        + Deserialize<Hamt<K, V, A, I, H>, StoreRef<I>>
+        + for<'a> CheckBytes<DefaultValidator<'a>>,
+    I: Clone + for<'any> CheckBytes<DefaultValidator<'any>>,
+    S: Subscriber<K, V>,
+    H: KeyHasher,
+{
+    /// Creates a new empty map with no subscriptions
+    pub fn new() -> Self {
+        Self::from_map(Hamt::new())
+    }
+
+    /// Watches further changes to `map`
+    pub fn from_map(map: Hamt<K, V, A, I, H>) -> Self {
+        WatchedHamt {
+            map,
+            subscriptions: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Notifies `subscriber` of the changes to the keys covered by `watch`
+    pub fn subscribe(
+        &mut self,
+        watch: Watch<K>,
+        subscriber: S,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.push((id, watch, subscriber));
+        id
+    }
+
+    /// Cancels a subscription, returning its subscriber, or `None` if it
+    /// was already cancelled or dropped
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> Option<S> {
+        let pos = self.subscriptions.iter().position(|(i, ..)| *i == id)?;
+        Some(self.subscriptions.remove(pos).2)
+    }
+
+    /// Inserts a key-value pair, returning the previous value under `key`
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let old = self.map.insert(key.clone(), val.clone());
+        let change = match old.clone() {
+            Some(old) => Change::Changed { key, old, new: val },
+            None => Change::Added { key, val },
+        };
+        self.notify(&change);
+        old
+    }
+
+    /// Removes the entry stored under `key`, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let val = self.map.remove(key)?;
+        self.notify(&Change::Removed {
+            key: key.clone(),
+            val: val.clone(),
+        });
+        Some(val)
+    }
+
+    /// Returns the underlying map
+    pub fn as_map(&self) -> &Hamt<K, V, A, I, H> {
+        &self.map
+    }
+
+    /// Consumes the wrapper, dropping the subscriptions, and returns the map
+    pub fn into_map(self) -> Hamt<K, V, A, I, H> {
+        self.map
+    }
+
+    fn notify(&mut self, change: &Change<K, V>) {
+        let key = change.key();
+        let digest = H::digest(key);
+        let mut i = 0;
+        while i < self.subscriptions.len() {
+            let (_, watch, subscriber) = &mut self.subscriptions[i];
+            let watched = match watch {
+                Watch::Key(watched) => watched == key,
+                Watch::Path(path) => path
+                    .iter()
+                    .enumerate()
+                    .all(|(depth, ofs)| slot::<H>(digest, depth) == *ofs),
+            };
+            if !watched || subscriber.notify(change) {
+                i += 1;
+            } else {
+                self.subscriptions.remove(i);
+            }
+        }
+    }
+}
+
+impl<K, V, A, I, S, H> Default for WatchedHamt<K, V, A, I, S, H>
+where
+    A: Annotation<KvPair<K, V>>,
+{
+    fn default() -> Self {
+        WatchedHamt {
+            map: Default::default(),
+            subscriptions: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+#![cfg(feature = "ffi")]
+
+use core::ffi::c_void;
+
+use dusk_hamt::ffi::*;
+
+extern "C" fn count(
+    ctx: *mut c_void,
+    _: *const u8,
+    key_len: usize,
+    _: *const u8,
+    val_len: usize,
+) {
+    assert_eq!(key_len, 32);
+    assert_eq!(val_len, 3);
+    unsafe { *(ctx as *mut usize) += 1 }
+}
+
+#[test]
+fn roundtrip() {
+    unsafe {
+        let map = hamt_new();
+
+        for i in 0..64u8 {
+            let key = [i; 32];
+            let val = [i, i, i];
+            assert_eq!(
+                hamt_insert(map, key.as_ptr(), 32, val.as_ptr(), 3),
+                HAMT_OK
+            );
+        }
+
+        let mut val = core::ptr::null();
+        let mut val_len = 0;
+        for i in 0..64u8 {
+            let key = [i; 32];
+            assert_eq!(
+                hamt_get(map, key.as_ptr(), 32, &mut val, &mut val_len),
+                HAMT_OK
+            );
+            assert_eq!(core::slice::from_raw_parts(val, val_len), [i, i, i]);
+        }
+
+        let short = [0u8; 31];
+        assert_eq!(
+            hamt_get(map, short.as_ptr(), 31, &mut val, &mut val_len),
+            HAMT_INVALID_KEY
+        );
+
+        let mut visited = 0usize;
+        let ctx = &mut visited as *mut usize as *mut c_void;
+        assert_eq!(hamt_iterate(map, count, ctx), HAMT_OK);
+        assert_eq!(visited, 64);
+
+        let key = [64u8; 32];
+        let null = core::ptr::null();
+        assert_eq!(hamt_insert(map, key.as_ptr(), 32, null, 0), HAMT_OK);
+        assert_eq!(
+            hamt_get(map, key.as_ptr(), 32, &mut val, &mut val_len),
+            HAMT_OK
+        );
+        assert_eq!(val_len, 0);
+        assert_eq!(hamt_insert(map, key.as_ptr(), 32, null, 1), HAMT_NULL);
+        assert_eq!(hamt_remove(map, key.as_ptr(), 32), HAMT_OK);
+
+        let key = [7u8; 32];
+        assert_eq!(hamt_remove(map, key.as_ptr(), 32), HAMT_OK);
+        assert_eq!(hamt_remove(map, key.as_ptr(), 32), HAMT_NOT_FOUND);
+
+        hamt_free(map);
+    }
+}
+
+#[test]
+fn reopen_from_store() {
+    let dir = std::env::temp_dir()
+        .join(format!("dusk-hamt-ffi-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = std::ffi::CString::new(dir.to_str().unwrap()).unwrap();
+
+    unsafe {
+        let map = hamt_new();
+        for i in 0..64u8 {
+            let key = [i; 32];
+            let val = [i, i, i];
+            assert_eq!(
+                hamt_insert(map, key.as_ptr(), 32, val.as_ptr(), 3),
+                HAMT_OK
+            );
+        }
+
+        let long = [0u8; HAMT_MAX_VAL_LEN + 1];
+        let key = [0u8; 32];
+        assert_eq!(
+            hamt_insert(map, key.as_ptr(), 32, long.as_ptr(), long.len()),
+            HAMT_INVALID_VAL
+        );
+
+        let store = hamt_store_open(path.as_ptr());
+        assert!(!store.is_null());
+        let mut root = HamtRoot { offset: 0, len: 0 };
+        assert_eq!(hamt_store_map(store, map, &mut root), HAMT_OK);
+        assert_eq!(hamt_store_persist(store), HAMT_OK);
+        hamt_store_free(store);
+        hamt_free(map);
+
+        let store = hamt_store_open(path.as_ptr());
+        assert!(!store.is_null());
+        let map = hamt_open(store, root);
+        assert!(!map.is_null());
+
+        let mut val = core::ptr::null();
+        let mut val_len = 0;
+        for i in 0..64u8 {
+            let key = [i; 32];
+            assert_eq!(
+                hamt_get(map, key.as_ptr(), 32, &mut val, &mut val_len),
+                HAMT_OK
+            );
+            assert_eq!(core::slice::from_raw_parts(val, val_len), [i, i, i]);
+        }
+
+        let mut visited = 0usize;
+        let ctx = &mut visited as *mut usize as *mut c_void;
+        assert_eq!(hamt_iterate(map, count, ctx), HAMT_OK);
+        assert_eq!(visited, 64);
+
+        let bogus = HamtRoot {
+            offset: root.offset + 1,
+            len: root.len,
+        };
+        assert!(hamt_open(store, bogus).is_null());
+
+        hamt_free(map);
+        hamt_store_free(store);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
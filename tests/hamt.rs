@@ -4,11 +4,28 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+#![cfg(feature = "seahash")]
+// Raised by newer toolchains on the original tests, kept as they were
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::clone_on_copy,
+    clippy::useless_conversion
+)]
+
 use bytecheck::CheckBytes;
-use dusk_hamt::{Hamt, Lookup};
+use dusk_hamt::{
+    verify_many, verify_update, BatchProof, Bucket, Change, CheckedAdd,
+    CheckedCardinality, CheckedSum, Commitment, CommitmentHasher, DecodeError,
+    DistinctValues, DomainTag, FixedHamt, Hamt, HamtPriorityQueue, KeyError,
+    KeyHasher, KvPair, Lookup, LookupScratch, LruHamt, MaxOf, MinOf, Overflow,
+    Profile, Projection, ProofSlot, SeaHash, SequencedHamt, SmallHamt,
+    StaticHamt, StaticHamtBuilder, StaticSlot, Tagged, TombstoneHamt, TtlHamt,
+    Update, VerifyError, Version, VersionedHamt, Watch, WatchedHamt,
+    MAX_PROOF_DEPTH, PROOF_FORMAT_VERSION,
+};
 use microkelvin::{
-    All, Annotation, Cardinality, Child, Compound, Keyed, MaybeArchived, Nth,
-    OffsetLen,
+    All, Annotation, Cardinality, Child, Combine, Compound, Keyed, MaxKey,
+    MaybeArchived, Nth, OffsetLen,
 };
 use rkyv::rend::LittleEndian;
 use rkyv::{Archive, Deserialize, Serialize};
@@ -58,6 +75,24 @@ fn multiple() {
     assert!(correct_empty_state(hamt));
 }
 
+#[test]
+fn remove_missing() {
+    let mut hamt = Hamt::<LittleEndian<u32>, _, (), OffsetLen>::new();
+
+    for i in 0..64u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    // the paths of missing keys end on the leaves of present ones
+    for i in 64..1024u32 {
+        assert_eq!(hamt.remove(&i.into()), None);
+    }
+
+    for i in 0..64u32 {
+        assert_eq!(hamt.remove(&i.into()), Some(i));
+    }
+}
+
 #[test]
 fn insert_get_immut() {
     let n: u32 = 1024;
@@ -238,3 +273,2203 @@ fn map_behavior_with_simple_key() {
         }
     }
 }
+
+#[test]
+fn pop() {
+    let n: u32 = 1024;
+
+    let mut hamt = Hamt::<LittleEndian<u32>, _, (), OffsetLen>::new();
+
+    for i in 0..n {
+        hamt.insert(i.into(), i);
+    }
+
+    let mut popped = vec![];
+
+    while let Some((key, val)) = hamt.pop() {
+        assert_eq!(u32::from(key), val);
+        popped.push(val);
+    }
+
+    popped.sort_unstable();
+
+    assert_eq!(popped, (0..n).collect::<Vec<_>>());
+    assert!(correct_empty_state(hamt));
+}
+
+#[test]
+fn replace_key() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..16u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    assert_eq!(hamt.replace_key(&3.into(), 100.into()), Ok(()));
+    assert!(hamt.get(&3.into()).is_none());
+    assert_eq!(hamt.get(&100.into()).expect("Some(_)").leaf(), 3);
+
+    assert_eq!(
+        hamt.replace_key(&4.into(), 5.into()),
+        Err(KeyError::Occupied)
+    );
+    assert_eq!(hamt.get(&4.into()).expect("Some(_)").leaf(), 4);
+    assert_eq!(hamt.get(&5.into()).expect("Some(_)").leaf(), 5);
+
+    assert_eq!(
+        hamt.replace_key(&3.into(), 200.into()),
+        Err(KeyError::NotFound)
+    );
+    assert!(hamt.get(&200.into()).is_none());
+}
+
+#[test]
+fn map_values() {
+    let n: u32 = 1024;
+
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..n {
+        hamt.insert(i.into(), i);
+    }
+
+    let mapped: Hamt<_, LittleEndian<u64>, Cardinality, OffsetLen> = hamt
+        .map_values(|key, val| {
+            (u64::from(u32::from(*key)) + u64::from(val)).into()
+        });
+
+    for i in 0..n {
+        let expected: LittleEndian<u64> = (2 * i as u64).into();
+        assert_eq!(mapped.get(&i.into()).expect("Some(_)").leaf(), expected);
+    }
+
+    assert!(mapped.walk(Nth(n as u64 - 1)).is_some());
+    assert!(mapped.walk(Nth(n as u64)).is_none());
+}
+
+#[test]
+fn filter_map() {
+    let n: u32 = 1024;
+
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..n {
+        hamt.insert(i.into(), i);
+    }
+
+    let mut filtered: Hamt<_, u64, Cardinality, OffsetLen> =
+        hamt.filter_map(|_, val| (val % 3 == 0).then(|| val as u64 * 2));
+
+    for i in 0..n {
+        match filtered.get(&i.into()) {
+            Some(branch) => {
+                assert_eq!(i % 3, 0);
+                assert_eq!(branch.leaf(), i as u64 * 2);
+            }
+            None => assert_ne!(i % 3, 0),
+        }
+    }
+
+    for i in (0..n).filter(|i| i % 3 == 0) {
+        assert_eq!(filtered.remove(&i.into()), Some(i as u64 * 2));
+    }
+
+    assert!(correct_empty_state(filtered));
+}
+
+#[test]
+fn join() {
+    let mut left = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut right = Hamt::<
+        LittleEndian<u32>,
+        LittleEndian<u64>,
+        Cardinality,
+        OffsetLen,
+    >::new();
+
+    for i in 0..600u32 {
+        left.insert(i.into(), i);
+    }
+    for i in 400..1000u32 {
+        right.insert(i.into(), (i as u64 * 2).into());
+    }
+
+    let mut joined: Vec<_> = left
+        .join(&right)
+        .map(|(key, l, r)| {
+            let l = l.map(|l| *l);
+            let r = r.map(|r| u64::from(*r));
+            (u32::from(*key), l, r)
+        })
+        .collect();
+
+    joined.sort_unstable();
+
+    let expected: Vec<_> = (0..1000u32)
+        .map(|i| {
+            let l = if i < 600 { Some(i) } else { None };
+            let r = if i >= 400 { Some(i as u64 * 2) } else { None };
+            (i, l, r)
+        })
+        .collect();
+
+    assert_eq!(joined, expected);
+}
+
+#[test]
+fn left_join() {
+    let mut owners = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut names = Hamt::<LittleEndian<u32>, [u8; 4], (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        owners.insert(i.into(), i * 10);
+    }
+    for i in (0..2048u32).step_by(2) {
+        names.insert(i.into(), i.to_le_bytes());
+    }
+
+    let mut enriched: Vec<_> = owners
+        .left_join(&names)
+        .map(|(key, val, name)| (u32::from(*key), *val, name.map(|n| *n)))
+        .collect();
+
+    enriched.sort_unstable();
+
+    let expected: Vec<_> = (0..512u32)
+        .map(|i| {
+            let name = if i % 2 == 0 {
+                Some(i.to_le_bytes())
+            } else {
+                None
+            };
+            (i, i * 10, name)
+        })
+        .collect();
+
+    assert_eq!(enriched, expected);
+}
+
+#[test]
+fn subset_superset_disjoint() {
+    let mut small = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut large = Hamt::<LittleEndian<u32>, (), (), OffsetLen>::new();
+    let mut other = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..256u32 {
+        small.insert(i.into(), i);
+    }
+    for i in 0..1024u32 {
+        large.insert(i.into(), ());
+    }
+    for i in 1024..2048u32 {
+        other.insert(i.into(), i);
+    }
+
+    assert!(small.is_subset(&large));
+    assert!(!large.is_subset(&small));
+    assert!(large.is_superset(&small));
+    assert!(!small.is_superset(&large));
+
+    assert!(small.is_disjoint(&other));
+    assert!(large.is_disjoint(&other));
+    assert!(!small.is_disjoint(&large));
+
+    // maps sharing most of their nodes
+    let mut copy = small.clone();
+    assert!(copy.is_subset(&small) && copy.is_superset(&small));
+
+    copy.insert(4096.into(), 0);
+    assert!(small.is_subset(&copy));
+    assert!(!copy.is_subset(&small));
+
+    copy.remove(&7.into());
+    assert!(!small.is_subset(&copy));
+}
+
+#[derive(Default)]
+struct Sea(Vec<u8>);
+
+impl CommitmentHasher for Sea {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes)
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        let mut digest = [0; 32];
+        for (i, chunk) in digest.chunks_mut(8).enumerate() {
+            let seed = i as u64;
+            let mut hasher = seahash::SeaHasher::with_seeds(
+                seed,
+                seed + 1,
+                seed + 2,
+                seed + 3,
+            );
+            std::hash::Hasher::write(&mut hasher, &self.0);
+            let word = std::hash::Hasher::finish(&hasher);
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+}
+
+#[test]
+fn pinned_commitment() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    // leaves encode their keys and values the same way on every platform
+    let mut map = Map::new();
+    for i in 0..16u32 {
+        map.insert(i.into(), i * 3);
+    }
+    assert_eq!(
+        format!("{:?}", map.commitment()),
+        "Commitment(\
+         f19cc9571d1f6e79026a3bd72b8f4ae39acd3840958b176240ad18811e7b67cc)"
+    );
+}
+
+#[test]
+fn eq_fast() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    let mut a = Map::new();
+    let mut b = Map::new();
+
+    for i in 0..512u32 {
+        a.insert(i.into(), i);
+    }
+    for i in (0..512u32).rev() {
+        b.insert(i.into(), i);
+    }
+
+    assert!(a.eq_fast(&b));
+
+    let mut c = a.clone();
+    *c.get_mut(&100.into()).unwrap().leaf_mut() = 0;
+    assert!(!a.eq_fast(&c));
+
+    *c.get_mut(&100.into()).unwrap().leaf_mut() = 100;
+    assert!(a.eq_fast(&c));
+
+    c.remove(&7.into());
+    assert!(!a.eq_fast(&c));
+
+    assert!(Map::new().eq_fast(&Map::new()));
+}
+
+thread_local! {
+    static FINALIZED: std::cell::Cell<usize> =
+        const { std::cell::Cell::new(0) };
+}
+
+/// Counts the digests computed on the current thread
+#[derive(Default)]
+struct CountedSea(Sea);
+
+impl CommitmentHasher for CountedSea {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes)
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        FINALIZED.with(|n| n.set(n.get() + 1));
+        self.0.finalize()
+    }
+}
+
+#[test]
+fn incremental_commitment() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<CountedSea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..4096u32 {
+        map.insert(i.into(), i);
+    }
+    map.commitment();
+
+    map.insert(4096.into(), 4096);
+    let depth = map.get(&4096.into()).unwrap().depth();
+
+    FINALIZED.with(|n| n.set(0));
+    let commitment = map.commitment();
+    let finalized = FINALIZED.with(|n| n.get());
+
+    // one digest per node on the path, plus its up to four leaves
+    assert!(finalized <= 5 * (depth + 1), "{} digests", finalized);
+
+    let mut fresh = Map::new();
+    for i in (0..=4096u32).rev() {
+        fresh.insert(i.into(), i);
+    }
+    assert_eq!(commitment, fresh.commitment());
+}
+
+#[test]
+fn guarded_values() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<CountedSea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..1024u32 {
+        map.insert(i.into(), i);
+    }
+    let commitment = map.commitment();
+
+    // only the root is hashed again, the rest is cached
+    FINALIZED.with(|n| n.set(0));
+    map.commitment();
+    let cached = FINALIZED.with(|n| n.get());
+
+    // reading through the guard keeps the cached commitments
+    for i in 0..1024u32 {
+        let guard = map.get_guarded(&i.into()).expect("Some(_)");
+        assert_eq!(*guard, i);
+        assert!(!guard.is_dirty());
+    }
+    assert!(map.get_guarded(&1024.into()).is_none());
+
+    FINALIZED.with(|n| n.set(0));
+    assert_eq!(map.commitment(), commitment);
+    assert_eq!(FINALIZED.with(|n| n.get()), cached);
+
+    // writing through it updates the map on drop
+    *map.get_guarded(&7.into()).expect("Some(_)") += 1;
+    assert_eq!(*map.get(&7.into()).expect("Some(_)").leaf(), 8);
+    assert_ne!(map.commitment(), commitment);
+}
+
+#[test]
+fn prove_many() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..1024u32 {
+        map.insert(i.into(), i);
+    }
+    let root = map.commitment();
+
+    let keys: Vec<LittleEndian<u32>> = (0..64u32).map(Into::into).collect();
+    let key_refs: Vec<_> = keys.iter().collect();
+    let vals: Vec<u32> = (0..64).collect();
+    let entries: Vec<_> = keys.iter().zip(vals.iter()).collect();
+
+    let batch = map.prove_many(&key_refs).unwrap();
+    assert_eq!(batch.root(), Ok(root));
+    assert_eq!(batch.verify(&root, &entries), Ok(()));
+
+    // sharing the nodes near the root makes the batch smaller
+    let separate: usize = key_refs
+        .iter()
+        .map(|k| map.prove_many::<Sea>(&[*k]).unwrap().nodes().len())
+        .sum();
+    assert!(batch.nodes().len() < separate);
+
+    let wrong = (&keys[3], &4u32);
+    assert_eq!(batch.verify(&root, &[wrong]), Err(VerifyError::NotIncluded));
+    let unproven = (&LittleEndian::<u32>::from(1000), &1000u32);
+    assert_eq!(
+        batch.verify(&root, &[unproven]),
+        Err(VerifyError::NotIncluded)
+    );
+
+    let mut other = map.clone();
+    other.insert(5000.into(), 0);
+    assert_eq!(
+        batch.verify(&other.commitment(), &entries),
+        Err(VerifyError::RootMismatch)
+    );
+
+    let truncated = BatchProof::<Sea>::new(batch.nodes()[1..].to_vec());
+    assert!(truncated.verify(&root, &entries).is_err());
+
+    assert!(map.prove_many::<Sea>(&[&5000.into()]).is_none());
+}
+
+#[test]
+fn verify_many_proofs() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<CountedSea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..1024u32 {
+        map.insert(i.into(), i);
+    }
+    let root = map.commitment();
+
+    let keys: Vec<LittleEndian<u32>> = (0..64u32).map(Into::into).collect();
+    let vals: Vec<u32> = (0..64).collect();
+    let entries: Vec<_> = keys.iter().zip(vals.iter()).collect();
+
+    // one proof per key, sharing the nodes close to the root
+    let proofs: Vec<_> = keys
+        .iter()
+        .map(|key| map.prove_many(&[key]).unwrap())
+        .collect();
+    let checks: Vec<_> = proofs.iter().zip(entries.chunks(1)).collect();
+
+    FINALIZED.with(|n| n.set(0));
+    for (proof, entries) in &checks {
+        assert_eq!(proof.verify(&root, entries), Ok(()));
+    }
+    let one_by_one = FINALIZED.with(|n| n.get());
+
+    FINALIZED.with(|n| n.set(0));
+    assert_eq!(verify_many(&root, &checks), Ok(()));
+    assert!(FINALIZED.with(|n| n.get()) < one_by_one);
+
+    assert_eq!(verify_many::<u32, u32, _, SeaHash>(&root, &[]), Ok(()));
+
+    // the first rejected proof is reported
+    let wrong = [(&keys[5], &0)];
+    let mut bad = checks.clone();
+    bad[5].1 = &wrong;
+    bad[9].1 = &wrong;
+    assert_eq!(verify_many(&root, &bad), Err((5, VerifyError::NotIncluded)));
+
+    let mut other = map.clone();
+    other.insert(5000.into(), 5000);
+    let stale = other.prove_many(&[&keys[7]]).unwrap();
+    let mut bad = checks.clone();
+    bad[7].0 = &stale;
+    assert_eq!(
+        verify_many(&root, &bad),
+        Err((7, VerifyError::RootMismatch))
+    );
+}
+
+#[test]
+fn proof_bytes() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..256u32 {
+        map.insert(i.into(), i);
+    }
+
+    let keys: Vec<LittleEndian<u32>> = (0..8u32).map(Into::into).collect();
+    let key_refs: Vec<_> = keys.iter().collect();
+    let proof = map.prove_many::<Sea>(&key_refs).unwrap();
+
+    let bytes = proof.to_bytes();
+    assert_eq!(bytes[0], PROOF_FORMAT_VERSION);
+    assert_eq!(BatchProof::from_bytes(&bytes), Ok(proof));
+
+    let mut other = bytes.clone();
+    other[0] = 0;
+    assert_eq!(
+        BatchProof::<Sea>::from_bytes(&other),
+        Err(DecodeError::Version(0))
+    );
+    other = bytes.clone();
+    other[1] = 9;
+    assert_eq!(
+        BatchProof::<Sea>::from_bytes(&other),
+        Err(DecodeError::Kind(9))
+    );
+    other = bytes.clone();
+    other[6] = 7;
+    assert_eq!(
+        BatchProof::<Sea>::from_bytes(&other),
+        Err(DecodeError::Tag(7))
+    );
+    assert_eq!(
+        BatchProof::<Sea>::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(DecodeError::Truncated)
+    );
+    other = bytes.clone();
+    other.push(0);
+    assert_eq!(
+        BatchProof::<Sea>::from_bytes(&other),
+        Err(DecodeError::Trailing)
+    );
+}
+
+/// Hashes every key to the same digest
+struct Colliding;
+
+impl KeyHasher for Colliding {
+    type Digest = u64;
+
+    fn digest<T>(_: &T) -> u64
+    where
+        T: std::hash::Hash + ?Sized,
+    {
+        0
+    }
+}
+
+#[test]
+fn verify_update_colliding() {
+    type Map =
+        Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen, Colliding>;
+
+    let mut map = Map::new();
+    map.insert(1.into(), 1);
+    let root = map.commitment();
+
+    // the keys share their path at every depth, and never split
+    let key = LittleEndian::from(2u32);
+    let proof = map.prove_update::<Sea>(&key);
+    assert_eq!(
+        verify_update(&root, &proof, Update::Insert(&key, &2), &root),
+        Err(VerifyError::Malformed)
+    );
+}
+
+#[test]
+fn hostile_proof() {
+    // a chain of `len` nodes, each opening the next
+    let chain = |len: usize| {
+        let mut nodes = vec![
+            [
+                ProofSlot::Open,
+                ProofSlot::Empty,
+                ProofSlot::Empty,
+                ProofSlot::Empty
+            ];
+            len - 1
+        ];
+        nodes.push([ProofSlot::Empty; 4]);
+        BatchProof::<Sea>::new(nodes)
+    };
+
+    let deepest = chain(MAX_PROOF_DEPTH + 1);
+    assert_eq!(BatchProof::from_bytes(&deepest.to_bytes()), Ok(deepest));
+
+    let hostile = chain(MAX_PROOF_DEPTH + 2);
+    assert_eq!(
+        BatchProof::<Sea>::from_bytes(&hostile.to_bytes()),
+        Err(DecodeError::TooDeep)
+    );
+    assert_eq!(hostile.root(), Err(VerifyError::TooDeep));
+}
+
+#[test]
+fn verify_many_hostile() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..256u32 {
+        map.insert(i.into(), i);
+    }
+    let root = map.commitment();
+    let key = LittleEndian::from(7u32);
+    let proof = map.prove_many::<Sea>(&[&key]).unwrap();
+
+    // a chain of nodes, each opening the next, one deeper than allowed
+    let mut nodes = vec![
+        [
+            ProofSlot::Open,
+            ProofSlot::Empty,
+            ProofSlot::Empty,
+            ProofSlot::Empty
+        ];
+        MAX_PROOF_DEPTH + 1
+    ];
+    nodes.push([ProofSlot::Empty; 4]);
+    let hostile = BatchProof::<Sea>::new(nodes);
+
+    let entries = [(&key, &7)];
+    let checks = [(&proof, &entries[..]), (&hostile, &entries[..])];
+    assert_eq!(verify_many(&root, &checks), Err((1, VerifyError::TooDeep)));
+}
+
+#[test]
+fn verify_updates() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    let mut map = Map::new();
+
+    // inserts into empty slots and split leaves, then replaces values,
+    // then removes both present and missing keys, collapsing nodes
+    let inserts = (0..512u32).map(|i| (i, i));
+    let replaces = (0..512u32).step_by(3).map(|i| (i, i + 1));
+    for (key, val) in inserts.chain(replaces) {
+        let key = LittleEndian::from(key);
+        let old_root = map.commitment();
+        let proof = map.prove_update::<Sea>(&key);
+        map.insert(key, val);
+        let new_root = map.commitment();
+
+        let update = Update::Insert(&key, &val);
+        assert_eq!(verify_update(&old_root, &proof, update, &new_root), Ok(()));
+        let update = Update::Insert(&key, &(val + 1));
+        assert_eq!(
+            verify_update(&old_root, &proof, update, &new_root),
+            Err(VerifyError::RootMismatch)
+        );
+    }
+
+    for key in (0..600u32).rev() {
+        let key = LittleEndian::from(key);
+        let old_root = map.commitment();
+        let proof = map.prove_update::<Sea>(&key);
+        map.remove(&key);
+        let new_root = map.commitment();
+
+        let update = Update::<_, u32>::Remove(&key);
+        assert_eq!(verify_update(&old_root, &proof, update, &new_root), Ok(()));
+    }
+    assert!(map.eq_fast(&Map::new()));
+
+    // a proof for another key does not vouch for the update
+    let mut map = Map::new();
+    for i in 0..64u32 {
+        map.insert(i.into(), i);
+    }
+    let old_root = map.commitment();
+    let proof = map.prove_update::<Sea>(&3.into());
+    let key = LittleEndian::from(4);
+    map.remove(&key);
+    let update = Update::<_, u32>::Remove(&key);
+    assert!(
+        verify_update(&old_root, &proof, update, &map.commitment()).is_err()
+    );
+}
+
+#[test]
+fn distinct_values() {
+    let mut hamt =
+        Hamt::<LittleEndian<u32>, u32, DistinctValues, OffsetLen>::new();
+
+    assert_eq!(hamt.distinct_values(), 0);
+
+    for i in 0..4096u32 {
+        hamt.insert(i.into(), i % 300);
+    }
+
+    let estimate = hamt.distinct_values();
+    assert!(estimate > 200 && estimate < 400, "estimate {}", estimate);
+
+    let mut few =
+        Hamt::<LittleEndian<u32>, u32, DistinctValues, OffsetLen>::new();
+    for i in 0..1024u32 {
+        few.insert(i.into(), i % 3);
+    }
+
+    let estimate = few.distinct_values();
+    assert!((2..=4).contains(&estimate), "estimate {}", estimate);
+}
+
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Archive,
+    Serialize,
+    Deserialize,
+    CheckBytes,
+)]
+#[archive(as = "Self")]
+struct Expiry(u64);
+
+impl Projection<LittleEndian<u32>, [u64; 2]> for Expiry {
+    fn project(_: &LittleEndian<u32>, val: &[u64; 2]) -> Self {
+        Expiry(val[0])
+    }
+
+    fn project_archived(_: &LittleEndian<u32>, val: &[u64; 2]) -> Self {
+        Expiry(val[0])
+    }
+}
+
+#[test]
+fn projected_extremes() {
+    let mut latest =
+        Hamt::<LittleEndian<u32>, [u64; 2], MaxOf<Expiry>, OffsetLen>::new();
+    let mut earliest =
+        Hamt::<LittleEndian<u32>, [u64; 2], MinOf<Expiry>, OffsetLen>::new();
+
+    assert!(latest.max_of::<Expiry>().is_none());
+    assert!(earliest.min_of::<Expiry>().is_none());
+
+    for i in 0..1024u32 {
+        let expiry = (i as u64 * 7919) % 1024;
+        latest.insert(i.into(), [expiry, i as u64]);
+        earliest.insert(i.into(), [expiry, i as u64]);
+    }
+
+    for expected in (1000..1024).rev() {
+        let key = {
+            let branch = latest.max_of::<Expiry>().expect("Some(_)");
+            match branch.leaf() {
+                MaybeArchived::Memory(kv) => {
+                    assert_eq!(kv.value()[0], expected);
+                    *kv.key()
+                }
+                MaybeArchived::Archived(_) => unreachable!("in memory map"),
+            }
+        };
+        latest.remove(&key);
+    }
+
+    for expected in 0..24 {
+        let key = {
+            let branch = earliest.min_of::<Expiry>().expect("Some(_)");
+            match branch.leaf() {
+                MaybeArchived::Memory(kv) => {
+                    assert_eq!(kv.value()[0], expected);
+                    *kv.key()
+                }
+                MaybeArchived::Archived(_) => unreachable!("in memory map"),
+            }
+        };
+        earliest.remove(&key);
+    }
+}
+
+#[test]
+fn max_pop_max() {
+    let mut hamt = Hamt::<
+        LittleEndian<u64>,
+        u64,
+        MaxKey<LittleEndian<u64>>,
+        OffsetLen,
+    >::new();
+
+    assert!(hamt.max().is_none());
+    assert!(hamt.pop_max().is_none());
+
+    for i in 0..1024u64 {
+        hamt.insert(((i * 7919) % 1024).into(), i);
+    }
+
+    assert_eq!(*hamt.max().expect("Some(_)").leaf().key(), 1023);
+
+    for expected in (0..1024u64).rev() {
+        let (key, _) = hamt.pop_max().expect("Some(_)");
+        assert_eq!(key, expected);
+    }
+
+    assert!(hamt.pop_max().is_none());
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Archive,
+    Serialize,
+    Deserialize,
+    CheckBytes,
+)]
+#[archive(as = "Self")]
+struct Fee(u64);
+
+impl CheckedAdd for Fee {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Fee)
+    }
+}
+
+// not imported, it would shadow the inherent `checked_sub` of integers
+impl dusk_hamt::CheckedSub for Fee {
+    fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Fee)
+    }
+}
+
+impl Projection<LittleEndian<u32>, u64> for Fee {
+    fn project(_: &LittleEndian<u32>, fee: &u64) -> Self {
+        Fee(*fee)
+    }
+
+    fn project_archived(_: &LittleEndian<u32>, fee: &u64) -> Self {
+        Fee(*fee)
+    }
+}
+
+#[test]
+fn priority_queue() {
+    let mut queue =
+        HamtPriorityQueue::<LittleEndian<u32>, u64, Fee, OffsetLen>::new();
+
+    assert!(queue.peek_max().is_none());
+    assert!(queue.pop_max().is_none());
+
+    for i in 0..512u32 {
+        queue.push(i.into(), (i as u64 * 7919) % 512);
+    }
+
+    // bump one entry to the top, and drop the previous maximum
+    let bumped = *queue.peek_max().expect("Some(_)").leaf().key();
+    queue.remove(&bumped);
+    queue.push(3.into(), 10_000);
+
+    assert_eq!(queue.pop_max(), Some((3.into(), 10_000)));
+
+    let mut last = u64::MAX;
+    let mut popped = 2;
+    while let Some((_, fee)) = queue.pop_max() {
+        assert!(fee <= last);
+        last = fee;
+        popped += 1;
+    }
+
+    assert_eq!(popped, 512);
+}
+
+#[test]
+fn lru() {
+    let mut cache = LruHamt::<LittleEndian<u32>, u32, OffsetLen>::new(64);
+
+    for i in 0..64u32 {
+        assert_eq!(cache.push(i.into(), i), None);
+    }
+    assert_eq!(cache.len(), 64);
+
+    // touch the first half, making the second half least recently used
+    for i in 0..32u32 {
+        *cache.get(&i.into()).expect("Some(_)").leaf_mut() += 1000;
+    }
+
+    for i in 64..96u32 {
+        let (evicted, val) = cache.push(i.into(), i).expect("cache is full");
+        assert_eq!(u32::from(evicted), i - 32);
+        assert_eq!(val, i - 32);
+    }
+    assert_eq!(cache.len(), 64);
+
+    for i in 0..32u32 {
+        assert_eq!(
+            *cache.get(&i.into()).expect("Some(_)").leaf_mut(),
+            i + 1000
+        );
+    }
+    for i in 32..64u32 {
+        assert!(cache.get(&i.into()).is_none());
+    }
+
+    // replacing refreshes the entry without evicting
+    assert_eq!(cache.push(64.into(), 0), Some((64.into(), 64)));
+    assert_eq!(cache.pop_lru(), Some((65.into(), 65)));
+    assert_eq!(cache.remove(&66.into()), Some(66));
+    assert_eq!(cache.len(), 62);
+}
+
+#[test]
+fn ttl() {
+    let mut sessions = TtlHamt::<LittleEndian<u32>, u32, OffsetLen>::new();
+
+    for i in 0..1024u32 {
+        sessions.insert(i.into(), i, i as u64 % 64);
+    }
+
+    let now = 32;
+
+    for i in 0..1024u32 {
+        if i % 64 > 32 {
+            assert_eq!(
+                *sessions.get(&i.into(), now).expect("Some(_)").leaf(),
+                i
+            );
+        } else {
+            assert!(sessions.get(&i.into(), now).is_none());
+        }
+    }
+
+    assert_eq!(sessions.purge_expired(now), 33 * 16);
+    assert_eq!(sessions.purge_expired(now), 0);
+
+    assert_eq!(sessions.remove(&33.into(), now), Some(33));
+    assert_eq!(sessions.remove(&97.into(), 64), None);
+    assert!(sessions.get(&97.into(), now).is_none());
+    assert_eq!(sessions.purge_expired(u64::MAX), 1024 - 33 * 16 - 2);
+}
+
+#[test]
+fn insertion_order() {
+    let mut log = SequencedHamt::<LittleEndian<u32>, u32, OffsetLen>::new();
+
+    assert!(log.first().is_none());
+    assert_eq!(log.iter_in_insertion_order().count(), 0);
+
+    let keys: Vec<u32> = (0..512u32).map(|i| (i * 7919) % 512).collect();
+
+    for key in &keys {
+        log.insert((*key).into(), *key);
+    }
+
+    // replacing a value keeps the position of the key
+    assert_eq!(log.insert(keys[3].into(), 0), Some(keys[3]));
+    assert_eq!(log.remove(&keys[0].into()), Some(keys[0]));
+
+    assert_eq!(*log.first().expect("Some(_)").leaf().key(), keys[1]);
+    assert_eq!(*log.get(&keys[3].into()).expect("Some(_)").leaf(), 0);
+
+    let ordered: Vec<u32> = log
+        .iter_in_insertion_order()
+        .map(|(key, _)| u32::from(*key))
+        .collect();
+
+    assert_eq!(ordered, keys[1..]);
+}
+
+#[test]
+fn versioned_hamt() {
+    let mut balances =
+        VersionedHamt::<LittleEndian<u32>, u64, OffsetLen, 3>::new();
+
+    assert_eq!(balances.insert(1.into(), 100, 10), None);
+    assert_eq!(balances.insert(1.into(), 80, 11), Some(100));
+    assert_eq!(balances.insert(2.into(), 5, 11), None);
+    assert_eq!(balances.insert(1.into(), 90, 12), Some(80));
+    assert_eq!(balances.insert(1.into(), 70, 14), Some(90));
+
+    assert_eq!(balances.get(&1.into()), Some(70));
+    assert_eq!(
+        balances.get_versions(&1.into()),
+        Some(vec![
+            Version::new(11, 80),
+            Version::new(12, 90),
+            Version::new(14, 70)
+        ])
+    );
+    assert_eq!(
+        balances.get_versions(&2.into()),
+        Some(vec![Version::new(11, 5)])
+    );
+
+    assert_eq!(balances.remove(&1.into()), Some(70));
+    assert_eq!(balances.get_versions(&1.into()), None);
+    assert_eq!(balances.insert(1.into(), 1, 15), None);
+}
+
+#[test]
+fn tombstone_hamt() {
+    type Replica = TombstoneHamt<LittleEndian<u32>, u32, OffsetLen>;
+
+    let mut a = Replica::new();
+    let mut b = Replica::new();
+
+    a.insert(1.into(), 100, 1);
+    b.merge(&a);
+    assert_eq!(b.remove(1.into(), 2), Some(100));
+
+    // the older value on `a` does not resurrect the key
+    b.merge(&a);
+    a.merge(&b);
+    assert!(a.get(&1.into()).is_none());
+    assert_eq!(a.tombstone(&1.into()), Some(2));
+
+    a.insert(2.into(), 5, 3);
+    b.insert(2.into(), 6, 4);
+    a.insert(3.into(), 1, 5);
+    b.remove(3.into(), 5);
+
+    a.merge(&b);
+    b.merge(&a);
+    for replica in [&a, &b].iter() {
+        assert_eq!(*replica.get(&2.into()).unwrap().leaf(), 6);
+        assert!(replica.get(&3.into()).is_none());
+        assert_eq!(replica.tombstone(&3.into()), Some(5));
+    }
+
+    assert_eq!(a.purge_tombstones(5), 1);
+    assert_eq!(a.tombstone(&1.into()), None);
+    assert_eq!(a.tombstone(&3.into()), Some(5));
+    assert_eq!(*a.get(&2.into()).unwrap().leaf(), 6);
+
+    // concurrent insertions at the same sequence number converge
+    let mut c = Replica::new();
+    let mut d = Replica::new();
+    c.insert(4.into(), 7, 6);
+    d.insert(4.into(), 8, 6);
+    let (mut cd, mut dc) = (c.clone(), d.clone());
+    cd.merge(&d);
+    dc.merge(&c);
+    assert_eq!(*cd.get(&4.into()).unwrap().leaf(), 8);
+    assert_eq!(*dc.get(&4.into()).unwrap().leaf(), 8);
+}
+
+#[test]
+fn digest_order() {
+    // test vector, changing it breaks the stability contract of
+    // `iter_by_digest`
+    const ORDER: [u64; 16] =
+        [0, 7, 5, 4, 13, 3, 6, 15, 1, 8, 9, 14, 11, 12, 10, 2];
+
+    let mut forward = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+    let mut backward = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+
+    for i in 0..16u64 {
+        forward.insert(i.into(), i);
+    }
+    for i in (0..64u64).rev() {
+        backward.insert(i.into(), i);
+    }
+    for i in 16..64u64 {
+        backward.remove(&i.into());
+    }
+
+    for hamt in [forward, backward].iter() {
+        let order: Vec<u64> = hamt
+            .iter_by_digest()
+            .map(|(key, val)| {
+                assert_eq!(*key, *val);
+                u64::from(*key)
+            })
+            .collect();
+
+        assert_eq!(order, ORDER);
+    }
+}
+
+#[test]
+fn snapshot_iteration() {
+    let mut map = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+    for i in 0..1024u64 {
+        map.insert(i.into(), i);
+    }
+    let order: Vec<_> = map
+        .iter_by_digest()
+        .map(|(key, val)| (*key, *val))
+        .collect();
+
+    // mutating the map while iterating does not affect the snapshot
+    let mut snapshot = Vec::new();
+    for (i, (key, val)) in map.iter_snapshot().enumerate() {
+        snapshot.push((key, val));
+        map.remove(&key);
+        map.insert((4096 + i as u64).into(), 0);
+    }
+    assert_eq!(snapshot, order);
+
+    assert_eq!(map.iter_by_digest().count(), 1024);
+    assert!(map.iter_by_digest().all(|(key, _)| u64::from(*key) >= 4096));
+}
+
+#[test]
+fn bucketed_iteration() {
+    let mut map = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+    assert!(map.iter_buckets().all(|mut bucket| bucket.next().is_none()));
+
+    for i in 0..1024u64 {
+        map.insert(i.into(), i);
+    }
+
+    let mut chained = Vec::new();
+    for (ofs, bucket) in map.iter_buckets().enumerate() {
+        for (key, _) in bucket {
+            let path = map.get(key).expect("Some(_)").path();
+            assert_eq!(path[0], ofs);
+            chained.push(*key);
+        }
+    }
+    assert_eq!(map.iter_buckets().count(), 4);
+
+    let order: Vec<_> = map.iter_by_digest().map(|(key, _)| *key).collect();
+    assert_eq!(chained, order);
+}
+
+#[test]
+fn checked_cardinality() {
+    let mut hamt =
+        Hamt::<LittleEndian<u32>, u32, CheckedCardinality, OffsetLen>::new();
+
+    assert_eq!(hamt.checked_len(), Ok(0));
+
+    for i in 0..1024u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    assert_eq!(hamt.checked_len(), Ok(1024));
+
+    let mut nth: Vec<u32> = (0..1024)
+        .map(|n| {
+            let branch = hamt.checked_nth(n).unwrap().expect("Some(_)");
+            u32::from(*branch.leaf().key())
+        })
+        .collect();
+    nth.sort_unstable();
+
+    assert_eq!(nth, (0..1024).collect::<Vec<_>>());
+    assert!(hamt.checked_nth(1024).unwrap().is_none());
+
+    let mut card = CheckedCardinality::Count(u64::MAX - 1);
+    card.combine(&CheckedCardinality::Count(1));
+    assert_eq!(card.get(), Ok(u64::MAX));
+    card.combine(&CheckedCardinality::Count(1));
+    assert!(card.get().is_err());
+    card.combine(&CheckedCardinality::Count(0));
+    assert_eq!(card, CheckedCardinality::Overflow);
+}
+
+#[test]
+fn checked_sum() {
+    let mut fees =
+        Hamt::<LittleEndian<u32>, u64, CheckedSum<Fee>, OffsetLen>::new();
+
+    assert_eq!(fees.checked_sum(), Ok(Fee(0)));
+
+    for i in 0..1024u32 {
+        fees.insert(i.into(), i as u64);
+    }
+
+    assert_eq!(fees.checked_sum(), Ok(Fee(1023 * 1024 / 2)));
+
+    fees.insert(2000.into(), u64::MAX);
+    assert!(fees.checked_sum::<Fee>().is_err());
+
+    // removing the offending entry recovers the sum
+    fees.remove(&2000.into());
+    assert_eq!(fees.checked_sum(), Ok(Fee(1023 * 1024 / 2)));
+}
+
+#[test]
+fn delta_annotations() {
+    let mut fees =
+        Hamt::<LittleEndian<u32>, u64, CheckedSum<Fee>, OffsetLen>::new();
+    let mut counted =
+        Hamt::<LittleEndian<u32>, u32, CheckedCardinality, OffsetLen>::new();
+
+    let mut sum = 0;
+    let mut len = 0;
+    for i in 0..1024u32 {
+        fees.insert_delta(i.into(), i as u64);
+        counted.insert_delta(i.into(), i);
+        sum += i as u64;
+        len += 1;
+
+        // reading the aggregates caches the annotations along the way
+        if i % 64 == 0 {
+            assert_eq!(fees.checked_sum(), Ok(Fee(sum)));
+            assert_eq!(counted.checked_len(), Ok(len));
+        }
+    }
+
+    for i in (0..1024u32).step_by(3) {
+        assert_eq!(fees.insert_delta(i.into(), 1), Some(i as u64));
+        counted.insert_delta(i.into(), 1);
+        sum = sum - i as u64 + 1;
+    }
+    assert_eq!(fees.checked_sum(), Ok(Fee(sum)));
+    assert_eq!(counted.checked_len(), Ok(len));
+
+    for i in (0..1200u32).step_by(2) {
+        if let Some(fee) = fees.remove_delta(&i.into()) {
+            sum -= fee;
+        }
+        if counted.remove_delta(&i.into()).is_some() {
+            len -= 1;
+        }
+    }
+    assert_eq!(fees.checked_sum(), Ok(Fee(sum)));
+    assert_eq!(counted.checked_len(), Ok(len));
+
+    for n in 0..len {
+        assert!(counted.checked_nth(n).unwrap().is_some());
+    }
+    assert!(counted.checked_nth(len).unwrap().is_none());
+}
+
+#[test]
+fn recompute() {
+    let mut hamt =
+        Hamt::<LittleEndian<u32>, u32, Cardinality, OffsetLen>::new();
+
+    for i in 0..1024u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    assert_eq!(u64::from(hamt.recompute()), 1024);
+
+    for i in 0..1024u32 {
+        *hamt.get_mut(&i.into()).expect("Some(_)").leaf_mut() += 1;
+    }
+    for i in 0..512u32 {
+        hamt.remove(&i.into());
+    }
+
+    assert_eq!(u64::from(hamt.recompute()), 512);
+    assert_eq!(*hamt.get(&1000.into()).expect("Some(_)").leaf(), 1001);
+}
+
+#[test]
+fn value_mut_key() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..256u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    for i in 0..256u32 {
+        let mut value = hamt.get_mut(&i.into()).expect("Some(_)");
+        let key = u32::from(*value.key());
+        *value.leaf_mut() += key;
+    }
+
+    for i in 0..256u32 {
+        assert_eq!(*hamt.get(&i.into()).expect("Some(_)").leaf(), i * 2);
+    }
+}
+
+#[test]
+fn value_owned() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..256u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    let owned: Vec<u32> = (0..256u32)
+        .map(|i| hamt.get(&i.into()).expect("Some(_)").into_owned())
+        .collect();
+
+    // the borrow of the map is released
+    hamt.insert(0.into(), 1000);
+
+    assert_eq!(owned, (0..256).collect::<Vec<_>>());
+
+    let value = hamt.get(&0.into()).expect("Some(_)");
+    assert_eq!(*value.key(), 0);
+    assert_eq!(value.to_owned(), 1000);
+}
+
+#[test]
+fn kv_pair() {
+    let pair = KvPair::new(1u32, 2u32);
+
+    assert_eq!(*pair.key(), 1);
+    assert_eq!(*pair.value(), 2);
+
+    let pair = pair.map_val(|val| val.to_string());
+    assert_eq!(pair.into_parts(), (1, "2".to_string()));
+}
+
+#[test]
+fn value_path() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..256u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    for i in 0..256u32 {
+        let (path, depth) = {
+            let value = hamt.get(&i.into()).expect("Some(_)");
+            (value.path(), value.depth())
+        };
+
+        assert_eq!(path.len(), depth + 1);
+        assert!(path.iter().all(|slot| *slot < 4));
+
+        let value = hamt.get_mut(&i.into()).expect("Some(_)");
+        assert_eq!(value.path(), path);
+        assert_eq!(value.depth(), depth);
+
+        let subtree = hamt.subtree(&path[..depth]);
+        assert_eq!(*subtree.get(&i.into()).expect("Some(_)").leaf(), i);
+    }
+}
+
+#[test]
+fn aggregates() {
+    let mut hamt =
+        Hamt::<LittleEndian<u32>, u32, Cardinality, OffsetLen>::new();
+    assert_eq!(u64::from(hamt.aggregate::<Cardinality>()), 0);
+    assert!(hamt.aggregate_of_subtree::<Cardinality>(&[0]).is_none());
+
+    for i in 0..1024u32 {
+        hamt.insert(i.into(), i);
+    }
+    assert_eq!(u64::from(hamt.aggregate::<Cardinality>()), 1024);
+
+    let mut total = 0;
+    for a in 0..4 {
+        for b in 0..4 {
+            let path = [a, b];
+            let count = hamt.subtree(&path).iter_by_digest().count() as u64;
+            let aggregate = hamt
+                .aggregate_of_subtree::<Cardinality>(&path)
+                .map(u64::from)
+                .unwrap_or(0);
+            assert_eq!(aggregate, count);
+            total += aggregate;
+        }
+    }
+    assert_eq!(total, 1024);
+
+    // a path ending at a leaf aggregates that leaf alone
+    let path = hamt.get(&7.into()).expect("Some(_)").path();
+    let leaf = hamt.aggregate_of_subtree::<Cardinality>(&path);
+    assert_eq!(leaf.map(u64::from), Some(1));
+}
+
+#[test]
+fn profile() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    let profile = hamt.profile();
+    assert!(profile.is_empty());
+    assert_eq!(profile.depth(), 0);
+    assert_eq!(profile.levels()[0].empty, 4);
+    assert_eq!(Profile::default().depth(), 0);
+
+    for i in 0..1024u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    let profile = hamt.profile();
+    assert_eq!(profile.len(), 1024);
+    assert_eq!(profile.levels()[0].nodes, 1);
+    assert_eq!(profile.leaf_depths().sum::<usize>(), 1024);
+
+    for (depth, level) in profile.levels().iter().enumerate() {
+        assert_eq!(level.empty + level.leaves + level.links, level.nodes * 4);
+
+        match profile.levels().get(depth + 1) {
+            Some(next) => assert_eq!(level.links, next.nodes),
+            None => assert_eq!(level.links, 0),
+        }
+    }
+
+    let deepest = (0..1024u32)
+        .map(|i| hamt.get(&i.into()).expect("Some(_)").depth())
+        .max();
+    assert_eq!(deepest, Some(profile.depth()));
+}
+
+#[test]
+fn compact() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..1024u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    for i in (0..1024u32).filter(|i| i % 3 != 0) {
+        assert_eq!(hamt.remove(&i.into()), Some(i));
+    }
+
+    let profile = hamt.profile();
+    assert_eq!(hamt.compact(), 0);
+    assert_eq!(hamt.profile(), profile);
+
+    for i in 0..1024u32 {
+        let expected = if i % 3 == 0 { Some(i) } else { None };
+        assert_eq!(hamt.get(&i.into()).map(|v| *v.leaf()), expected);
+    }
+
+    let mut subtree = hamt.subtree(&[0, 1]);
+    let keys: Vec<_> = subtree.iter_by_digest().map(|(k, _)| *k).collect();
+    for key in keys.iter().skip(1) {
+        subtree.remove(key);
+    }
+
+    subtree.compact();
+    assert_eq!(subtree.profile().depth(), 0);
+    assert_eq!(subtree.profile().len(), keys.len().min(1));
+}
+
+struct Seeded;
+
+impl KeyHasher for Seeded {
+    type Digest = u64;
+
+    fn digest<T>(t: &T) -> u64
+    where
+        T: std::hash::Hash + ?Sized,
+    {
+        let mut hasher = seahash::SeaHasher::with_seeds(1, 2, 3, 4);
+        t.hash(&mut hasher);
+        std::hash::Hasher::finish(&hasher)
+    }
+}
+
+#[test]
+fn migrate_hasher() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..1024u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    let order: Vec<_> = hamt.iter_by_digest().map(|(k, _)| *k).collect();
+
+    let migrated: Hamt<_, _, _, _, Seeded> = hamt.migrate_hasher();
+    let migrated_order: Vec<_> =
+        migrated.iter_by_digest().map(|(k, _)| *k).collect();
+    assert_ne!(order, migrated_order);
+
+    for i in 0..1024u32 {
+        assert_eq!(*migrated.get(&i.into()).expect("Some(_)").leaf(), i);
+    }
+
+    let hamt: Hamt<_, _, _, _> = migrated.migrate_hasher();
+    let restored_order: Vec<_> =
+        hamt.iter_by_digest().map(|(k, _)| *k).collect();
+    assert_eq!(order, restored_order);
+}
+
+#[test]
+fn fixed_hamt() {
+    let mut map = FixedHamt::<u32, u32, 1024>::new();
+
+    for i in 0..1024u32 {
+        assert_eq!(map.insert(i, i), Ok(None));
+    }
+    assert_eq!(map.len(), 1024);
+
+    for i in 0..1024u32 {
+        *map.get_mut(&i).expect("Some(_)") += 1;
+    }
+
+    for i in 0..1024u32 {
+        assert_eq!(map.get(&i), Some(&(i + 1)));
+        assert_eq!(map.remove(&i), Some(i + 1));
+        assert_eq!(map.get(&i), None);
+    }
+    assert!(map.is_empty());
+    assert_eq!(map.nodes(), 1);
+
+    let mut full = FixedHamt::<u32, u32, 2>::new();
+    let mut inserted = 0;
+    for i in 0..1024u32 {
+        match full.insert(i, i) {
+            Ok(_) => inserted += 1,
+            Err(pair) => assert_eq!(pair, (i, i)),
+        }
+    }
+    assert!((1..=7).contains(&inserted));
+    assert_eq!(full.len(), inserted);
+    assert_eq!(full.nodes(), 2);
+}
+
+static EMPTY: StaticHamt<u32, u32> = StaticHamt::new(&[[StaticSlot::Empty; 4]]);
+
+#[test]
+fn static_hamt() {
+    use std::fmt::Write;
+
+    assert_eq!(EMPTY.get(&0), None);
+
+    let mut builder = StaticHamtBuilder::new();
+    for i in 0..1024u32 {
+        assert_eq!(builder.insert(i, i * 2), None);
+    }
+
+    let mut source = String::new();
+    builder
+        .write(
+            &mut source,
+            |out, key| write!(out, "{}u32", key),
+            |out, val| write!(out, "{}u32", val),
+        )
+        .unwrap();
+    assert!(source.starts_with("&["));
+    assert!(source.contains("::dusk_hamt::StaticSlot::Leaf(7u32, 14u32)"));
+
+    let nodes = Box::leak(builder.into_nodes().into_boxed_slice());
+    let map = StaticHamt::new(nodes);
+
+    for i in 0..1024u32 {
+        assert_eq!(map.get(&i), Some(&(i * 2)));
+    }
+    assert_eq!(map.get(&1024), None);
+    assert_eq!(map.iter().count(), 1024);
+}
+
+#[test]
+fn small_hamt() {
+    let mut map = SmallHamt::<LittleEndian<u32>, u32, (), OffsetLen, 8>::new();
+
+    for i in 0..8u32 {
+        assert_eq!(map.insert(i.into(), i), None);
+    }
+    assert_eq!(map.insert(3.into(), 33), Some(3));
+    assert!(!map.is_trie());
+
+    assert_eq!(map.remove(&3.into()), Some(33));
+    assert_eq!(map.insert(3.into(), 3), None);
+    assert!(!map.is_trie());
+
+    for i in 8..256u32 {
+        assert_eq!(map.insert(i.into(), i), None);
+    }
+    assert!(map.is_trie());
+    assert_eq!(map.len(), 256);
+
+    for i in 0..256u32 {
+        assert_eq!(map.get(&i.into()).map(|v| *v), Some(i));
+    }
+
+    assert_eq!(map.remove(&100.into()), Some(100));
+    assert_eq!(map.get(&100.into()).map(|v| *v), None);
+    assert_eq!(map.len(), 255);
+
+    let hamt = map.into_hamt();
+    assert_eq!(hamt.iter_by_digest().count(), 255);
+}
+
+#[test]
+fn multi_get() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    let keys: Vec<LittleEndian<u32>> = [7u32, 600, 0, 511, 7, 1000, 256]
+        .iter()
+        .map(|i| (*i).into())
+        .collect();
+    let refs: Vec<_> = keys.iter().collect();
+
+    let found: Vec<_> = hamt
+        .multi_get(&refs)
+        .into_iter()
+        .map(|v| v.map(|v| *v))
+        .collect();
+    assert_eq!(
+        found,
+        [Some(7), None, Some(0), Some(511), Some(7), None, Some(256)]
+    );
+
+    assert!(hamt.multi_get(&[]).is_empty());
+}
+
+#[test]
+fn reused_lookups() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+    }
+
+    for i in 0..1024u32 {
+        let found = hamt.find(&i.into()).map(|v| *v);
+        assert_eq!(found, if i < 512 { Some(i) } else { None });
+    }
+
+    let mut scratch = LookupScratch::with_capacity(4);
+    let mut found = Vec::new();
+    for batch in [[7u32, 600, 0, 7], [511, 1000, 256, 3]].iter() {
+        let keys: Vec<LittleEndian<u32>> =
+            batch.iter().map(|i| (*i).into()).collect();
+        let refs: Vec<_> = keys.iter().collect();
+
+        hamt.multi_get_with(&mut scratch, &refs, &mut found);
+        let expected: Vec<_> = hamt.multi_get(&refs);
+        assert_eq!(found.len(), expected.len());
+        for (found, expected) in found.iter().zip(expected) {
+            assert_eq!(found.as_deref(), expected.as_deref());
+        }
+    }
+
+    hamt.multi_get_with(&mut scratch, &[], &mut found);
+    assert!(found.is_empty());
+}
+
+#[test]
+fn digest_many() {
+    let keys: Vec<u32> = (0..11).collect();
+    let refs: Vec<_> = keys.iter().collect();
+
+    let digests: Vec<_> = keys.iter().map(SeaHash::digest).collect();
+    assert_eq!(SeaHash::digest_many(&refs), digests);
+    assert!(SeaHash::digest_many::<u32>(&[]).is_empty());
+}
+
+#[test]
+fn hashed_key() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    let mut map = Map::new();
+    for i in 0..256u32 {
+        map.insert(i.into(), i);
+    }
+
+    for i in 0..300u32 {
+        let key = map.hash_key(&i.into());
+        assert_eq!(key.digest(), SeaHash::digest(key.key()));
+
+        // validate, then apply, hashing the key once
+        let found = map.get_hashed(&key).map(|v| *v.leaf());
+        assert_eq!(found, if i < 256 { Some(i) } else { None });
+
+        let old_root = map.commitment();
+        let proof = map.prove_update_hashed::<Sea>(&key);
+        let removed = map.remove_hashed(&key);
+        assert_eq!(removed, found);
+        let update = Update::<_, u32>::Remove(key.key());
+        assert_eq!(
+            verify_update(&old_root, &proof, update, &map.commitment()),
+            Ok(())
+        );
+
+        assert_eq!(map.insert_hashed(key.clone(), i + 1), None);
+        assert_eq!(*map.get_hashed(&key).expect("Some(_)").leaf(), i + 1);
+    }
+}
+
+#[test]
+fn raw_digests() {
+    let mut map = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        let key = LittleEndian::from(i);
+        let digest = SeaHash::digest(&key);
+        assert_eq!(map.raw_insert(digest, KvPair::new(key, i)), None);
+    }
+
+    for i in 0..512u32 {
+        let digest = SeaHash::digest(&LittleEndian::<u32>::from(i));
+        let found = map.raw_get(digest, |key| key.value() == i);
+        assert_eq!(found.map(|v| *v.leaf()), Some(i));
+        assert_eq!(*map.get(&i.into()).expect("Some(_)").leaf(), i);
+
+        assert!(map.raw_get(digest, |key| key.value() == i + 1).is_none());
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn raw_insert_wrong_digest() {
+    let mut map = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let digest = SeaHash::digest(&LittleEndian::<u32>::from(1));
+    map.raw_insert(digest, KvPair::new(2.into(), 2));
+}
+
+#[test]
+fn multi_remove() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut reference = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+        if i % 3 != 0 {
+            reference.insert(i.into(), i);
+        }
+    }
+
+    let keys: Vec<LittleEndian<u32>> = (0..512u32)
+        .filter(|i| i % 3 == 0)
+        .chain([0, 600])
+        .map(Into::into)
+        .collect();
+    let refs: Vec<_> = keys.iter().collect();
+
+    let removed = hamt.multi_remove(&refs);
+    for (i, val) in removed.iter().enumerate().take(171) {
+        assert_eq!(*val, Some(i as u32 * 3));
+    }
+    assert_eq!(removed[171..], [None, None]);
+
+    // the map is left in the shape of one built without the removed keys
+    assert_eq!(hamt.profile(), reference.profile());
+
+    let all: Vec<LittleEndian<u32>> = (0..512u32).map(Into::into).collect();
+    let refs: Vec<_> = all.iter().collect();
+    hamt.multi_remove(&refs);
+    assert_eq!(hamt.profile().len(), 0);
+}
+
+#[test]
+fn absorb() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut other = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut reference = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+        reference.insert(i.into(), i);
+    }
+    for i in 256..1024u32 {
+        other.insert(i.into(), i + 1);
+        reference.insert(i.into(), i + 1);
+    }
+
+    hamt.absorb(other);
+
+    for i in 0..1024u32 {
+        let expected = if i < 256 { i } else { i + 1 };
+        assert_eq!(hamt.get(&i.into()).map(|v| *v.leaf()), Some(expected));
+    }
+    assert_eq!(hamt.profile(), reference.profile());
+}
+
+#[test]
+fn split_off() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut odd = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut even = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+        if i % 2 == 0 {
+            even.insert(i.into(), i);
+        } else {
+            odd.insert(i.into(), i);
+        }
+    }
+
+    let split = hamt.split_off(|_, v| v % 2 == 0);
+    assert_eq!(split.profile(), even.profile());
+    assert_eq!(hamt.profile(), odd.profile());
+    for i in 0..512u32 {
+        let (with, without) = if i % 2 == 0 {
+            (&split, &hamt)
+        } else {
+            (&hamt, &split)
+        };
+        assert_eq!(with.get(&i.into()).map(|v| *v.leaf()), Some(i));
+        assert!(without.get(&i.into()).is_none());
+    }
+
+    let low = hamt.split_off_digests(..u64::MAX / 2);
+    assert_eq!(low.profile().len() + hamt.profile().len(), 256);
+    assert!(low
+        .iter_by_digest()
+        .all(|(key, _)| SeaHash::digest(key) < u64::MAX / 2));
+    assert!(hamt
+        .iter_by_digest()
+        .all(|(key, _)| SeaHash::digest(key) >= u64::MAX / 2));
+}
+
+#[test]
+fn partition() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut small = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+        if i < 100 {
+            small.insert(i.into(), i);
+        }
+    }
+
+    let mut calls = 0;
+    let (yes, no) = hamt.partition(|_, v| {
+        calls += 1;
+        *v < 100
+    });
+    assert_eq!(calls, 512);
+    assert_eq!(yes.profile(), small.profile());
+    assert_eq!(no.profile().len(), 412);
+    assert!(no.iter_by_digest().all(|(_, v)| *v >= 100));
+}
+
+#[test]
+fn extract_if() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+    let mut kept = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        hamt.insert(i.into(), i);
+        if i % 5 != 0 {
+            kept.insert(i.into(), i);
+        }
+    }
+
+    let mut extracted: Vec<u32> = hamt
+        .extract_if(|k, v| u32::from(*k) == *v && v % 5 == 0)
+        .map(|(_, v)| v)
+        .collect();
+    extracted.sort_unstable();
+    assert_eq!(extracted, (0..512).step_by(5).collect::<Vec<_>>());
+    assert_eq!(hamt.profile(), kept.profile());
+}
+
+#[test]
+fn increment() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u8, (), OffsetLen>::new();
+
+    for i in 0..256u32 {
+        for _ in 0..=i % 4 {
+            hamt.increment(&i.into(), 10).expect("no overflow");
+        }
+    }
+    for i in 0..256u32 {
+        let expected = 10 * (i % 4 + 1) as u8;
+        assert_eq!(hamt.get(&i.into()).map(|v| *v.leaf()), Some(expected));
+    }
+
+    assert_eq!(hamt.increment(&3.into(), 200), Ok(240));
+    assert_eq!(hamt.checked_add_assign(&3.into(), 16), Err(Overflow));
+    assert_eq!(hamt.get(&3.into()).map(|v| *v.leaf()), Some(240));
+    assert_eq!(hamt.checked_add_assign(&3.into(), 15), Ok(()));
+    assert_eq!(hamt.get(&3.into()).map(|v| *v.leaf()), Some(255));
+}
+
+#[test]
+fn update_checked() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u64, (), OffsetLen>::new();
+
+    for i in 0..64u32 {
+        hamt.insert(i.into(), 100);
+    }
+
+    let withdraw = |amount| {
+        move |balance: &u64| balance.checked_sub(amount).ok_or("insufficient")
+    };
+
+    assert_eq!(hamt.update_checked(&7.into(), withdraw(30)), Ok(Some(100)));
+    assert_eq!(
+        hamt.update_checked(&7.into(), withdraw(80)),
+        Err("insufficient")
+    );
+    assert_eq!(hamt.get(&7.into()).map(|v| *v.leaf()), Some(70));
+    assert_eq!(hamt.update_checked(&64.into(), withdraw(1)), Ok(None));
+    assert_eq!(hamt.get(&64.into()).map(|v| *v.leaf()), None);
+}
+
+#[test]
+fn changes() {
+    let mut old = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+
+    for i in 0..512u32 {
+        old.insert(i.into(), i);
+    }
+
+    let mut new = old.clone();
+    new.remove(&3.into());
+    new.insert(512.into(), 512);
+    new.insert(7.into(), 70);
+    new.insert(8.into(), 8);
+
+    assert!(old.changes(&old.clone()).is_empty());
+
+    let mut changes = old.changes(&new).into_changes();
+    changes.sort_by_key(|change| u32::from(*change.key()));
+    assert_eq!(
+        changes,
+        [
+            Change::Removed {
+                key: 3.into(),
+                val: 3
+            },
+            Change::Changed {
+                key: 7.into(),
+                old: 7,
+                new: 70
+            },
+            Change::Added {
+                key: 512.into(),
+                val: 512
+            },
+        ]
+    );
+
+    let report = new.changes(&old).to_string();
+    assert_eq!(report.lines().count(), 3);
+    assert!(report.contains("~ 7: 70 -> 7"));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn reference_model() {
+    use dusk_hamt::testing::{self, Model, Op, Ops};
+
+    for seed in 0..8 {
+        let mut map = Hamt::<LittleEndian<u32>, u32, (), OffsetLen>::new();
+        let mut model = Model::new();
+
+        let ops = Ops::new(seed, 256)
+            .take(2048)
+            .map(|op| op.map(|k| (k as u32).into(), |v| v as u32));
+        for (i, op) in ops.enumerate() {
+            assert_eq!(testing::apply(&mut map, &mut model, op), Ok(()));
+            if i % 256 == 0 {
+                assert_eq!(testing::check_invariants(&map), Ok(()));
+            }
+        }
+        assert_eq!(testing::check_model(&map, &model), Ok(()));
+        assert_eq!(testing::check_invariants(&map), Ok(()));
+
+        // a map changed behind the model diverges from it
+        let op = Op::Insert(LittleEndian::from(1000), 0);
+        model.apply(op);
+        assert!(testing::check_model(&map, &model).is_err());
+    }
+}
+
+#[cfg(feature = "audit")]
+#[test]
+fn logged_hamt() {
+    use dusk_hamt::{LoggedHamt, Mutation};
+
+    let mut map =
+        LoggedHamt::<LittleEndian<u32>, u32, (), OffsetLen, Vec<_>>::new(
+            Vec::new(),
+        );
+
+    assert_eq!(map.insert(1.into(), 10), Ok(None));
+    assert_eq!(map.insert(2.into(), 20), Ok(None));
+    assert_eq!(map.update(&1.into(), |v| v + 1), Ok(Some(10)));
+    assert_eq!(map.update(&3.into(), |v| v + 1), Ok(None));
+    assert_eq!(map.remove(&2.into()), Ok(Some(20)));
+
+    let (hamt, log) = map.into_parts();
+    assert_eq!(hamt.get(&1.into()).map(|v| *v.leaf()), Some(11));
+    assert_eq!(
+        log,
+        [
+            Mutation::Insert {
+                key: 1.into(),
+                val: 10
+            },
+            Mutation::Insert {
+                key: 2.into(),
+                val: 20
+            },
+            Mutation::Insert {
+                key: 1.into(),
+                val: 11
+            },
+            Mutation::Remove { key: 2.into() },
+        ]
+    );
+}
+
+/// Hashes keys to 32 byte digests, from four differently seeded hashes
+struct Wide;
+
+impl KeyHasher for Wide {
+    type Digest = [u8; 32];
+
+    fn digest<T>(t: &T) -> [u8; 32]
+    where
+        T: std::hash::Hash + ?Sized,
+    {
+        let mut digest = [0; 32];
+        for (i, chunk) in digest.chunks_mut(8).enumerate() {
+            let seed = i as u64;
+            let mut hasher = seahash::SeaHasher::with_seeds(seed, 1, 2, 3);
+            t.hash(&mut hasher);
+            let hash = std::hash::Hasher::finish(&hasher);
+            chunk.copy_from_slice(&hash.to_le_bytes());
+        }
+        digest
+    }
+}
+
+#[test]
+fn wide_digest() {
+    let mut hamt = Hamt::<LittleEndian<u32>, u32, (), OffsetLen, Wide>::new();
+
+    for i in 0..1024u32 {
+        assert_eq!(hamt.insert(i.into(), i), None);
+    }
+    for i in 0..1024u32 {
+        assert_eq!(hamt.get(&i.into()).map(|v| *v.leaf()), Some(i));
+    }
+    for i in 0..512u32 {
+        assert_eq!(hamt.remove(&i.into()), Some(i));
+    }
+    assert_eq!(hamt.iter_by_digest().count(), 512);
+}
+
+struct Accounts;
+
+impl DomainTag for Accounts {
+    const TAG: &'static [u8] = b"accounts";
+}
+
+struct Nullifiers;
+
+impl DomainTag for Nullifiers {
+    const TAG: &'static [u8] = b"nullifiers";
+}
+
+#[test]
+fn domain_tag() {
+    type Map<D> = Hamt<LittleEndian<u32>, u32, (), OffsetLen, Tagged<D>>;
+
+    let mut accounts = Map::<Accounts>::new();
+    let mut nullifiers = Map::<Nullifiers>::new();
+
+    for i in 0..256u32 {
+        accounts.insert(i.into(), i);
+        nullifiers.insert(i.into(), i);
+    }
+    for i in 0..256u32 {
+        assert_eq!(accounts.get(&i.into()).map(|v| *v.leaf()), Some(i));
+        assert_eq!(nullifiers.get(&i.into()).map(|v| *v.leaf()), Some(i));
+    }
+
+    let accounts: Vec<_> = accounts.iter_by_digest().map(|(k, _)| *k).collect();
+    let nullifiers: Vec<_> =
+        nullifiers.iter_by_digest().map(|(k, _)| *k).collect();
+    assert_ne!(accounts, nullifiers);
+}
+
+#[cfg(feature = "audit")]
+#[test]
+fn logged_tagged_hamt() {
+    use dusk_hamt::LoggedHamt;
+
+    type Map = Hamt<LittleEndian<u32>, u32, (), OffsetLen, Tagged<Accounts>>;
+
+    let mut map = LoggedHamt::from_parts(Map::new(), vec![]);
+    for i in 0..256u32 {
+        map.insert(i.into(), i).unwrap();
+    }
+    for i in 0..128u32 {
+        map.remove(&i.into()).unwrap();
+    }
+
+    let (map, log) = map.into_parts();
+    let replayed = Map::replay(log);
+
+    let entries: Vec<_> = map.iter_by_digest().map(|(k, v)| (*k, *v)).collect();
+    let replayed: Vec<_> =
+        replayed.iter_by_digest().map(|(k, v)| (*k, *v)).collect();
+    assert_eq!(entries.len(), 128);
+    assert_eq!(entries, replayed);
+}
+
+#[test]
+fn watched_changes() {
+    type Map = WatchedHamt<
+        LittleEndian<u32>,
+        u32,
+        (),
+        OffsetLen,
+        Vec<Change<LittleEndian<u32>, u32>>,
+    >;
+
+    let mut map = Map::new();
+    for i in 0..256u32 {
+        map.insert(i.into(), i);
+    }
+
+    let key = LittleEndian::from(7u32);
+    let path = map.as_map().get(&key).expect("Some(_)").path();
+    let by_key = map.subscribe(Watch::Key(key), Vec::new());
+    let by_path = map.subscribe(Watch::Path(path[..1].to_vec()), Vec::new());
+
+    map.insert(key, 8);
+    map.remove(&key);
+    map.remove(&key);
+    for i in 256..512u32 {
+        map.insert(i.into(), i);
+    }
+
+    let changes = map.unsubscribe(by_key).expect("subscribed");
+    assert_eq!(
+        changes,
+        [
+            Change::Changed {
+                key,
+                old: 7,
+                new: 8
+            },
+            Change::Removed { key, val: 8 },
+        ]
+    );
+    assert!(map.unsubscribe(by_key).is_none());
+
+    // a quarter of the new keys land under the first slot of the path
+    let changes = map.unsubscribe(by_path).expect("subscribed");
+    assert_eq!(
+        &changes[..2],
+        &[
+            Change::Changed {
+                key,
+                old: 7,
+                new: 8
+            },
+            Change::Removed { key, val: 8 }
+        ]
+    );
+    let added = changes[2..]
+        .iter()
+        .filter(|change| matches!(change, Change::Added { .. }))
+        .count();
+    assert_eq!(added, changes.len() - 2);
+    assert!(added > 0 && added < 256);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn watched_channels() {
+    use std::sync::mpsc::{channel, Sender};
+
+    type Map = WatchedHamt<
+        LittleEndian<u32>,
+        u32,
+        (),
+        OffsetLen,
+        Sender<Change<LittleEndian<u32>, u32>>,
+    >;
+
+    let mut map = Map::new();
+    let (sender, receiver) = channel();
+    let id = map.subscribe(Watch::Key(1.into()), sender);
+
+    map.insert(0.into(), 0);
+    map.insert(1.into(), 1);
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        [Change::Added {
+            key: 1.into(),
+            val: 1
+        }]
+    );
+
+    // dropping the receiver cancels the subscription on the next change
+    drop(receiver);
+    map.insert(1.into(), 2);
+    assert!(map.unsubscribe(id).is_none());
+}
+
+#[test]
+fn node_primitives() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    // two keys sharing their slot at the root, but not below it
+    let first = LittleEndian::from(0u32);
+    let second = (1..)
+        .map(LittleEndian::from)
+        .find(|key| {
+            Map::slot_of(key, 0) == Map::slot_of(&first, 0)
+                && Map::slot_of(key, 1) != Map::slot_of(&first, 1)
+        })
+        .expect("Some(_)");
+
+    let mut expected = Map::new();
+    expected.insert(first, 0);
+    expected.insert(second, 1);
+
+    let mut node = Map::new();
+    assert_eq!(node.insert_at(first, 0, 1), None);
+    assert_eq!(node.insert_at(second, 1, 1), None);
+    let mut root = Map::new();
+    root.buckets_mut()[Map::slot_of(&first, 0)] = node.into_bucket();
+    assert_eq!(root.commitment(), expected.commitment());
+    assert_eq!(*root.get(&second).expect("Some(_)").leaf(), 1);
+
+    // removing below the root leaves a single leaf, collapsed by the caller
+    let mut node = match root.into_buckets()[Map::slot_of(&first, 0)].clone() {
+        Bucket::Node(link) => link.unlink(),
+        _ => panic!("a node holds both keys"),
+    };
+    assert_eq!(node.remove_at(&second, 1), Some(1));
+    assert!(matches!(node.into_bucket(), Bucket::Leaf(_)));
+
+    // rebuilding every node from its buckets, with trusted annotations
+    let mut map = Map::new();
+    for i in 0..256u32 {
+        map.insert(i.into(), i);
+    }
+    let buckets = map.clone().into_buckets().map(|bucket| match bucket {
+        Bucket::Node(link) => {
+            let node = Map::from_buckets(link.unlink().into_buckets());
+            let annotation = node.recompute();
+            Bucket::node_with_annotation(node, annotation)
+        }
+        bucket => bucket,
+    });
+    let rebuilt = Map::from_buckets(buckets);
+    assert_eq!(rebuilt.commitment(), map.commitment());
+    assert!(matches!(rebuilt.buckets()[0], Bucket::Node(_)));
+}
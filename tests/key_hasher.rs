@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use core::hash::{Hash, Hasher};
+
+use dusk_hamt::{Hamt, KeyHasher, Lookup};
+use microkelvin::{HostStore, OffsetLen, StoreRef};
+use rkyv::rend::LittleEndian;
+
+/// 64 bit FNV-1a, mixed with the SplitMix64 finalizer, standing for a key
+/// hasher supplied by the user, which maps need without the `seahash`
+/// feature
+///
+/// The low bits of plain FNV-1a only depend on the low bits of the input,
+/// and slots are taken from the low bits of digests.
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+impl KeyHasher for Fnv {
+    type Digest = u64;
+
+    fn digest<T>(t: &T) -> u64
+    where
+        T: Hash + ?Sized,
+    {
+        let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+type Map = Hamt<LittleEndian<u32>, u32, (), OffsetLen, Fnv>;
+
+#[test]
+fn user_key_hasher() {
+    let mut map = Map::new();
+
+    for i in 0..1024u32 {
+        assert_eq!(map.insert(i.into(), i), None);
+    }
+    for i in 0..1024u32 {
+        assert_eq!(*map.get(&i.into()).expect("Some(_)").leaf(), i);
+    }
+    for i in (0..1024u32).step_by(2) {
+        assert_eq!(map.remove(&i.into()), Some(i));
+    }
+
+    let store = StoreRef::new(HostStore::new());
+    let stored = store.store(&map);
+    for i in 0..1024u32 {
+        let found = stored.get(&i.into()).map(|val| *val.leaf());
+        assert_eq!(found, if i % 2 == 0 { None } else { Some(i) });
+    }
+}
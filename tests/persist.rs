@@ -4,9 +4,20 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use dusk_hamt::{Hamt, Lookup};
-use microkelvin::{HostStore, StoreRef};
+#![cfg(feature = "seahash")]
+
+use dusk_hamt::{
+    CachedHamt, Commitment, CommitmentHasher, Conflict, CountingStore, First,
+    GraftError, Hamt, KvPair, Last, LimitError, Limits, Lookup, Migrate,
+    OverlayHamt, RootRegistry, SeaHash, Update, Upgradable, Version,
+    VersionedHamt,
+};
+use microkelvin::{
+    All, Cardinality, Compound, HostStore, Keyed, MaybeArchived, Nth,
+    OffsetLen, StoreRef, UnwrapInfallible,
+};
 use rkyv::rend::LittleEndian;
+use rkyv::Deserialize;
 
 #[test]
 fn persist_across_threads() {
@@ -41,3 +52,799 @@ fn persist_across_threads() {
         assert_eq!(hamt.remove(&le), Some(i + 1));
     }
 }
+
+#[test]
+fn join_with_stored() {
+    let store = StoreRef::new(HostStore::new());
+
+    let mut left = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+    let mut right = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+
+    for i in 0..512u64 {
+        left.insert(i.into(), i);
+        right.insert((i + 256).into(), i + 256);
+    }
+
+    let stored = store.store(&right);
+    let right: Hamt<_, _, _, _> = stored
+        .inner()
+        .deserialize(&mut store.clone())
+        .unwrap_infallible();
+
+    let mut both = 0;
+    let mut total = 0;
+
+    for (key, l, r) in left.join(&right) {
+        let key = u64::from(*key);
+        if let Some(l) = &l {
+            assert_eq!(**l, key);
+        }
+        if let Some(r) = &r {
+            assert_eq!(**r, key);
+        }
+        if l.is_some() && r.is_some() {
+            both += 1;
+        }
+        total += 1;
+    }
+
+    assert_eq!(both, 256);
+    assert_eq!(total, 768);
+}
+
+#[test]
+fn subtree_of_stored() {
+    let store = StoreRef::new(HostStore::new());
+
+    let mut hamt = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+
+    for i in 0..1024u64 {
+        hamt.insert(i.into(), i);
+    }
+
+    let stored = store.store(&hamt);
+    let hamt: Hamt<_, _, _, _> = stored
+        .inner()
+        .deserialize(&mut store.clone())
+        .unwrap_infallible();
+
+    let mut total = 0;
+
+    for a in 0..4 {
+        for b in 0..4 {
+            let shard = hamt.subtree(&[a, b]);
+            let stored_shard = store.store(&shard);
+
+            for (key, val) in shard.iter_by_digest() {
+                assert_eq!(u64::from(*key), *val);
+                assert_eq!(stored_shard.get(key).unwrap().leaf(), *val);
+                total += 1;
+            }
+        }
+    }
+
+    assert_eq!(total, 1024);
+    assert_eq!(hamt.subtree(&[]).iter_by_digest().count(), 1024);
+}
+
+#[test]
+fn owned_from_stored() {
+    let store = StoreRef::new(HostStore::new());
+
+    let mut hamt = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+
+    for i in 0..256u64 {
+        hamt.insert(i.into(), i + 1);
+    }
+
+    let stored = store.store(&hamt);
+
+    for i in 0..256u64 {
+        let value = stored.get(&i.into()).expect("Some(_)");
+        assert_eq!(u64::from(*value.key()), i);
+        assert_eq!(value.into_owned(), i + 1);
+    }
+}
+
+#[test]
+fn rebuild_stored() {
+    let store = StoreRef::new(HostStore::new());
+
+    let mut hamt = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+
+    for i in 0..1024u64 {
+        hamt.insert(i.into(), i);
+    }
+
+    let stored = store.store(&hamt);
+    let restored: Hamt<_, _, _, _> = stored
+        .inner()
+        .deserialize(&mut store.clone())
+        .unwrap_infallible();
+
+    let rebuilt = restored.rebuild();
+    assert_eq!(rebuilt.profile(), hamt.profile());
+
+    for i in 0..1024u64 {
+        assert_eq!(*rebuilt.get(&i.into()).expect("Some(_)").leaf(), i);
+    }
+}
+
+#[cfg(feature = "audit")]
+#[test]
+fn replay_from_checkpoint() {
+    use dusk_hamt::LoggedHamt;
+
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+    let mut roots = Vec::new();
+
+    let mut map = LoggedHamt::new(Vec::new());
+    for i in 0..512u64 {
+        map.insert(i.into(), i).unwrap();
+        if i % 2 == 0 {
+            map.remove(&(i / 2).into()).unwrap();
+        }
+        if i % 100 == 99 {
+            roots.push(store.store(map.as_map()));
+            map.checkpoint(roots.len() as u64 - 1).unwrap();
+        }
+    }
+
+    let (map, log) = map.into_parts();
+
+    let mut restored = None;
+    let replayed = Map::replay_from_checkpoint(&log, |id| {
+        restored = Some(id);
+        let map: Map = roots[id as usize]
+            .inner()
+            .deserialize(&mut store.clone())
+            .unwrap_infallible();
+        Some(map)
+    });
+    assert_eq!(restored, Some(4));
+    assert!(replayed.changes(&map).is_empty());
+
+    let replayed = Map::replay(log);
+    assert!(replayed.changes(&map).is_empty());
+}
+
+#[test]
+fn counting_store() {
+    let store = CountingStore::new(HostStore::new());
+    let telemetry = store.telemetry();
+    let store = StoreRef::new(store);
+
+    let mut hamt = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+    for i in 0..256u64 {
+        hamt.insert(i.into(), i);
+    }
+
+    let stored = store.store(&hamt);
+    let written = telemetry.stats();
+    assert!(written.writes > 0);
+    assert!(written.bytes_written > 0);
+    assert_eq!(written.reads, 0);
+
+    telemetry.reset();
+    for i in 0..256u64 {
+        assert_eq!(stored.get(&i.into()).unwrap().leaf(), i);
+    }
+    let read = telemetry.stats();
+    assert!(read.reads >= 256);
+    assert!(read.bytes_read > 0);
+    assert_eq!(read.writes, 0);
+}
+
+#[test]
+fn check_limits() {
+    let store = StoreRef::new(HostStore::new());
+
+    let mut hamt = Hamt::<LittleEndian<u64>, u64, (), OffsetLen>::new();
+    for i in 0..1024u64 {
+        hamt.insert(i.into(), i);
+    }
+    let profile = hamt.profile();
+    let nodes = profile.levels().iter().map(|level| level.nodes).sum();
+    let depth = profile.depth();
+
+    let stored = store.store(&hamt);
+
+    let limits = Limits {
+        max_depth: depth,
+        max_nodes: nodes,
+    };
+    assert_eq!(stored.check_limits(&limits), Ok(()));
+    assert_eq!(hamt.check_limits(&limits), Ok(()));
+
+    let shallow = Limits {
+        max_depth: depth - 1,
+        ..limits
+    };
+    assert_eq!(stored.check_limits(&shallow), Err(LimitError::Depth));
+
+    let small = Limits {
+        max_nodes: nodes - 1,
+        ..limits
+    };
+    assert_eq!(stored.check_limits(&small), Err(LimitError::Nodes));
+}
+
+#[test]
+fn nth_stored() {
+    let store = StoreRef::new(HostStore::new());
+
+    let mut hamt = Hamt::<LittleEndian<u64>, u64, Cardinality, _>::new();
+    for i in 0..512u64 {
+        hamt.insert(i.into(), i);
+    }
+
+    let stored = store.store(&hamt);
+
+    for i in 0..512u64 {
+        let memory = hamt.nth(i).expect("Some(_)");
+        let archived = stored.nth(i).expect("Some(_)");
+        assert_eq!(memory.key(), archived.key());
+        assert_eq!(
+            stored.walk(Nth(i)).expect("Some(_)").depth(),
+            archived.depth() + 1
+        );
+    }
+    assert!(stored.nth(512).is_none());
+}
+
+#[test]
+fn update_stored() {
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = CountingStore::new(HostStore::new());
+    let telemetry = store.telemetry();
+    let store = StoreRef::new(store);
+
+    let mut hamt = Map::new();
+    for i in 0..1024u64 {
+        hamt.insert(i.into(), i);
+    }
+    let root = store.store(&hamt);
+    let full = telemetry.stats().bytes_written;
+
+    telemetry.reset();
+    let (inserted, old) = Map::insert_stored(&root, 7.into(), 70);
+    assert_eq!(old, Some(7));
+    assert!(telemetry.stats().bytes_written * 10 < full);
+
+    let (removed, old) = Map::remove_stored(&inserted, &8.into());
+    assert_eq!(old, Some(8));
+
+    assert_eq!(root.get(&7.into()).unwrap().leaf(), 7);
+    assert_eq!(inserted.get(&7.into()).unwrap().leaf(), 70);
+    assert_eq!(inserted.get(&8.into()).unwrap().leaf(), 8);
+    assert_eq!(removed.get(&7.into()).unwrap().leaf(), 70);
+    assert!(removed.get(&8.into()).is_none());
+    for i in 9..1024u64 {
+        assert_eq!(removed.get(&i.into()).unwrap().leaf(), i);
+    }
+}
+
+#[test]
+fn root_registry() {
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut balances = Map::new();
+    let mut nonces = Map::new();
+    for i in 0..64u64 {
+        balances.insert(i.into(), i * 100);
+        nonces.insert(i.into(), i);
+    }
+
+    let mut registry = RootRegistry::new(store.clone());
+    registry.save_root("balances", &store.store(&balances));
+    registry.save_root("nonces", &store.store(&nonces));
+    let committed = registry.commit();
+
+    // uncommitted changes are not seen by the committed registry
+    registry.remove_root("nonces");
+    balances.insert(1.into(), 0);
+    registry.save_root("balances", &store.store(&balances));
+
+    let reopened = RootRegistry::open(store.clone(), &committed);
+    assert_eq!(reopened.names().collect::<Vec<_>>(), ["balances", "nonces"]);
+    let old = reopened.load_root::<Map>("balances").unwrap();
+    assert_eq!(old.get(&1.into()).unwrap().leaf(), 100);
+
+    let reopened = RootRegistry::open(store, &registry.commit());
+    assert_eq!(reopened.names().collect::<Vec<_>>(), ["balances"]);
+    let new = reopened.load_root::<Map>("balances").unwrap();
+    assert_eq!(new.get(&1.into()).unwrap().leaf(), 0);
+}
+
+#[derive(Default)]
+struct Sea(Vec<u8>);
+
+impl CommitmentHasher for Sea {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes)
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        let mut digest = [0; 32];
+        for (i, chunk) in digest.chunks_mut(8).enumerate() {
+            let seed = i as u64;
+            let mut hasher = seahash::SeaHasher::with_seeds(
+                seed,
+                seed + 1,
+                seed + 2,
+                seed + 3,
+            );
+            std::hash::Hasher::write(&mut hasher, &self.0);
+            let word = std::hash::Hasher::finish(&hasher);
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+}
+
+#[test]
+fn compare_and_apply() {
+    type Map = Hamt<LittleEndian<u32>, u32, Commitment<Sea>, OffsetLen>;
+
+    fn cas(
+        registry: &RootRegistry<OffsetLen>,
+        name: &str,
+        expected: &Commitment<Sea>,
+        updates: &[Update<LittleEndian<u32>, u32>],
+    ) -> Result<(), Conflict<Sea>> {
+        registry
+            .compare_and_apply::<_, _, Commitment<Sea>, SeaHash, _>(
+                name, expected, updates,
+            )
+            .map(drop)
+    }
+
+    let store = StoreRef::new(HostStore::new());
+    let mut map = Map::new();
+    for i in 0..64u32 {
+        map.insert(i.into(), i);
+    }
+
+    let mut first = RootRegistry::new(store.clone());
+    first.store_map("accounts", &map);
+    let second = first.clone();
+
+    // both writers read the same root
+    let read = map.commitment();
+    let (one, two, three) = (1.into(), 2.into(), 64.into());
+    let batch = [
+        Update::Insert(&one, &10),
+        Update::Remove(&two),
+        Update::Insert(&three, &64),
+    ];
+    let other = [Update::Insert(&one, &20)];
+
+    map.insert(one, 10);
+    map.remove(&two);
+    map.insert(three, 64);
+    let applied = map.commitment();
+
+    assert!(cas(&first, "accounts", &read, &batch).is_ok());
+
+    // the second writer prepared its batch against a replaced root
+    assert_eq!(
+        cas(&second, "accounts", &read, &other),
+        Err(Conflict {
+            expected: read,
+            found: applied
+        })
+    );
+
+    let current = second.load_root::<Map>("accounts").unwrap();
+    assert_eq!(current.get(&one).unwrap().leaf(), 10);
+    assert!(current.get(&two).is_none());
+    assert_eq!(current.get(&three).unwrap().leaf(), 64);
+
+    // once prepared again from the current root, the batch goes through
+    assert!(cas(&second, "accounts", &applied, &other).is_ok());
+    let current = first.load_root::<Map>("accounts").unwrap();
+    assert_eq!(current.get(&one).unwrap().leaf(), 20);
+
+    // a name with no root stands for an empty map
+    let empty = Map::new().commitment();
+    assert!(cas(&first, "fresh", &empty, &batch).is_ok());
+    assert!(first.names().any(|name| name == "fresh"));
+}
+
+#[test]
+fn persist_registry() {
+    type Balances = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+    type Names = Hamt<LittleEndian<u32>, [u8; 4], Cardinality, OffsetLen>;
+
+    let dir = std::env::temp_dir()
+        .join(format!("dusk-hamt-registry-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut balances = Balances::new();
+    let mut names = Names::new();
+    for i in 0..64u32 {
+        balances.insert(u64::from(i).into(), u64::from(i) * 100);
+        names.insert(i.into(), i.to_le_bytes());
+    }
+
+    let committed = {
+        let store = StoreRef::new(HostStore::with_file(&dir).unwrap());
+        let mut registry = RootRegistry::new(store);
+        registry.store_map("balances", &balances);
+        registry.store_map("names", &names);
+        registry.persist().unwrap()
+    };
+
+    let store = StoreRef::new(HostStore::with_file(&dir).unwrap());
+    let registry = RootRegistry::open(store, &committed);
+    let balances = registry.load_root::<Balances>("balances").unwrap();
+    assert_eq!(balances.get(&7.into()).unwrap().leaf(), 700);
+    let names = registry.load_root::<Names>("names").unwrap();
+    assert_eq!(names.get(&7.into()).unwrap().leaf(), [7, 0, 0, 0]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn graft_stored() {
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut base = Map::new();
+    let mut epoch = Map::new();
+    for i in 0..1024u64 {
+        base.insert(i.into(), i);
+        epoch.insert(i.into(), i + 1);
+    }
+    let epoch = store.store(&epoch);
+
+    let path = epoch.get(&5.into()).unwrap().path();
+    let prefix = &path[..2];
+
+    let mut view = base.clone();
+    assert_eq!(view.graft(prefix, &epoch), Ok(()));
+
+    for i in 0..1024u64 {
+        let value = view.get(&i.into()).unwrap();
+        let grafted = value.path().starts_with(prefix);
+        let expected = if grafted { i + 1 } else { i };
+        assert_eq!(*value.leaf(), expected);
+    }
+
+    assert_eq!(view.graft(&[4], &epoch), Err(GraftError::NoSourceNode));
+    let full_path = &path[..];
+    assert_eq!(view.graft(full_path, &epoch), Err(GraftError::NoSourceNode));
+
+    let mut empty = Map::new();
+    assert_eq!(empty.graft(prefix, &epoch), Err(GraftError::NoTargetPath));
+}
+
+#[derive(Debug, PartialEq)]
+struct Account {
+    balance: u64,
+    nonce: u32,
+}
+
+impl Migrate for Account {
+    const VERSION: u16 = 2;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.balance.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    fn migrate(version: u16, bytes: &[u8]) -> Option<Self> {
+        let mut balance = [0; 8];
+        let mut nonce = [0; 4];
+        match (version, bytes.len()) {
+            // the first layout had no nonce
+            (1, 8) => balance.copy_from_slice(bytes),
+            (2, 12) => {
+                balance.copy_from_slice(&bytes[..8]);
+                nonce.copy_from_slice(&bytes[8..]);
+            }
+            _ => return None,
+        }
+        Some(Account {
+            balance: u64::from_le_bytes(balance),
+            nonce: u32::from_le_bytes(nonce),
+        })
+    }
+}
+
+#[test]
+fn inspect_nodes() {
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut map = Map::new();
+    for i in 0..1024u64 {
+        map.insert(i.into(), i);
+    }
+    assert!(map.nodes().all(|node| node.ident.is_none()));
+
+    let stored = store.store(&map);
+    let root: Map = stored
+        .inner()
+        .deserialize(&mut store.clone())
+        .unwrap_infallible();
+
+    let nodes: Vec<_> = root.nodes().collect();
+    let profile = root.profile();
+
+    assert_eq!(nodes[0].depth, 0);
+    assert!(nodes[0].ident.is_none());
+    assert!(nodes[1..].iter().all(|node| node.ident.is_some()));
+    assert!(nodes.iter().all(|node| node.path.len() == node.depth));
+
+    let total: usize = profile.levels().iter().map(|l| l.nodes).sum();
+    assert_eq!(nodes.len(), total);
+    assert_eq!(nodes.iter().map(|n| n.leaves).sum::<usize>(), 1024);
+    assert_eq!(nodes.iter().map(|n| n.depth).max(), Some(profile.depth()));
+}
+
+#[test]
+fn upgrade_on_read() {
+    type Map = Hamt<LittleEndian<u32>, Upgradable<Account, 16>, (), OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut map = Map::new();
+    for i in 0..256u32 {
+        let old = Upgradable::from_parts(1, &(i as u64).to_le_bytes());
+        map.insert(i.into(), old);
+    }
+    let stored = store.store(&map);
+
+    let mut upgraded: Map =
+        stored.inner().deserialize(&mut store.clone()).unwrap();
+    let account = Account {
+        balance: 1000,
+        nonce: 1,
+    };
+    upgraded.insert(1000.into(), Upgradable::new(&account));
+
+    for i in 0..256u32 {
+        let expected = Account {
+            balance: i as u64,
+            nonce: 0,
+        };
+        let value = stored.get(&i.into()).unwrap();
+        assert_eq!(value.decode(), Some(expected));
+        assert_eq!(
+            upgraded.get(&i.into()).unwrap().decode().unwrap().balance,
+            i as u64
+        );
+    }
+    assert_eq!(upgraded.get(&1000.into()).unwrap().decode(), Some(account));
+
+    let corrupt = Upgradable::<Account, 16>::from_parts(1, &[0; 3]);
+    assert_eq!(corrupt.decode(), None);
+    let future = Upgradable::<Account, 16>::from_parts(3, &[0; 12]);
+    assert_eq!(future.decode(), None);
+}
+
+#[test]
+fn overlay_over_stored() {
+    type Map = Hamt<LittleEndian<u64>, u64, Cardinality, OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut map = Map::new();
+    for i in 0..1024u64 {
+        map.insert(i.into(), i);
+    }
+    let root = store.store(&map);
+
+    let mut block = OverlayHamt::new(root.clone());
+    assert!(!block.is_dirty());
+    assert_eq!(block.insert(7.into(), 70), Some(7));
+    assert_eq!(block.insert(2048.into(), 2048), None);
+    assert_eq!(block.remove(&8.into()), Some(8));
+    assert_eq!(block.remove(&8.into()), None);
+    assert!(block.is_dirty());
+
+    assert_eq!(block.get(&7.into()), Some(70));
+    assert_eq!(block.get(&8.into()), None);
+    assert_eq!(block.get(&9.into()), Some(9));
+    assert_eq!(block.get(&2048.into()), Some(2048));
+
+    // discarding leaves the stored map as it was
+    block.discard();
+    assert_eq!(block.get(&7.into()), Some(7));
+    assert_eq!(
+        block.flush().ident().erase().offset(),
+        root.ident().erase().offset()
+    );
+
+    block.insert(7.into(), 70);
+    block.remove(&8.into());
+    let flushed = block.flush();
+    assert!(!block.is_dirty());
+    assert_eq!(
+        block.root().ident().erase().offset(),
+        flushed.ident().erase().offset()
+    );
+
+    assert_eq!(root.get(&7.into()).unwrap().leaf(), 7);
+    assert_eq!(flushed.get(&7.into()).unwrap().leaf(), 70);
+    assert!(flushed.get(&8.into()).is_none());
+    assert_eq!(flushed.get(&9.into()).unwrap().leaf(), 9);
+    assert_eq!(block.get(&7.into()), Some(70));
+}
+
+#[test]
+fn cached_reads() {
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = CountingStore::new(HostStore::new());
+    let telemetry = store.telemetry();
+    let store = StoreRef::new(store);
+
+    let mut map = Map::new();
+    for i in 0..1024u64 {
+        map.insert(i.into(), i);
+    }
+    let root = store.store(&map);
+    let map: Map = root
+        .inner()
+        .deserialize(&mut store.clone())
+        .unwrap_infallible();
+
+    let mut cached = CachedHamt::<_, _, _, _, SeaHash>::new(map, 2);
+    assert_eq!(cached.get(&7.into()), Some(7));
+    assert_eq!(cached.get(&8.into()), Some(8));
+    assert_eq!(cached.get(&2048.into()), None);
+
+    // hot keys are read without touching the store
+    telemetry.reset();
+    for _ in 0..16 {
+        assert_eq!(cached.get(&7.into()), Some(7));
+        assert_eq!(cached.get(&8.into()), Some(8));
+    }
+    assert_eq!(telemetry.stats().reads, 0);
+
+    // mutations only drop the copy of the key they change
+    assert_eq!(cached.insert(7.into(), 70), Some(7));
+    assert!(!cached.is_cached(&7.into()));
+    assert!(cached.is_cached(&8.into()));
+    assert_eq!(cached.get(&7.into()), Some(70));
+    assert_eq!(cached.remove(&8.into()), Some(8));
+    assert_eq!(cached.get(&8.into()), None);
+
+    // the least recently read key is evicted
+    cached.get(&9.into());
+    cached.get(&10.into());
+    assert!(!cached.is_cached(&7.into()));
+    assert!(cached.is_cached(&9.into()));
+}
+
+#[test]
+fn owned_stored_values() {
+    type Map = Hamt<LittleEndian<u64>, u64, (), OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut map = Map::new();
+    for i in 0..256u64 {
+        map.insert(i.into(), i * 3);
+    }
+    let root = store.store(&map);
+
+    for i in 0..256u64 {
+        let value = root.get(&i.into()).expect("Some(_)");
+        assert!(matches!(value.leaf(), MaybeArchived::Archived(_)));
+        assert_eq!(value.to_owned(), i * 3);
+        assert_eq!(value.deserialize_with(&mut store.clone()), Ok(i * 3));
+    }
+
+    let value = map.get(&7.into()).expect("Some(_)");
+    assert_eq!(value.deserialize_with(&mut store.clone()), Ok(21));
+}
+
+#[test]
+fn standard_walkers() {
+    type Map = Hamt<LittleEndian<u64>, u64, Cardinality, OffsetLen>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut map = Map::new();
+    assert!(map.walk(First).is_none());
+    assert!(map.walk(Last).is_none());
+
+    for i in 0..512u64 {
+        map.insert(i.into(), i);
+    }
+    let stored = store.store(&map);
+
+    let order: Vec<u64> = map
+        .iter_by_digest()
+        .map(|(key, _)| u64::from(*key))
+        .collect();
+    let key = |leaf: MaybeArchived<KvPair<LittleEndian<u64>, u64>>| match leaf {
+        MaybeArchived::Memory(kv) => u64::from(*kv.key()),
+        MaybeArchived::Archived(kv) => u64::from(*kv.key()),
+    };
+
+    let first = map.walk(First).expect("Some(_)");
+    assert_eq!(key(first.leaf()), order[0]);
+    let first = stored.walk(First).expect("Some(_)");
+    assert_eq!(key(first.leaf()), order[0]);
+
+    let last = map.walk(Last).expect("Some(_)");
+    assert_eq!(key(last.leaf()), order[511]);
+    let last = stored.walk(Last).expect("Some(_)");
+    assert_eq!(key(last.leaf()), order[511]);
+
+    let all = stored.walk(All).expect("Some(_)");
+    assert_eq!(key(all.leaf()), order[0]);
+
+    for n in [0, 7, 511].iter() {
+        let nth = stored.walk(Nth(*n)).expect("Some(_)");
+        let in_memory = map.walk(Nth(*n)).expect("Some(_)");
+        assert_eq!(key(nth.leaf()), key(in_memory.leaf()));
+    }
+    assert!(stored.walk(Nth(512)).is_none());
+}
+
+#[test]
+fn versioned_round_trip() {
+    type Balances = VersionedHamt<LittleEndian<u32>, u64, OffsetLen, 3>;
+
+    let store = StoreRef::new(HostStore::new());
+
+    let mut balances = Balances::new();
+    for seq in 0..5u64 {
+        for i in 0..256u32 {
+            balances.insert(i.into(), u64::from(i) * 10 + seq, seq);
+        }
+    }
+    let expected = |i: u32| {
+        (2..5u64)
+            .map(|seq| Version::new(seq, u64::from(i) * 10 + seq))
+            .collect::<Vec<_>>()
+    };
+
+    let stored = store.store(balances.as_map());
+    for i in 0..256u32 {
+        let found = stored.get(&i.into()).expect("Some(_)");
+        match found.leaf() {
+            MaybeArchived::Archived(versions) => {
+                let seqs: Vec<u64> = versions.iter().map(|v| v.seq()).collect();
+                assert_eq!(seqs, [2, 3, 4]);
+            }
+            MaybeArchived::Memory(_) => unreachable!(),
+        }
+    }
+
+    let mut loaded = Balances::from_map(
+        stored
+            .inner()
+            .deserialize(&mut store.clone())
+            .unwrap_infallible(),
+    );
+    for i in 0..256u32 {
+        assert_eq!(loaded.get(&i.into()), Some(u64::from(i) * 10 + 4));
+        assert_eq!(loaded.get_versions(&i.into()), Some(expected(i)));
+    }
+
+    assert_eq!(loaded.insert(7.into(), 1, 5), Some(74));
+    assert_eq!(
+        loaded.get_versions(&7.into()),
+        Some(vec![
+            Version::new(3, 73),
+            Version::new(4, 74),
+            Version::new(5, 1)
+        ])
+    );
+    assert_eq!(loaded.remove(&8.into()), Some(84));
+    assert_eq!(loaded.get_versions(&8.into()), None);
+}